@@ -0,0 +1,262 @@
+use {
+    crate::{download_to_temp, error::LabError},
+    log::*,
+    sha2::{Digest, Sha256},
+    std::{
+        fs::{self, File},
+        io,
+        path::{Path, PathBuf},
+    },
+};
+
+pub const DEFAULT_ARTIFACT_CACHE_DIR: &str = "artifact-cache";
+
+/// Content-addressed store for downloaded artifacts: release tarballs, genesis programs, and feature sets.
+/// Blobs live at `<root>/sha256/<digest>` and `<root>/index/<key>` holds the digest for a key,
+/// e.g. `release/v1.18.20` or `program/spl_token-3.5.0`. A blob is verified against its digest on every read.
+/// In offline mode a missing artifact is an error instead of a download.
+/// Moving release channels such as `stable` are downloaded again on every use, see `fetch_moving`
+#[derive(Clone, Debug)]
+pub struct ArtifactCache {
+    root: PathBuf,
+    offline: bool,
+}
+
+fn io_error(context: String) -> impl FnOnce(io::Error) -> LabError {
+    move |err| LabError::download(context).with_source(err)
+}
+
+pub fn release_key(release_channel: &str) -> String {
+    format!("release/{release_channel}")
+}
+
+/// True unless `release_channel` is an exact version like `v1.18.20`.
+/// Channels such as `stable`, `beta`, `edge`, or `v1.18` point at a new release every so often
+pub fn is_moving_release(release_channel: &str) -> bool {
+    let version = release_channel.strip_prefix('v').unwrap_or(release_channel);
+    let parts: Vec<&str> = version.split('.').collect();
+    parts.len() != 3
+        || parts
+            .iter()
+            .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+}
+
+pub fn program_key(program_name: &str) -> String {
+    format!("program/{program_name}")
+}
+
+pub fn feature_set_key(version: &str) -> String {
+    format!("feature-set/{version}")
+}
+
+/// SHA-256 of the file at `path` as lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String, LabError> {
+    let mut file =
+        File::open(path).map_err(io_error(format!("Failed to open {}", path.display())))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .map_err(io_error(format!("Failed to read {}", path.display())))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl ArtifactCache {
+    pub fn new(root: PathBuf, offline: bool) -> Result<Self, LabError> {
+        for dir in [root.join("sha256"), root.join("index")] {
+            fs::create_dir_all(&dir)
+                .map_err(io_error(format!("Failed to create {}", dir.display())))?;
+        }
+        Ok(Self { root, offline })
+    }
+
+    fn index_path(&self, key: &str) -> PathBuf {
+        self.root.join("index").join(key)
+    }
+
+    fn blob_path(&self, digest: &str) -> PathBuf {
+        self.root.join("sha256").join(digest)
+    }
+
+    /// The cached artifact for `key`, if any. A blob that no longer matches its digest is evicted
+    pub fn get(&self, key: &str) -> Result<Option<PathBuf>, LabError> {
+        let index_path = self.index_path(key);
+        let Ok(digest) = fs::read_to_string(&index_path) else {
+            return Ok(None);
+        };
+        let blob = self.blob_path(digest.trim());
+        if !blob.exists() {
+            return Ok(None);
+        }
+        let actual = sha256_file(&blob)?;
+        if actual != digest.trim() {
+            warn!(
+                "Cached {key} is corrupt: expected sha256 {} but found {actual}. Evicting it",
+                digest.trim()
+            );
+            let _ = fs::remove_file(&blob);
+            let _ = fs::remove_file(&index_path);
+            return Ok(None);
+        }
+        debug!("Cache hit for {key}: {}", blob.display());
+        Ok(Some(blob))
+    }
+
+    /// Stores the file at `path` as the artifact for `key`, checking it against `expected_sha256` if given.
+    /// Returns the cached blob
+    pub fn insert(
+        &self,
+        key: &str,
+        path: &Path,
+        expected_sha256: Option<&str>,
+    ) -> Result<PathBuf, LabError> {
+        let digest = sha256_file(path)?;
+        if let Some(expected) = expected_sha256 {
+            if !expected.eq_ignore_ascii_case(&digest) {
+                return Err(LabError::download(format!(
+                    "Checksum mismatch for {key} from {}: expected sha256 {expected} but found {digest}",
+                    path.display()
+                )));
+            }
+        }
+
+        let blob = self.blob_path(&digest);
+        if !blob.exists() {
+            // copy to a temporary name first so a partial copy is never taken for the blob
+            let partial = blob.with_extension("partial");
+            fs::copy(path, &partial)
+                .and_then(|_| fs::rename(&partial, &blob))
+                .map_err(io_error(format!(
+                    "Failed to cache {} as {}",
+                    path.display(),
+                    blob.display()
+                )))?;
+        }
+
+        let index_path = self.index_path(key);
+        if let Some(parent) = index_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(io_error(format!("Failed to create {}", parent.display())))?;
+        }
+        fs::write(&index_path, &digest).map_err(io_error(format!(
+            "Failed to write {}",
+            index_path.display()
+        )))?;
+        info!("Cached {key} (sha256 {digest})");
+        Ok(blob)
+    }
+
+    /// Downloads `url` into the cache as `key`, replacing any previous artifact for `key`
+    pub async fn download(
+        &self,
+        key: &str,
+        url: &str,
+        expected_sha256: Option<&str>,
+    ) -> Result<PathBuf, LabError> {
+        if self.offline {
+            return Err(LabError::download(format!(
+                "{key} is not in the artifact cache {} and downloads are disabled by --offline. \
+                Add it with the cache-artifact subcommand",
+                self.root.display()
+            )));
+        }
+        let download_path = self.root.join(format!("download-{}", std::process::id()));
+        let result = match download_to_temp(url, &download_path).await {
+            Ok(()) => self.insert(key, &download_path, expected_sha256),
+            Err(err) => Err(err),
+        };
+        let _ = fs::remove_file(&download_path);
+        result
+    }
+
+    /// The cached artifact for `key`, downloading it from `url` on a miss
+    pub async fn fetch(
+        &self,
+        key: &str,
+        url: &str,
+        expected_sha256: Option<&str>,
+    ) -> Result<PathBuf, LabError> {
+        if let Some(blob) = self.get(key)? {
+            let digest = blob
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            match expected_sha256 {
+                Some(expected) if !expected.eq_ignore_ascii_case(digest) => {
+                    info!("Cached {key} does not match sha256 {expected}. Fetching it again");
+                }
+                _ => return Ok(blob),
+            }
+        }
+        self.download(key, url, expected_sha256).await
+    }
+
+    /// Like `fetch` for an artifact that changes upstream under the same key, e.g. the `stable` release:
+    /// it is downloaded again, replacing the cached one, so it is never pinned to its first download.
+    /// In offline mode the last download is used
+    pub async fn fetch_moving(
+        &self,
+        key: &str,
+        url: &str,
+        expected_sha256: Option<&str>,
+    ) -> Result<PathBuf, LabError> {
+        if self.offline {
+            warn!("Using the last download of {key}, which may be out of date since it moves");
+            return self.fetch(key, url, expected_sha256).await;
+        }
+        self.download(key, url, expected_sha256).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tempfile::TempDir};
+
+    #[tokio::test]
+    async fn test_artifact_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let cache = ArtifactCache::new(root.join("cache"), true).unwrap();
+        let artifact = root.join("program.so");
+        fs::write(&artifact, b"program bytes").unwrap();
+        let digest = sha256_file(&artifact).unwrap();
+
+        assert!(cache.get("program/p").unwrap().is_none());
+        assert!(cache.insert("program/p", &artifact, Some("00")).is_err());
+        let blob = cache.insert("program/p", &artifact, Some(&digest)).unwrap();
+        assert_eq!(cache.get("program/p").unwrap(), Some(blob.clone()));
+        // offline, so a hit is served and a mismatch fails fast instead of downloading
+        assert_eq!(
+            cache
+                .fetch("program/p", "https://example.com/p.so", None)
+                .await
+                .unwrap(),
+            blob
+        );
+        assert!(cache
+            .fetch("program/p", "https://example.com/p.so", Some("00"))
+            .await
+            .is_err());
+
+        // offline, a moving artifact falls back to its last download
+        assert_eq!(
+            cache
+                .fetch_moving("program/p", "https://example.com/p.so", None)
+                .await
+                .unwrap(),
+            blob
+        );
+
+        // a corrupted blob is evicted
+        fs::write(&blob, b"tampered").unwrap();
+        assert!(cache.get("program/p").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_is_moving_release() {
+        for channel in ["stable", "beta", "edge", "v1.18", "v2", "v1.18.x", "v1..20"] {
+            assert!(is_moving_release(channel), "{channel}");
+        }
+        for channel in ["v1.18.20", "1.18.20", "v2.0.0"] {
+            assert!(!is_moving_release(channel), "{channel}");
+        }
+    }
+}
@@ -1,22 +1,58 @@
 use {
+    crate::{deserialize_transparent_args, error::LabError},
+    serde::{Deserialize, Deserializer},
     solana_sdk::pubkey::Pubkey,
-    std::{error::Error, path::PathBuf},
+    std::{path::PathBuf, str::FromStr},
     strum_macros::Display,
 };
 
-#[derive(Clone, PartialEq, Debug)]
+fn deserialize_optional_pubkey<'de, D>(deserializer: D) -> Result<Option<Pubkey>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|pubkey| Pubkey::from_str(&pubkey).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+fn default_num_clients() -> usize {
+    1
+}
+
+fn default_client_duration_seconds() -> u64 {
+    7500
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
 pub struct BenchTpsConfig {
     pub num_clients: usize,
     pub client_duration_seconds: u64,
     pub client_type: String,
+    #[serde(deserialize_with = "deserialize_transparent_args")]
     pub bench_tps_args: Vec<String>,
     pub client_wait_for_n_nodes: Option<usize>,
     pub client_to_run: String,
+    #[serde(deserialize_with = "deserialize_optional_pubkey")]
     pub client_target_node: Option<Pubkey>,
 }
 
+impl Default for BenchTpsConfig {
+    fn default() -> Self {
+        Self {
+            num_clients: default_num_clients(),
+            client_duration_seconds: default_client_duration_seconds(),
+            client_type: "tpu-client".to_string(),
+            bench_tps_args: vec![],
+            client_wait_for_n_nodes: None,
+            client_to_run: "bench-tps".to_string(),
+            client_target_node: None,
+        }
+    }
+}
+
 impl ClientTrait for BenchTpsConfig {
-    fn executable_path(&self) -> Result<Vec<String>, Box<dyn Error>> {
+    fn executable_path(&self) -> Result<Vec<String>, LabError> {
         let command = vec!["/home/solana/k8s-cluster-scripts/client-startup-script.sh".to_string()];
         Ok(command)
     }
@@ -48,29 +84,29 @@ impl ClientTrait for BenchTpsConfig {
     }
 }
 
-#[derive(Default, Clone, PartialEq, Debug)]
+#[derive(Default, Clone, PartialEq, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub struct GenericClientConfig {
+    #[serde(default = "default_num_clients")]
     pub num_clients: usize,
+    #[serde(default = "default_client_duration_seconds")]
     pub client_duration_seconds: u64,
+    #[serde(default, deserialize_with = "deserialize_transparent_args")]
     pub args: Vec<String>,
     pub image: String,
     pub executable_path: PathBuf,
+    #[serde(default)]
     pub delay_start: u64,
 }
 
 impl ClientTrait for GenericClientConfig {
-    fn executable_path(&self) -> Result<Vec<String>, Box<dyn Error>> {
+    fn executable_path(&self) -> Result<Vec<String>, LabError> {
         let exec_path_string = self
             .executable_path
             .clone()
             .into_os_string()
             .into_string()
-            .map_err(|err| {
-                std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Invalid Unicode data in path: {:?}", err),
-                )
-            })?;
+            .map_err(|err| LabError::config(format!("Invalid Unicode data in path: {:?}", err)))?;
         Ok(vec![exec_path_string])
     }
     fn generate_client_command_flags(&self) -> Vec<String> {
@@ -78,12 +114,14 @@ impl ClientTrait for GenericClientConfig {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Display)]
+#[derive(Debug, Clone, PartialEq, Display, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ClientConfig {
     #[strum(serialize = "bench-tps")]
     BenchTps(BenchTpsConfig),
     #[strum(serialize = "generic")]
     Generic(GenericClientConfig),
+    #[serde(skip)]
     None,
 }
 
@@ -96,20 +134,20 @@ impl ClientConfig {
         }
     }
 
-    pub fn build_command(&self) -> Result<Vec<String>, Box<dyn Error>> {
+    pub fn build_command(&self) -> Result<Vec<String>, LabError> {
         match self {
             ClientConfig::BenchTps(config) => config.build_command(),
             ClientConfig::Generic(config) => config.build_command(),
-            ClientConfig::None => Err("Client config is None".into()),
+            ClientConfig::None => Err(LabError::config("Client config is None")),
         }
     }
 }
 
 pub trait ClientTrait {
-    fn executable_path(&self) -> Result<Vec<String>, Box<dyn Error>>;
+    fn executable_path(&self) -> Result<Vec<String>, LabError>;
     fn generate_client_command_flags(&self) -> Vec<String>;
     /// Build command to run on pod deployment
-    fn build_command(&self) -> Result<Vec<String>, Box<dyn Error>> {
+    fn build_command(&self) -> Result<Vec<String>, LabError> {
         let mut command = self.executable_path()?;
         command.extend(self.generate_client_command_flags());
         Ok(command)
@@ -1,6 +1,6 @@
 use {
-    crate::{node::Node, NodeType},
-    std::{error::Error, result::Result},
+    crate::{error::LabError, node::Node, NodeType},
+    std::result::Result,
 };
 
 // Holds all validators.
@@ -27,31 +27,31 @@ impl ClusterImages {
         }
     }
 
-    pub fn bootstrap(&mut self) -> Result<&mut Node, Box<dyn Error>> {
+    pub fn bootstrap(&mut self) -> Result<&mut Node, LabError> {
         self.bootstrap
             .as_mut()
-            .ok_or_else(|| "Bootstrap validator is not available".into())
+            .ok_or_else(|| LabError::config("Bootstrap validator is not available"))
     }
 
-    pub fn validator(&mut self) -> Result<&mut Node, Box<dyn Error>> {
+    pub fn validator(&mut self) -> Result<&mut Node, LabError> {
         self.validator
             .as_mut()
-            .ok_or_else(|| "Validator is not available".into())
+            .ok_or_else(|| LabError::config("Validator is not available"))
     }
 
-    pub fn rpc(&mut self) -> Result<&mut Node, Box<dyn Error>> {
+    pub fn rpc(&mut self) -> Result<&mut Node, LabError> {
         self.rpc
             .as_mut()
-            .ok_or_else(|| "Validator is not available".into())
+            .ok_or_else(|| LabError::config("RPC node is not available"))
     }
 
-    pub fn client(&mut self, client_index: usize) -> Result<&mut Node, Box<dyn Error>> {
+    pub fn client(&mut self, client_index: usize) -> Result<&mut Node, LabError> {
         if self.clients.is_empty() {
-            return Err("No Clients available".to_string().into());
+            return Err(LabError::config("No Clients available"));
         }
         self.clients
             .get_mut(client_index)
-            .ok_or_else(|| "Client index out of bounds".to_string().into())
+            .ok_or_else(|| LabError::config(format!("Client index {client_index} out of bounds")))
     }
 
     pub fn get_validators(&self) -> impl Iterator<Item = &Node> {
@@ -0,0 +1,128 @@
+use {
+    crate::error::LabError,
+    reqwest::Client,
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+    serde_json::{json, Value},
+    std::time::Duration,
+};
+
+pub const RPC_PORT: i32 = 8899;
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcResponseError>,
+}
+
+#[derive(Deserialize)]
+struct RpcResponseError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpochInfo {
+    pub epoch: u64,
+    pub slot_index: u64,
+    pub slots_in_epoch: u64,
+    pub absolute_slot: u64,
+}
+
+/// A node as seen in gossip
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterNode {
+    pub pubkey: String,
+    pub gossip: Option<String>,
+    pub rpc: Option<String>,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoteAccount {
+    pub vote_pubkey: String,
+    pub node_pubkey: String,
+    pub activated_stake: u64,
+    pub last_vote: u64,
+    pub root_slot: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct VoteAccounts {
+    pub current: Vec<VoteAccount>,
+    pub delinquent: Vec<VoteAccount>,
+}
+
+/// Minimal JSON-RPC client for the cluster's RPC endpoint
+pub struct ClusterRpc {
+    url: String,
+    client: Client,
+}
+
+impl ClusterRpc {
+    pub fn new(url: String) -> Result<Self, LabError> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|err| LabError::rpc("Failed to create RPC client").with_source(err))?;
+        Ok(Self { url, client })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, LabError> {
+        let request_failed = |err: reqwest::Error| {
+            LabError::rpc(format!("RPC request {method} to {} failed", self.url)).with_source(err)
+        };
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}))
+            .send()
+            .await
+            .map_err(request_failed)?
+            .error_for_status()
+            .map_err(request_failed)?
+            .json::<RpcResponse<T>>()
+            .await
+            .map_err(request_failed)?;
+
+        match (response.result, response.error) {
+            (Some(result), _) => Ok(result),
+            (None, Some(err)) => Err(LabError::rpc(format!(
+                "RPC request {method} to {} returned error {}: {}",
+                self.url, err.code, err.message
+            ))),
+            (None, None) => Err(LabError::rpc(format!(
+                "RPC request {method} to {} returned no result",
+                self.url
+            ))),
+        }
+    }
+
+    /// Slot at the given commitment, e.g. "processed" or "finalized"
+    pub async fn get_slot(&self, commitment: &str) -> Result<u64, LabError> {
+        self.request("getSlot", json!([{ "commitment": commitment }]))
+            .await
+    }
+
+    pub async fn get_epoch_info(&self) -> Result<EpochInfo, LabError> {
+        self.request("getEpochInfo", json!([])).await
+    }
+
+    pub async fn get_cluster_nodes(&self) -> Result<Vec<ClusterNode>, LabError> {
+        self.request("getClusterNodes", json!([])).await
+    }
+
+    pub async fn get_vote_accounts(&self) -> Result<VoteAccounts, LabError> {
+        self.request("getVoteAccounts", json!([])).await
+    }
+}
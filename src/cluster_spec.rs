@@ -0,0 +1,265 @@
+use {
+    crate::{
+        client_config::ClientConfig,
+        error::LabError,
+        genesis::GenesisFlags,
+        image_builder::{ImageBuilderKind, ImageDistribution},
+        kubernetes::PodRequests,
+        node_placement::NodePlacement,
+        release::{BuildOptions, BuildType, DeployMethod},
+        stake_distribution::StakeDistribution,
+        validator_config::ValidatorConfig,
+        Metrics,
+    },
+    serde::Deserialize,
+    std::{collections::BTreeMap, fs, path::Path},
+};
+
+/// One group of nodes built from a single Agave version.
+/// Heterogeneous clusters are described by multiple deployments.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DeploymentSpec {
+    pub deploy_method: DeployMethod,
+    pub build_type: Option<BuildType>,
+    #[serde(default)]
+    pub build_options: BuildOptions,
+    /// expected SHA-256 of a `release-channel` tarball
+    pub release_sha256: Option<String>,
+    #[serde(default)]
+    pub num_validators: usize,
+    #[serde(default)]
+    pub num_rpc_nodes: usize,
+    #[serde(default)]
+    pub stake_distribution: StakeDistribution,
+    pub client: Option<ClientConfig>,
+}
+
+/// Declarative cluster definition read from `--config <file>`.
+/// Keys match the command line flag names. Flags passed on the command line take precedence.
+///
+/// e.g. cluster.toml
+/// ```toml
+/// namespace = "greg"
+/// cluster-data-path = "/home/sol/validator-lab-build"
+/// registry = "gregcusack"
+///
+/// [genesis]
+/// slots-per-epoch = 400
+///
+/// [validator]
+/// enable-full-rpc = true
+///
+/// [pod-requests]
+/// cpu = "20"
+/// memory = "70Gi"
+///
+/// [placement.validator]
+/// required-node-labels = { provider = "equinix" }
+///
+/// [[deployments]]
+/// deploy-method = { local = "/home/sol/agave" }
+/// num-validators = 3
+/// client = { bench-tps = { num-clients = 1, bench-tps-args = "tx-count=5000" } }
+///
+/// [[deployments]]
+/// deploy-method = { release-channel = "v1.18.15" }
+/// num-validators = 5
+/// stake-distribution = "power-law:1.5"
+/// num-rpc-nodes = 4
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ClusterSpec {
+    pub namespace: Option<String>,
+    pub cluster_data_path: Option<String>,
+    pub registry: Option<String>,
+    pub image_name: Option<String>,
+    pub base_image: Option<String>,
+    /// local files replacing built-in startup scripts, by script file name
+    pub startup_scripts: BTreeMap<String, String>,
+    pub image_builder: Option<ImageBuilderKind>,
+    pub kaniko_context_dir: Option<String>,
+    pub image_distribution: Option<ImageDistribution>,
+    pub local_cluster: Option<String>,
+    pub no_bootstrap: bool,
+    pub keypair_dir: Option<String>,
+    pub artifact_cache: Option<String>,
+    pub build_cache: Option<String>,
+    pub offline: bool,
+    pub genesis: Option<GenesisFlags>,
+    pub validator: Option<ValidatorConfig>,
+    pub pod_requests: Option<PodRequests>,
+    pub placement: Option<NodePlacement>,
+    pub metrics: Option<Metrics>,
+    pub readiness_timeout: Option<u64>,
+    pub deployments: Vec<DeploymentSpec>,
+}
+
+impl ClusterSpec {
+    pub fn load(path: &Path) -> Result<Self, LabError> {
+        let content = fs::read_to_string(path).map_err(|err| {
+            LabError::config(format!("Failed to read config file {path:?}")).with_source(err)
+        })?;
+        let parse_error = || LabError::config(format!("Failed to parse {path:?}"));
+        let spec = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => {
+                toml::from_str(&content).map_err(|err| parse_error().with_source(err))?
+            }
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&content).map_err(|err| parse_error().with_source(err))?
+            }
+            _ => {
+                return Err(LabError::config(format!(
+                    "Unsupported config file {path:?}. Expected a .toml, .yaml, or .yml file"
+                )))
+            }
+        };
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::client_config::BenchTpsConfig, std::path::PathBuf, tempfile::TempDir};
+
+    fn write_config(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        let toml = write_config(
+            dir,
+            "cluster.toml",
+            r#"
+namespace = "lab"
+readiness-timeout = 600
+
+[[deployments]]
+deploy-method = { release-channel = "v1.18.15" }
+num-validators = 2
+"#,
+        );
+        let yaml = write_config(
+            dir,
+            "cluster.yaml",
+            r#"
+namespace: lab
+readiness-timeout: 600
+deployments:
+  - deploy-method: !release-channel v1.18.15
+    num-validators: 2
+"#,
+        );
+        for path in [toml, yaml] {
+            let spec = ClusterSpec::load(&path).unwrap();
+            assert_eq!(spec.namespace.as_deref(), Some("lab"));
+            assert_eq!(spec.readiness_timeout, Some(600));
+            assert_eq!(spec.deployments.len(), 1);
+            assert_eq!(
+                spec.deployments[0].deploy_method,
+                DeployMethod::ReleaseChannel("v1.18.15".to_string())
+            );
+            assert_eq!(spec.deployments[0].num_validators, 2);
+            assert_eq!(spec.deployments[0].num_rpc_nodes, 0);
+        }
+
+        let unknown_key = write_config(dir, "unknown.toml", "num-validator = 3\n");
+        assert!(ClusterSpec::load(&unknown_key).is_err());
+        let unknown_deployment_key = write_config(
+            dir,
+            "unknown-deployment.yml",
+            "deployments:\n  - deploy-method: !local /agave\n    validators: 3\n",
+        );
+        assert!(ClusterSpec::load(&unknown_deployment_key).is_err());
+        let json = write_config(dir, "cluster.json", "{}");
+        assert!(ClusterSpec::load(&json).is_err());
+        assert!(ClusterSpec::load(&dir.join("missing.toml")).is_err());
+    }
+
+    #[test]
+    fn test_load_heterogeneous_cluster() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let path = write_config(
+            dir,
+            "cluster.toml",
+            r#"
+namespace = "greg"
+cluster-data-path = "/home/sol/validator-lab-build"
+registry = "gregcusack"
+
+[genesis]
+slots-per-epoch = 400
+
+[validator]
+enable-full-rpc = true
+
+[pod-requests]
+cpu = "20"
+memory = "70Gi"
+
+[placement.validator]
+required-node-labels = { provider = "equinix" }
+
+[[deployments]]
+deploy-method = { local = "/home/sol/agave" }
+num-validators = 3
+client = { bench-tps = { num-clients = 1, bench-tps-args = "tx-count=5000" } }
+
+[[deployments]]
+deploy-method = { release-channel = "v1.18.15" }
+num-validators = 5
+stake-distribution = "power-law:1.5"
+num-rpc-nodes = 4
+"#,
+        );
+
+        let spec = ClusterSpec::load(&path).unwrap();
+        assert_eq!(spec.registry.as_deref(), Some("gregcusack"));
+        assert_eq!(spec.genesis.unwrap().slots_per_epoch, Some(400));
+        assert!(spec.validator.unwrap().enable_full_rpc);
+        let pod_requests = spec.pod_requests.unwrap();
+        assert_eq!(pod_requests.cpu(), Some("20"));
+        assert_eq!(pod_requests.memory(), Some("70Gi"));
+        assert_eq!(
+            spec.placement.unwrap().validator.required_node_labels["provider"],
+            "equinix"
+        );
+
+        let [first, second] = spec.deployments.as_slice() else {
+            panic!("Expected 2 deployments, got {:?}", spec.deployments);
+        };
+        assert_eq!(
+            first.deploy_method,
+            DeployMethod::Local("/home/sol/agave".to_string())
+        );
+        assert_eq!(first.num_validators, 3);
+        assert_eq!(first.stake_distribution, StakeDistribution::Uniform);
+        let Some(ClientConfig::BenchTps(BenchTpsConfig {
+            num_clients,
+            bench_tps_args,
+            ..
+        })) = &first.client
+        else {
+            panic!("Expected a bench-tps client, got {:?}", first.client);
+        };
+        assert_eq!(*num_clients, 1);
+        assert_eq!(bench_tps_args, &["--tx-count", "5000"]);
+
+        assert_eq!(
+            second.deploy_method,
+            DeployMethod::ReleaseChannel("v1.18.15".to_string())
+        );
+        assert_eq!(second.num_validators, 5);
+        assert_eq!(second.num_rpc_nodes, 4);
+        assert_eq!(second.stake_distribution, StakeDistribution::PowerLaw(1.5));
+        assert!(second.client.is_none());
+    }
+}
@@ -0,0 +1,513 @@
+use {
+    crate::{
+        cluster_rpc::{ClusterNode, ClusterRpc, VoteAccount, VoteAccounts, RPC_PORT},
+        error::LabError,
+        kube_backend::KubeBackend,
+        kubernetes::LOAD_BALANCER_SERVICE,
+        node::{
+            CLIENT_NAME_LABEL, RPC_NODE_IDENTITY_LABEL, RPC_NODE_TYPE_LABEL,
+            VALIDATOR_IDENTITY_LABEL, VALIDATOR_TYPE_LABEL,
+        },
+        NodeType,
+    },
+    k8s_openapi::api::{
+        apps::v1::ReplicaSet,
+        core::v1::{Node as K8sNode, Service},
+    },
+    log::*,
+    serde::Serialize,
+    solana_sdk::native_token::lamports_to_sol,
+    std::{
+        collections::{BTreeMap, HashMap},
+        fmt::{self, Display, Formatter},
+    },
+};
+
+/// Slot and epoch progress as reported by the cluster RPC
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainStatus {
+    pub slot: u64,
+    pub root_slot: u64,
+    pub epoch: u64,
+    pub slot_index: u64,
+    pub slots_in_epoch: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    pub replica_set: String,
+    pub node_type: String,
+    pub identity: Option<String>,
+    pub desired: i32,
+    pub available: i32,
+    /// None if the node has no identity or the RPC could not be queried
+    pub in_gossip: Option<bool>,
+    pub version: Option<String>,
+    pub vote_account: Option<VoteAccount>,
+    pub delinquent: bool,
+    pub problems: Vec<String>,
+}
+
+impl NodeStatus {
+    fn from_replica_set(replica_set: &ReplicaSet) -> Option<Self> {
+        let labels = replica_set
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.template.as_ref())
+            .and_then(|template| template.metadata.as_ref())
+            .and_then(|metadata| metadata.labels.as_ref())?;
+
+        let (node_type, identity) = if let Some(node_type) = labels.get(VALIDATOR_TYPE_LABEL) {
+            (node_type.clone(), labels.get(VALIDATOR_IDENTITY_LABEL))
+        } else if let Some(node_type) = labels.get(RPC_NODE_TYPE_LABEL) {
+            (node_type.clone(), labels.get(RPC_NODE_IDENTITY_LABEL))
+        } else if labels.contains_key(CLIENT_NAME_LABEL) {
+            ("client".to_string(), None)
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            replica_set: replica_set.metadata.name.clone().unwrap_or_default(),
+            node_type,
+            identity: identity.cloned(),
+            desired: replica_set
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.replicas)
+                .unwrap_or(1),
+            available: replica_set
+                .status
+                .as_ref()
+                .and_then(|status| status.available_replicas)
+                .unwrap_or(0),
+            in_gossip: None,
+            version: None,
+            vote_account: None,
+            delinquent: false,
+            problems: vec![],
+        })
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.available >= self.desired
+    }
+
+    fn is_voting(&self) -> bool {
+        self.node_type == NodeType::Bootstrap.to_string()
+            || self.node_type == NodeType::Standard.to_string()
+    }
+}
+
+/// Readiness of every ReplicaSet we deployed in a namespace,
+/// joined with what the cluster RPC reports about gossip and voting
+#[derive(Debug, Clone, Serialize)]
+pub struct ClusterStatus {
+    pub namespace: String,
+    pub rpc_url: Option<String>,
+    pub rpc_error: Option<String>,
+    pub chain: Option<ChainStatus>,
+    pub nodes: Vec<NodeStatus>,
+}
+
+/// Returns the RPC url of the bootstrap and rpc node load balancer. Uses its external address
+/// if it has one, otherwise its NodePort on the first kubernetes node with an address.
+/// None if the load balancer has not been deployed
+pub async fn load_balancer_rpc_url(
+    backend: &dyn KubeBackend,
+    namespace: &str,
+) -> Result<Option<String>, LabError> {
+    let services = backend.list_services(namespace).await?;
+    let Some(load_balancer) = services
+        .iter()
+        .find(|service| service.metadata.name.as_deref() == Some(LOAD_BALANCER_SERVICE))
+    else {
+        return Ok(None);
+    };
+    if let Some(host) = external_host(load_balancer) {
+        return Ok(Some(format!("http://{host}:{RPC_PORT}")));
+    }
+
+    let node_port = load_balancer
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.ports.as_ref())
+        .and_then(|ports| ports.iter().find(|port| port.port == RPC_PORT))
+        .and_then(|port| port.node_port);
+    let Some(node_port) = node_port else {
+        return Ok(None);
+    };
+    let node_address = backend.list_nodes().await?.iter().find_map(node_address);
+    Ok(node_address.map(|address| format!("http://{address}:{node_port}")))
+}
+
+/// Prefers a node's external IP over its internal one
+fn node_address(node: &K8sNode) -> Option<String> {
+    let addresses = node.status.as_ref()?.addresses.as_ref()?;
+    ["ExternalIP", "InternalIP"].iter().find_map(|type_| {
+        addresses
+            .iter()
+            .find(|address| &address.type_ == type_)
+            .map(|address| address.address.clone())
+    })
+}
+
+fn external_host(service: &Service) -> Option<String> {
+    let ingress = service
+        .status
+        .as_ref()?
+        .load_balancer
+        .as_ref()?
+        .ingress
+        .as_ref()?
+        .first()?;
+    ingress.ip.clone().or_else(|| ingress.hostname.clone())
+}
+
+impl ClusterStatus {
+    /// Lists our ReplicaSets and, if an RPC url is given or the load balancer has one, queries the
+    /// cluster. RPC failures are recorded in `rpc_error` so pod readiness is still reported
+    pub async fn collect(
+        backend: &dyn KubeBackend,
+        namespace: &str,
+        rpc_url: Option<String>,
+    ) -> Result<Self, LabError> {
+        let mut nodes: Vec<NodeStatus> = backend
+            .list_replica_sets(namespace)
+            .await?
+            .iter()
+            .filter_map(NodeStatus::from_replica_set)
+            .collect();
+        nodes.sort_by(|a, b| a.replica_set.cmp(&b.replica_set));
+
+        let rpc_url = match rpc_url {
+            Some(rpc_url) => Some(rpc_url),
+            None => load_balancer_rpc_url(backend, namespace).await?,
+        };
+
+        let mut status = Self {
+            namespace: namespace.to_string(),
+            rpc_url: rpc_url.clone(),
+            rpc_error: None,
+            chain: None,
+            nodes,
+        };
+
+        match rpc_url {
+            Some(rpc_url) => {
+                if let Err(err) = status.query_rpc(ClusterRpc::new(rpc_url)?).await {
+                    warn!("{err}");
+                    status.rpc_error = Some(err.to_string());
+                }
+            }
+            None => {
+                status.rpc_error = Some(format!(
+                    "Could not find an address for {LOAD_BALANCER_SERVICE}. Pass --rpc-url"
+                ))
+            }
+        }
+        status.flag_problems();
+        Ok(status)
+    }
+
+    async fn query_rpc(&mut self, rpc: ClusterRpc) -> Result<(), LabError> {
+        let epoch_info = rpc.get_epoch_info().await?;
+        let root_slot = rpc.get_slot("finalized").await?;
+        self.chain = Some(ChainStatus {
+            slot: epoch_info.absolute_slot,
+            root_slot,
+            epoch: epoch_info.epoch,
+            slot_index: epoch_info.slot_index,
+            slots_in_epoch: epoch_info.slots_in_epoch,
+        });
+
+        let cluster_nodes = rpc.get_cluster_nodes().await?;
+        let vote_accounts = rpc.get_vote_accounts().await?;
+        self.join_rpc_nodes(&cluster_nodes, &vote_accounts);
+        Ok(())
+    }
+
+    fn join_rpc_nodes(&mut self, cluster_nodes: &[ClusterNode], vote_accounts: &VoteAccounts) {
+        let gossip: HashMap<&str, &ClusterNode> = cluster_nodes
+            .iter()
+            .map(|node| (node.pubkey.as_str(), node))
+            .collect();
+        let mut votes: BTreeMap<&str, (&VoteAccount, bool)> = BTreeMap::new();
+        for account in &vote_accounts.current {
+            votes.insert(account.node_pubkey.as_str(), (account, false));
+        }
+        for account in &vote_accounts.delinquent {
+            votes.insert(account.node_pubkey.as_str(), (account, true));
+        }
+
+        for node in &mut self.nodes {
+            let Some(identity) = node.identity.as_deref() else {
+                continue;
+            };
+            let gossip_node = gossip.get(identity);
+            node.in_gossip = Some(gossip_node.is_some());
+            node.version = gossip_node.and_then(|node| node.version.clone());
+            if let Some((account, delinquent)) = votes.get(identity) {
+                node.vote_account = Some((*account).clone());
+                node.delinquent = *delinquent;
+            }
+        }
+    }
+
+    fn flag_problems(&mut self) {
+        for node in &mut self.nodes {
+            if !node.is_ready() {
+                node.problems.push("not ready".to_string());
+            }
+            if node.in_gossip == Some(false) {
+                node.problems.push("missing from gossip".to_string());
+            }
+            if node.delinquent {
+                node.problems.push("delinquent".to_string());
+            }
+            // only known once the RPC has answered
+            if node.in_gossip.is_some() && node.is_voting() && node.vote_account.is_none() {
+                node.problems.push("no vote account".to_string());
+            }
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.rpc_error.is_none() && self.nodes.iter().all(|node| node.problems.is_empty())
+    }
+}
+
+impl Display for ClusterStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Namespace: {}", self.namespace)?;
+        match (&self.rpc_url, &self.chain) {
+            (Some(rpc_url), Some(chain)) => {
+                writeln!(f, "RPC: {rpc_url}")?;
+                writeln!(
+                    f,
+                    "Slot: {}  Root: {}  Epoch: {} ({}/{})",
+                    chain.slot,
+                    chain.root_slot,
+                    chain.epoch,
+                    chain.slot_index,
+                    chain.slots_in_epoch
+                )?;
+            }
+            (Some(rpc_url), None) => writeln!(f, "RPC: {rpc_url}")?,
+            (None, _) => writeln!(f, "RPC: unavailable")?,
+        }
+        if let Some(rpc_error) = &self.rpc_error {
+            writeln!(f, "RPC error: {rpc_error}")?;
+        }
+        writeln!(f)?;
+
+        let unknown = || "-".to_string();
+        let header = [
+            "NAME",
+            "TYPE",
+            "READY",
+            "GOSSIP",
+            "VOTE",
+            "STAKE",
+            "LAST VOTE",
+            "VERSION",
+            "IDENTITY",
+            "PROBLEMS",
+        ]
+        .map(String::from);
+        let rows: Vec<[String; 10]> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let vote = match (&node.vote_account, node.delinquent) {
+                    (Some(_), true) => "delinquent".to_string(),
+                    (Some(_), false) => "current".to_string(),
+                    (None, _) => unknown(),
+                };
+                [
+                    node.replica_set.clone(),
+                    node.node_type.clone(),
+                    format!("{}/{}", node.available, node.desired),
+                    node.in_gossip
+                        .map(|in_gossip| if in_gossip { "yes" } else { "no" }.to_string())
+                        .unwrap_or_else(unknown),
+                    vote,
+                    node.vote_account
+                        .as_ref()
+                        .map(|account| lamports_to_sol(account.activated_stake).to_string())
+                        .unwrap_or_else(unknown),
+                    node.vote_account
+                        .as_ref()
+                        .map(|account| account.last_vote.to_string())
+                        .unwrap_or_else(unknown),
+                    node.version.clone().unwrap_or_else(unknown),
+                    node.identity.clone().unwrap_or_else(unknown),
+                    if node.problems.is_empty() {
+                        "ok".to_string()
+                    } else {
+                        format!("⚠ {}", node.problems.join(", "))
+                    },
+                ]
+            })
+            .collect();
+
+        let mut widths = header.clone().map(|column| column.chars().count());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        for row in std::iter::once(&header).chain(&rows) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            writeln!(f, "{}", line.join("  ").trim_end())?;
+        }
+        if self.nodes.is_empty() {
+            writeln!(f, "No validator-lab ReplicaSets found")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ClientType};
+
+    fn node(replica_set: &str, node_type: NodeType, identity: Option<&str>) -> NodeStatus {
+        NodeStatus {
+            replica_set: replica_set.to_string(),
+            node_type: node_type.to_string(),
+            identity: identity.map(String::from),
+            desired: 1,
+            available: 1,
+            in_gossip: None,
+            version: None,
+            vote_account: None,
+            delinquent: false,
+            problems: vec![],
+        }
+    }
+
+    fn cluster_node(pubkey: &str) -> ClusterNode {
+        ClusterNode {
+            pubkey: pubkey.to_string(),
+            gossip: None,
+            rpc: None,
+            version: Some("1.18.15".to_string()),
+        }
+    }
+
+    fn vote_account(node_pubkey: &str) -> VoteAccount {
+        VoteAccount {
+            vote_pubkey: format!("vote-{node_pubkey}"),
+            node_pubkey: node_pubkey.to_string(),
+            activated_stake: 1,
+            last_vote: 10,
+            root_slot: 5,
+        }
+    }
+
+    fn cluster_status(nodes: Vec<NodeStatus>) -> ClusterStatus {
+        ClusterStatus {
+            namespace: "lab".to_string(),
+            rpc_url: Some("http://localhost:8899".to_string()),
+            rpc_error: None,
+            chain: None,
+            nodes,
+        }
+    }
+
+    fn problems(status: &ClusterStatus) -> Vec<Vec<&str>> {
+        status
+            .nodes
+            .iter()
+            .map(|node| node.problems.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_join_rpc_nodes() {
+        let mut status = cluster_status(vec![
+            node("bootstrap", NodeType::Bootstrap, Some("bootstrap")),
+            node("validator-0", NodeType::Standard, Some("validator-0")),
+            node("validator-1", NodeType::Standard, Some("validator-1")),
+            node("client-0", NodeType::Client(ClientType::BenchTps, 0), None),
+        ]);
+        let vote_accounts = VoteAccounts {
+            current: vec![vote_account("bootstrap")],
+            delinquent: vec![vote_account("validator-0")],
+        };
+        status.join_rpc_nodes(
+            &[cluster_node("bootstrap"), cluster_node("validator-0")],
+            &vote_accounts,
+        );
+
+        let bootstrap = &status.nodes[0];
+        assert_eq!(bootstrap.in_gossip, Some(true));
+        assert_eq!(bootstrap.version.as_deref(), Some("1.18.15"));
+        assert_eq!(
+            bootstrap
+                .vote_account
+                .as_ref()
+                .map(|account| account.vote_pubkey.as_str()),
+            Some("vote-bootstrap")
+        );
+        assert!(!bootstrap.delinquent);
+        assert!(status.nodes[1].delinquent);
+        assert_eq!(status.nodes[2].in_gossip, Some(false));
+        assert!(status.nodes[2].vote_account.is_none());
+        // nodes without an identity are left alone
+        assert_eq!(status.nodes[3].in_gossip, None);
+    }
+
+    #[test]
+    fn test_flag_problems() {
+        let mut not_ready = node("validator-2", NodeType::Standard, Some("validator-2"));
+        not_ready.available = 0;
+        let mut status = cluster_status(vec![
+            node("bootstrap", NodeType::Bootstrap, Some("bootstrap")),
+            node("validator-0", NodeType::Standard, Some("validator-0")),
+            node("validator-1", NodeType::Standard, Some("validator-1")),
+            not_ready,
+            node("rpc-node-0", NodeType::RPC, Some("rpc-node-0")),
+        ]);
+        status.join_rpc_nodes(
+            &[
+                cluster_node("bootstrap"),
+                cluster_node("validator-0"),
+                cluster_node("rpc-node-0"),
+            ],
+            &VoteAccounts {
+                current: vec![vote_account("bootstrap")],
+                delinquent: vec![vote_account("validator-0")],
+            },
+        );
+        status.flag_problems();
+
+        assert_eq!(
+            problems(&status),
+            vec![
+                vec![],
+                vec!["delinquent"],
+                vec!["missing from gossip", "no vote account"],
+                vec!["not ready", "missing from gossip", "no vote account"],
+                // rpc nodes do not vote
+                vec![],
+            ]
+        );
+        assert!(!status.is_healthy());
+
+        // without an RPC answer only readiness is known
+        let mut status = cluster_status(vec![node(
+            "validator-0",
+            NodeType::Standard,
+            Some("validator-0"),
+        )]);
+        status.flag_problems();
+        assert_eq!(problems(&status), vec![Vec::<&str>::new()]);
+        assert!(status.is_healthy());
+    }
+}
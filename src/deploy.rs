@@ -0,0 +1,605 @@
+use {
+    crate::{
+        client_config::ClientConfig,
+        cluster_images::ClusterImages,
+        cluster_rpc::ClusterRpc,
+        error::LabError,
+        image_builder::ImageDistribution,
+        kube_backend::KubeBackend,
+        kubernetes::{
+            Kubernetes, PodRequests, BOOTSTRAP_ACCOUNTS_SECRET, BOOTSTRAP_SERVICE, CLIENT_SERVICE,
+            LOAD_BALANCER_SERVICE, RPC_NODE_SERVICE, VALIDATOR_SERVICE,
+        },
+        node::{
+            LabelType, CLIENT_NAME_LABEL, DEPLOYMENT_TAG_LABEL, RPC_NODE_IDENTITY_LABEL,
+            RPC_NODE_NAME_LABEL, RPC_NODE_TYPE_LABEL, VALIDATOR_IDENTITY_LABEL,
+            VALIDATOR_NAME_LABEL, VALIDATOR_TYPE_LABEL,
+        },
+        node_placement::NodePlacement,
+        readiness::Readiness,
+        validator_config::ValidatorConfig,
+        Metrics, NodeType,
+    },
+    log::*,
+    solana_sdk::{
+        signature::{keypair::read_keypair_file, Keypair},
+        signer::Signer,
+    },
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+        time::Duration,
+    },
+};
+
+/// Where to reach the cluster RPC while waiting for it to converge
+pub enum ReadinessRpc {
+    LoadBalancer,
+    Url(String),
+}
+
+/// What a deployment puts in the cluster once its images are pushed
+/// and its genesis and accounts are in `config_directory`
+pub struct NodeDeployment<'a> {
+    pub namespace: &'a str,
+    /// the image tag, which suffixes the deployment's k8s names
+    pub deployment_tag: String,
+    pub config_directory: PathBuf,
+    pub cluster_images: ClusterImages,
+    pub client_config: ClientConfig,
+    pub num_validators: usize,
+    pub num_rpc_nodes: usize,
+    /// false for each deployment after the first, which joins the existing cluster
+    pub deploy_bootstrap_validator: bool,
+    pub shred_version: u16,
+    /// the scripts published in the deployment's startup scripts ConfigMap, by file name
+    pub startup_scripts: BTreeMap<String, String>,
+    pub pod_requests: PodRequests,
+    pub node_placement: NodePlacement,
+    pub metrics: Option<Metrics>,
+    pub image_distribution: ImageDistribution,
+    pub readiness: &'a Readiness,
+    /// None skips waiting for the cluster to converge
+    pub readiness_rpc: Option<&'a ReadinessRpc>,
+}
+
+pub async fn check_namespace(backend: &dyn KubeBackend, namespace: &str) -> Result<(), LabError> {
+    if !backend
+        .list_namespaces()
+        .await?
+        .iter()
+        .any(|ns| ns == namespace)
+    {
+        return Err(LabError::kube(format!(
+            "Namespace: '{namespace}' doesn't exist. Exiting..."
+        )));
+    }
+    Ok(())
+}
+
+fn read_identity(path: &Path) -> Result<Keypair, LabError> {
+    read_keypair_file(path).map_err(|err| {
+        LabError::genesis(format!("Failed to read keypair {}: {err}", path.display()))
+    })
+}
+
+/// Deploys the startup scripts, secrets, ReplicaSets, and Services of a deployment through `backend`:
+/// the bootstrap validator and load balancer, then RPC nodes, then validators, each waiting to be ready,
+/// then clients once the cluster converges
+pub async fn deploy_nodes(
+    backend: &dyn KubeBackend,
+    validator_config: &mut ValidatorConfig,
+    deployment: NodeDeployment<'_>,
+) -> Result<(), LabError> {
+    let NodeDeployment {
+        namespace,
+        deployment_tag,
+        config_directory,
+        mut cluster_images,
+        client_config,
+        num_validators,
+        num_rpc_nodes,
+        deploy_bootstrap_validator,
+        shred_version,
+        startup_scripts,
+        pod_requests,
+        node_placement,
+        metrics,
+        image_distribution,
+        readiness,
+        readiness_rpc,
+    } = deployment;
+
+    check_namespace(backend, namespace).await?;
+    let mut kub_controller = Kubernetes::new(
+        backend,
+        namespace,
+        validator_config,
+        client_config.clone(),
+        pod_requests,
+        node_placement,
+        metrics,
+        deployment_tag.clone(),
+    );
+    kub_controller.set_image_distribution(image_distribution);
+    kub_controller.set_shred_version(shred_version);
+
+    let startup_scripts = kub_controller.create_startup_scripts_config_map(startup_scripts);
+    kub_controller.deploy_config_map(&startup_scripts).await?;
+    info!("Applied Startup Scripts ConfigMap");
+
+    // metrics secret create once and use by all pods
+    // do not redploy this service for heterogeneous clusters
+    if kub_controller.metrics.is_some() && deploy_bootstrap_validator {
+        let metrics_secret = kub_controller.create_metrics_secret()?;
+        kub_controller.deploy_secret(&metrics_secret).await?;
+    };
+
+    // validators that must be in gossip and voting before the deploy is done
+    let mut validator_identities = vec![];
+    if deploy_bootstrap_validator {
+        let bootstrap_validator = cluster_images.bootstrap()?;
+        let secret =
+            kub_controller.create_bootstrap_secret(BOOTSTRAP_ACCOUNTS_SECRET, &config_directory)?;
+        bootstrap_validator.set_secret(secret);
+
+        kub_controller
+            .deploy_secret(bootstrap_validator.secret())
+            .await?;
+        info!("Deployed Bootstrap Secret");
+
+        // Create Bootstrap labels
+        // Bootstrap needs two labels, one for each service.
+        // One for Load Balancer, one direct
+        let bootstrap_keypair =
+            read_identity(&config_directory.join("bootstrap-validator/identity.json"))?;
+        kub_controller.add_known_validator(bootstrap_keypair.pubkey());
+
+        if kub_controller.validator_supports_full_rpc() {
+            bootstrap_validator.add_label(
+                "load-balancer/name",
+                "load-balancer-selector",
+                LabelType::Service,
+            );
+        }
+        bootstrap_validator.add_label(
+            "service/name",
+            "bootstrap-validator-selector",
+            LabelType::Service,
+        );
+        bootstrap_validator.add_label(
+            VALIDATOR_TYPE_LABEL,
+            bootstrap_validator.node_type().to_string(),
+            LabelType::Info,
+        );
+        bootstrap_validator.add_label(
+            VALIDATOR_IDENTITY_LABEL,
+            bootstrap_keypair.pubkey().to_string(),
+            LabelType::Info,
+        );
+        bootstrap_validator.add_label(DEPLOYMENT_TAG_LABEL, &deployment_tag, LabelType::Info);
+
+        // create bootstrap replica set
+        let replica_set = kub_controller.create_bootstrap_validator_replica_set(
+            bootstrap_validator.image(),
+            bootstrap_validator.secret().metadata.name.clone(),
+            &bootstrap_validator.all_labels(),
+        )?;
+        bootstrap_validator.set_replica_set(replica_set);
+
+        // deploy bootstrap replica set
+        kub_controller
+            .deploy_replicas_set(bootstrap_validator.replica_set())
+            .await?;
+        info!("Deployed {}", bootstrap_validator.replica_set_name());
+
+        // create and deploy bootstrap-service
+        let bootstrap_service = kub_controller
+            .create_bootstrap_service(BOOTSTRAP_SERVICE, bootstrap_validator.service_labels());
+        kub_controller.deploy_service(&bootstrap_service).await?;
+        info!("Deployed Bootstrap Validator Service");
+
+        // load balancer service. only create one and use for all bootstrap/rpc nodes
+        // service selector matches bootstrap selector
+        let load_balancer_label =
+            kub_controller.create_selector("load-balancer/name", "load-balancer-selector");
+        //create load balancer
+        let load_balancer = kub_controller
+            .create_validator_load_balancer(LOAD_BALANCER_SERVICE, &load_balancer_label)
+            .await?;
+
+        //deploy load balancer
+        kub_controller.deploy_service(&load_balancer).await?;
+        info!("Deployed Load Balancer Service");
+
+        // wait for bootstrap replicaset to deploy
+        readiness
+            .wait_for_replica_sets(
+                &kub_controller,
+                &[bootstrap_validator.replica_set_name().clone()],
+                true,
+            )
+            .await?;
+        validator_identities.push(bootstrap_keypair.pubkey().to_string());
+    }
+
+    if num_rpc_nodes > 0 {
+        let rpc_node = cluster_images.rpc()?;
+        let mut rpc_nodes = vec![];
+        // Create and deploy rpc secrets
+        for rpc_index in 0..num_rpc_nodes {
+            let rpc_secret = kub_controller.create_rpc_secret(rpc_index, &config_directory)?;
+            rpc_node.set_secret(rpc_secret);
+            kub_controller.deploy_secret(rpc_node.secret()).await?;
+            info!("Deployed RPC Node {rpc_index} Secret");
+
+            let rpc_keypair = read_identity(&config_directory.join(format!(
+                "rpc-node-identity-{deployment_tag}-{rpc_index}.json"
+            )))?;
+
+            rpc_node.add_label(
+                RPC_NODE_NAME_LABEL,
+                format!("rpc-node-{rpc_index}"),
+                LabelType::Service,
+            );
+
+            rpc_node.add_label(
+                RPC_NODE_TYPE_LABEL,
+                rpc_node.node_type().to_string(),
+                LabelType::Info,
+            );
+
+            rpc_node.add_label(
+                RPC_NODE_IDENTITY_LABEL,
+                rpc_keypair.pubkey().to_string(),
+                LabelType::Info,
+            );
+            rpc_node.add_label(DEPLOYMENT_TAG_LABEL, &deployment_tag, LabelType::Info);
+
+            rpc_node.add_label(
+                "load-balancer/name",
+                "load-balancer-selector",
+                LabelType::Service,
+            );
+
+            let replica_set = kub_controller.create_rpc_replica_set(
+                rpc_node.image(),
+                rpc_node.secret().metadata.name.clone(),
+                &rpc_node.all_labels(),
+                rpc_index,
+            )?;
+            rpc_node.set_replica_set(replica_set);
+
+            kub_controller
+                .deploy_replicas_set(rpc_node.replica_set())
+                .await?;
+            info!("Deployed RPC Node Replica Set ({rpc_index})");
+
+            let rpc_service = kub_controller.create_service(
+                RPC_NODE_SERVICE,
+                rpc_index,
+                rpc_node.service_labels(),
+            );
+            kub_controller.deploy_service(&rpc_service).await?;
+            info!("Deployed RPC Node Service ({rpc_index})");
+
+            rpc_nodes.push(rpc_node.replica_set_name().clone());
+        }
+
+        // wait for at least one rpc node to deploy
+        readiness
+            .wait_for_replica_sets(&kub_controller, &rpc_nodes, false)
+            .await?;
+    }
+
+    if num_validators > 0 {
+        let validator = cluster_images.validator()?;
+        let mut validator_replica_sets = vec![];
+        for validator_index in 0..num_validators {
+            // Create and deploy validators secrets
+            let validator_secret =
+                kub_controller.create_validator_secret(validator_index, &config_directory)?;
+            validator.set_secret(validator_secret);
+            kub_controller.deploy_secret(validator.secret()).await?;
+            info!("Deployed Validator {validator_index} Secret");
+
+            let validator_keypair = read_identity(&config_directory.join(format!(
+                "validator-identity-{deployment_tag}-{validator_index}.json"
+            )))?;
+
+            validator.add_label(
+                VALIDATOR_NAME_LABEL,
+                format!("validator-{validator_index}"),
+                LabelType::Service,
+            );
+            validator.add_label(
+                VALIDATOR_TYPE_LABEL,
+                validator.node_type().to_string(),
+                LabelType::Info,
+            );
+            validator.add_label(
+                VALIDATOR_IDENTITY_LABEL,
+                validator_keypair.pubkey().to_string(),
+                LabelType::Info,
+            );
+            validator.add_label(DEPLOYMENT_TAG_LABEL, &deployment_tag, LabelType::Info);
+
+            let replica_set = kub_controller.create_validator_replica_set(
+                validator.image(),
+                validator.secret().metadata.name.clone(),
+                &validator.all_labels(),
+                validator_index,
+            )?;
+            validator.set_replica_set(replica_set);
+
+            kub_controller
+                .deploy_replicas_set(validator.replica_set())
+                .await?;
+            info!("Deployed Validator Replica Set ({validator_index})");
+
+            let validator_service = kub_controller.create_service(
+                VALIDATOR_SERVICE,
+                validator_index,
+                validator.service_labels(),
+            );
+            kub_controller.deploy_service(&validator_service).await?;
+            info!("Deployed Validator Service ({validator_index})");
+
+            validator_replica_sets.push(validator.replica_set_name().clone());
+            validator_identities.push(validator_keypair.pubkey().to_string());
+        }
+
+        readiness
+            .wait_for_replica_sets(&kub_controller, &validator_replica_sets, true)
+            .await?;
+    }
+
+    if let Some(readiness_rpc) = readiness_rpc {
+        let rpc_url = match readiness_rpc {
+            ReadinessRpc::Url(url) => url.clone(),
+            ReadinessRpc::LoadBalancer => kub_controller
+                .load_balancer_rpc_url()
+                .await?
+                .ok_or_else(|| {
+                    LabError::readiness(
+                        "Could not find an address for the load balancer. \
+                        Pass --readiness-rpc-url or --skip-readiness-check",
+                    )
+                })?,
+        };
+        readiness
+            .wait_for_convergence(&ClusterRpc::new(rpc_url)?, &validator_identities)
+            .await?;
+    }
+
+    if let ClientConfig::Generic(ref generic_config) = client_config {
+        info!(
+            "Waiting {}s before deploying client",
+            generic_config.delay_start
+        );
+        std::thread::sleep(Duration::from_secs(generic_config.delay_start));
+    }
+
+    for client_node in cluster_images.get_clients_mut() {
+        let client_index = if let NodeType::Client(_, index) = client_node.node_type() {
+            *index
+        } else {
+            return Err(LabError::config("Invalid Validator Type in Client"));
+        };
+
+        let client_secret = kub_controller.create_client_secret(client_index, &config_directory)?;
+        client_node.set_secret(client_secret);
+
+        kub_controller.deploy_secret(client_node.secret()).await?;
+        info!("Deployed Client {client_index} Secret");
+
+        client_node.add_label(
+            CLIENT_NAME_LABEL,
+            format!("client-{client_index}"),
+            LabelType::Service,
+        );
+        client_node.add_label(DEPLOYMENT_TAG_LABEL, &deployment_tag, LabelType::Info);
+
+        let client_replica_set = kub_controller.create_client_replica_set(
+            client_node.image(),
+            client_node.secret().metadata.name.clone(),
+            &client_node.all_labels(),
+            client_index,
+        )?;
+        client_node.set_replica_set(client_replica_set);
+
+        kub_controller
+            .deploy_replicas_set(client_node.replica_set())
+            .await?;
+        info!("Deployed Client Replica Set ({client_index})");
+
+        let client_service = kub_controller.create_service(
+            CLIENT_SERVICE,
+            client_index,
+            client_node.service_labels(),
+        );
+        kub_controller.deploy_service(&client_service).await?;
+        info!("Deployed Client Service ({client_index})");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            docker::DockerImage,
+            genesis::{Genesis, GenesisFlags},
+            kube_backend::InMemoryBackend,
+            kubernetes::{RPC_NODE_ACCOUNT_SECRET, VALIDATOR_ACCOUNTS_SECRET},
+            node::Node,
+            startup_scripts::StartupScripts,
+        },
+        tempfile::TempDir,
+    };
+
+    const NAMESPACE: &str = "lab";
+    const TAG: &str = "v1-18-15";
+
+    fn node(node_type: NodeType) -> Node {
+        Node::new(DockerImage::new(
+            "registry".to_string(),
+            node_type,
+            "k8s-image".to_string(),
+            TAG.to_string(),
+        ))
+    }
+
+    fn deployment<'a>(
+        config_directory: &Path,
+        readiness: &'a Readiness,
+        startup_scripts: BTreeMap<String, String>,
+    ) -> NodeDeployment<'a> {
+        let mut cluster_images = ClusterImages::default();
+        for node_type in [NodeType::Bootstrap, NodeType::Standard, NodeType::RPC] {
+            cluster_images.set_item(node(node_type));
+        }
+        NodeDeployment {
+            namespace: NAMESPACE,
+            deployment_tag: TAG.to_string(),
+            config_directory: config_directory.to_path_buf(),
+            cluster_images,
+            client_config: ClientConfig::None,
+            num_validators: 2,
+            num_rpc_nodes: 1,
+            deploy_bootstrap_validator: true,
+            shred_version: 42,
+            startup_scripts,
+            pod_requests: PodRequests::new("1".to_string(), "1Gi".to_string()),
+            node_placement: NodePlacement::default(),
+            metrics: None,
+            image_distribution: ImageDistribution::Registry,
+            readiness,
+            readiness_rpc: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deploy_nodes() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path();
+        let mut genesis = Genesis::new(
+            config_dir.to_path_buf(),
+            GenesisFlags::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        genesis.generate_faucet().unwrap();
+        genesis
+            .generate_accounts(NodeType::Bootstrap, 1, None)
+            .unwrap();
+        genesis
+            .generate_accounts(NodeType::Standard, 2, Some(TAG))
+            .unwrap();
+        genesis
+            .generate_accounts(NodeType::RPC, 1, Some(TAG))
+            .unwrap();
+        let startup_scripts = StartupScripts::load(&BTreeMap::new()).unwrap();
+        let readiness = Readiness::new(Duration::from_secs(1));
+
+        // nothing is created in a namespace that doesn't exist
+        let mut validator_config = ValidatorConfig::default();
+        let missing = InMemoryBackend::new(&["other"]);
+        assert!(deploy_nodes(
+            &missing,
+            &mut validator_config,
+            deployment(config_dir, &readiness, startup_scripts.clone()),
+        )
+        .await
+        .is_err());
+        assert!(missing.objects().replica_sets.is_empty());
+
+        let mut validator_config = ValidatorConfig {
+            validator_stakes: vec![100.0, 5.0],
+            ..ValidatorConfig::default()
+        };
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        deploy_nodes(
+            &backend,
+            &mut validator_config,
+            deployment(config_dir, &readiness, startup_scripts),
+        )
+        .await
+        .unwrap();
+        let bootstrap_keypair =
+            read_identity(&config_dir.join("bootstrap-validator/identity.json")).unwrap();
+
+        let objects = backend.objects();
+        let names = |keys: Vec<&(String, String)>| -> Vec<String> {
+            keys.into_iter()
+                .map(|(namespace, name)| {
+                    assert_eq!(namespace, NAMESPACE);
+                    name.clone()
+                })
+                .collect()
+        };
+        assert_eq!(
+            names(objects.config_maps.keys().collect()),
+            vec![format!("startup-scripts-{TAG}")]
+        );
+        assert_eq!(
+            names(objects.secrets.keys().collect()),
+            vec![
+                format!("{BOOTSTRAP_ACCOUNTS_SECRET}-{TAG}"),
+                format!("{RPC_NODE_ACCOUNT_SECRET}-{TAG}-0"),
+                format!("{VALIDATOR_ACCOUNTS_SECRET}-{TAG}-0"),
+                format!("{VALIDATOR_ACCOUNTS_SECRET}-{TAG}-1"),
+            ]
+        );
+        assert_eq!(
+            names(objects.replica_sets.keys().collect()),
+            vec![
+                format!("bootstrap-validator-{TAG}-replicaset"),
+                format!("rpc-node-{TAG}-0-replicaset"),
+                format!("validator-{TAG}-0-replicaset"),
+                format!("validator-{TAG}-1-replicaset"),
+            ]
+        );
+        assert_eq!(
+            names(objects.services.keys().collect()),
+            vec![
+                LOAD_BALANCER_SERVICE.to_string(),
+                BOOTSTRAP_SERVICE.to_string(),
+                format!("{RPC_NODE_SERVICE}-{TAG}-0"),
+                format!("{VALIDATOR_SERVICE}-{TAG}-0"),
+                format!("{VALIDATOR_SERVICE}-{TAG}-1"),
+            ]
+        );
+
+        // each validator stakes its own amount and knows about the bootstrap
+        let command = |index: usize| -> Vec<String> {
+            let key = (
+                NAMESPACE.to_string(),
+                format!("validator-{TAG}-{index}-replicaset"),
+            );
+            objects.replica_sets[&key]
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.template.as_ref())
+                .and_then(|template| template.spec.as_ref())
+                .map(|spec| spec.containers[0].command.clone().unwrap())
+                .unwrap()
+        };
+        let flag_value = |command: &[String], flag: &str| -> String {
+            let position = command.iter().position(|arg| arg == flag).unwrap();
+            command[position + 1].clone()
+        };
+        let validator_0 = command(0);
+        let validator_1 = command(1);
+        assert_eq!(flag_value(&validator_0, "--internal-node-stake-sol"), "100");
+        assert_eq!(flag_value(&validator_1, "--internal-node-stake-sol"), "5");
+        assert_eq!(flag_value(&validator_0, "--expected-shred-version"), "42");
+        assert_eq!(
+            flag_value(&validator_1, "--known-validator"),
+            bootstrap_keypair.pubkey().to_string()
+        );
+    }
+}
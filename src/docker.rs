@@ -1,18 +1,51 @@
 use {
     crate::{
-        new_spinner_progress_bar, node::Node, startup_scripts::StartupScripts, ClientType,
-        NodeType, BUILD, ROCKET, SOLANA_RELEASE,
+        error::LabError,
+        image_builder::{check_output, ImageBuilder, ImageDistribution, BUILD_CONTEXT_PATHS},
+        new_spinner_progress_bar, ClientType, NodeType, BUILD, ROCKET, SOLANA_RELEASE,
     },
     log::*,
     std::{
-        error::Error,
         fmt::{self, Display, Formatter},
         fs,
-        path::{Path, PathBuf},
-        process::{Child, Command, Stdio},
+        path::Path,
+        process::Child,
     },
 };
 
+/// The images a push sent to the registry, those it already held,
+/// and those left for an external builder such as kaniko to push
+#[derive(Default)]
+pub struct PushedImages {
+    pub pushed: Vec<DockerImage>,
+    pub reused: Vec<DockerImage>,
+    pub external: Vec<DockerImage>,
+}
+
+impl Display for PushedImages {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Pushed {} image(s)", self.pushed.len())?;
+        for image in &self.pushed {
+            writeln!(f, "  {image}")?;
+        }
+        write!(f, "Reused {} image(s)", self.reused.len())?;
+        for image in &self.reused {
+            write!(f, "\n  {image}")?;
+        }
+        if !self.external.is_empty() {
+            write!(
+                f,
+                "\nLeft {} image(s) for the external builder to push",
+                self.external.len()
+            )?;
+            for image in &self.external {
+                write!(f, "\n  {image}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct DockerImage {
     registry: String,
@@ -35,10 +68,15 @@ impl DockerImage {
     }
 
     /// parse from string <registry>/<name>:<tag>
-    pub fn new_from_string(image_string: String) -> Result<Self, Box<dyn Error>> {
+    pub fn new_from_string(image_string: String) -> Result<Self, LabError> {
+        let invalid_format = || {
+            LabError::config(format!(
+                "Invalid image '{image_string}'. Expected <registry>/<name>:<tag>"
+            ))
+        };
         let split_string: Vec<&str> = image_string.split('/').collect();
         if split_string.len() != 2 {
-            return Err("Invalid format. Expected <registry>/<name>:<tag>".into());
+            return Err(invalid_format());
         }
 
         let registry = split_string[0].to_string();
@@ -46,7 +84,7 @@ impl DockerImage {
         // Split the second part into name and tag
         let name_tag: Vec<&str> = split_string[1].split(':').collect();
         if name_tag.len() != 2 {
-            return Err("Invalid format. Expected <registry>/<name>:<tag>".into());
+            return Err(invalid_format());
         }
 
         Ok(DockerImage {
@@ -65,135 +103,103 @@ impl DockerImage {
     pub fn tag(&self) -> String {
         self.tag.clone()
     }
+
+    /// Bootstrap and RPC nodes start from the genesis ledger, layered on the shared image
+    pub fn has_ledger(&self) -> bool {
+        matches!(self.node_type, NodeType::Bootstrap | NodeType::RPC)
+    }
+
+    /// The image holding this version's binaries and startup scripts, shared by every node
+    pub fn shared_image(&self) -> DockerImage {
+        DockerImage {
+            node_type: NodeType::Standard,
+            optional_full_image_path: None,
+            ..self.clone()
+        }
+    }
 }
 
-// Put DockerImage in format for building, pushing, and pulling
+// Put DockerImage in format for building, pushing, and pulling.
+// Every node of a version runs <registry>/<name>:<tag>,
+// except the bootstrap and RPC nodes, which run <registry>/<name>-ledger:<tag>
 impl Display for DockerImage {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self.node_type {
-            NodeType::Client(_, index) => {
-                if let Some(image_path) = &self.optional_full_image_path {
-                    write!(f, "{image_path}")
-                } else {
-                    write!(
-                        f,
-                        "{}/{}-{}-{}:{}",
-                        self.registry, self.node_type, index, self.image_name, self.tag
-                    )
-                }
-            }
-            NodeType::Bootstrap | NodeType::Standard | NodeType::RPC => write!(
+        if let Some(image_path) = &self.optional_full_image_path {
+            return write!(f, "{image_path}");
+        }
+        if self.has_ledger() {
+            write!(
                 f,
-                "{}/{}-{}:{}",
-                self.registry, self.node_type, self.image_name, self.tag
-            ),
+                "{}/{}-ledger:{}",
+                self.registry, self.image_name, self.tag
+            )
+        } else {
+            write!(f, "{}/{}:{}", self.registry, self.image_name, self.tag)
         }
     }
 }
 
 pub struct DockerConfig {
     pub base_image: String,
+    builder: Box<dyn ImageBuilder>,
+    image_distribution: ImageDistribution,
+    /// kind or k3d cluster images are side-loaded into
+    local_cluster: Option<String>,
 }
 
 impl DockerConfig {
-    pub fn new(base_image: String) -> Self {
-        DockerConfig { base_image }
+    pub fn new(
+        base_image: String,
+        builder: Box<dyn ImageBuilder>,
+        image_distribution: ImageDistribution,
+        local_cluster: Option<String>,
+    ) -> Self {
+        DockerConfig {
+            base_image,
+            builder,
+            image_distribution,
+            local_cluster,
+        }
     }
 
-    pub fn build_image(
+    /// Builds the shared image of each of `docker_images`, then the ledger images layered on it.
+    /// Returns every image built, once each
+    pub fn build_images<'a, I>(
         &self,
         solana_root_path: &Path,
-        docker_image: &DockerImage,
-    ) -> Result<(), Box<dyn Error>> {
-        let node_type = docker_image.node_type();
-        let docker_path = match node_type {
-            NodeType::Bootstrap | NodeType::Standard | NodeType::RPC => {
-                solana_root_path.join(format!("docker-build/{node_type}"))
+        docker_images: I,
+    ) -> Result<Vec<DockerImage>, LabError>
+    where
+        I: IntoIterator<Item = &'a DockerImage>,
+    {
+        Self::write_dockerignore(solana_root_path)?;
+        let mut built: Vec<DockerImage> = vec![];
+        let is_built = |built: &[DockerImage], image: &DockerImage| {
+            built
+                .iter()
+                .any(|built| built.to_string() == image.to_string())
+        };
+        for docker_image in docker_images {
+            let shared_image = docker_image.shared_image();
+            if !is_built(&built, &shared_image) {
+                self.build_shared_image(solana_root_path, &shared_image)?;
+                built.push(shared_image.clone());
             }
-            NodeType::Client(_, index) => {
-                solana_root_path.join(format!("docker-build/{node_type}-{index}"))
+            if docker_image.has_ledger() && !is_built(&built, docker_image) {
+                self.build_ledger_image(solana_root_path, docker_image, &shared_image)?;
+                built.push(docker_image.clone());
             }
-        };
-
-        self.create_base_image(solana_root_path, docker_image, &docker_path, &node_type)?;
-
-        Ok(())
-    }
-
-    fn create_base_image(
-        &self,
-        solana_root_path: &Path,
-        docker_image: &DockerImage,
-        docker_path: &PathBuf,
-        node_type: &NodeType,
-    ) -> Result<(), Box<dyn Error>> {
-        self.create_dockerfile(node_type, docker_path, solana_root_path, None)?;
-
-        // We use std::process::Command here because Docker-rs is very slow building dockerfiles
-        // when they are in large repos. Docker-rs doesn't seem to support the `--file` flag natively.
-        // so we result to using std::process::Command
-        let dockerfile = docker_path.join("Dockerfile");
-        let context_path = solana_root_path.display().to_string();
-
-        let progress_bar = new_spinner_progress_bar();
-        progress_bar.set_message(format!("{BUILD}Building {node_type} docker image...",));
-        let command = format!(
-            "docker build -t {docker_image} -f {} {context_path}",
-            dockerfile.display()
-        );
-        debug!("docker command: {command}");
-
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(&command)
-            .stdout(Stdio::null())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(Box::new)?;
-
-        if !output.status.success() {
-            return Err(String::from_utf8_lossy(&output.stderr).into());
         }
-        progress_bar.finish_and_clear();
-
-        Ok(())
+        Ok(built)
     }
 
-    fn write_startup_script_to_docker_directory(
-        file_name: &str,
-        docker_dir: &Path,
-        node_type: &NodeType,
-    ) -> Result<(), Box<dyn Error>> {
-        let script_path = docker_dir.join(file_name);
-        let script_content = node_type.script();
-        StartupScripts::write_script_to_file(script_content, &script_path).map_err(|e| e.into())
-    }
-
-    fn create_dockerfile(
+    fn build_shared_image(
         &self,
-        node_type: &NodeType,
-        docker_path: &PathBuf,
         solana_root_path: &Path,
-        content: Option<&str>,
-    ) -> Result<(), Box<dyn Error>> {
-        if docker_path.exists() {
-            fs::remove_dir_all(docker_path)?;
-        }
-        fs::create_dir_all(docker_path)?;
-
-        let file_name = format!("{node_type}-startup-script.sh");
-        Self::write_startup_script_to_docker_directory(&file_name, docker_path, node_type)?;
-        StartupScripts::write_script_to_file(
-            StartupScripts::common(),
-            &docker_path.join("common.sh"),
-        )?;
-
-        let startup_script_directory = match node_type {
-            NodeType::Bootstrap | NodeType::Standard | NodeType::RPC => {
-                format!("./docker-build/{node_type}")
-            }
-            NodeType::Client(_, index) => format!("./docker-build/{node_type}-{index}"),
-        };
+        docker_image: &DockerImage,
+    ) -> Result<(), LabError> {
+        let docker_path = solana_root_path.join("docker-build/shared");
+        Self::create_build_directory(&docker_path)?;
 
         let dockerfile = format!(
             r#"
@@ -204,94 +210,142 @@ RUN apt-get update && apt-get install -y iputils-ping curl vim && \
     adduser solana sudo
 
 USER solana
-COPY --chown=solana:solana  {startup_script_directory} /home/solana/k8s-cluster-scripts
-RUN chmod +x /home/solana/k8s-cluster-scripts/*
-{}
 COPY --chown=solana:solana ./{SOLANA_RELEASE}/bin/ /home/solana/bin/
 COPY --chown=solana:solana ./{SOLANA_RELEASE}/version.yml /home/solana/
 ENV PATH="/home/solana/bin:${{PATH}}"
 
 WORKDIR /home/solana
-{}
 "#,
             self.base_image,
-            DockerConfig::check_copy_ledger(node_type),
-            self.insert_client_accounts_if_present(solana_root_path, node_type)?
         );
-
-        debug!("dockerfile: {dockerfile:?}");
-        std::fs::write(
-            docker_path.join("Dockerfile"),
-            content.unwrap_or(dockerfile.as_str()),
-        )?;
-        Ok(())
+        self.build(
+            solana_root_path,
+            docker_image,
+            &docker_path,
+            &dockerfile,
+            "shared",
+        )
     }
 
-    fn check_copy_ledger(node_type: &NodeType) -> String {
-        match node_type {
-            NodeType::Bootstrap | NodeType::RPC => {
-                "COPY --chown=solana:solana ./config-k8s/bootstrap-validator /home/solana/ledger"
-                    .to_string()
-            }
-            NodeType::Standard | &NodeType::Client(_, _) => "".to_string(),
-        }
+    fn build_ledger_image(
+        &self,
+        solana_root_path: &Path,
+        docker_image: &DockerImage,
+        shared_image: &DockerImage,
+    ) -> Result<(), LabError> {
+        let docker_path = solana_root_path.join("docker-build/ledger");
+        Self::create_build_directory(&docker_path)?;
+        let dockerfile = format!(
+            r#"
+FROM {shared_image}
+COPY --chown=solana:solana ./config-k8s/bootstrap-validator /home/solana/ledger
+"#
+        );
+        self.build(
+            solana_root_path,
+            docker_image,
+            &docker_path,
+            &dockerfile,
+            "ledger",
+        )
     }
 
-    fn insert_client_accounts_if_present(
+    fn build(
         &self,
         solana_root_path: &Path,
-        node_type: &NodeType,
-    ) -> Result<String, Box<dyn Error>> {
-        match node_type {
-            NodeType::Client(_, index) => {
-                let bench_tps_path =
-                    solana_root_path.join(format!("config-k8s/bench-tps-{index}.yml"));
-                if bench_tps_path.exists() {
-                    Ok(format!(
-                        r#"
-COPY --chown=solana:solana ./config-k8s/bench-tps-{index}.yml /home/solana/client-accounts.yml
-                    "#
-                    ))
-                } else {
-                    Err(format!("{bench_tps_path:?} does not exist!").into())
-                }
-            }
-            NodeType::Bootstrap | NodeType::Standard | NodeType::RPC => Ok("".to_string()),
-        }
+        docker_image: &DockerImage,
+        docker_path: &Path,
+        dockerfile: &str,
+        description: &str,
+    ) -> Result<(), LabError> {
+        debug!("dockerfile: {dockerfile:?}");
+        let dockerfile_path = docker_path.join("Dockerfile");
+        fs::write(&dockerfile_path, dockerfile).map_err(|err| {
+            LabError::docker(format!("Failed to write {}", dockerfile_path.display()))
+                .with_source(err)
+        })?;
+
+        let progress_bar = new_spinner_progress_bar();
+        progress_bar.set_message(format!("{BUILD}Building {description} docker image...",));
+        self.builder
+            .build(docker_image, &dockerfile_path, solana_root_path)?;
+        progress_bar.finish_and_clear();
+
+        Ok(())
     }
 
-    pub fn push_image(docker_image: &DockerImage) -> Result<Child, Box<dyn Error>> {
-        let command = format!("docker push '{docker_image}'");
-        let child = Command::new("sh")
-            .arg("-c")
-            .arg(&command)
-            .stdout(Stdio::null())
-            .stderr(Stdio::piped())
-            .spawn()?;
+    fn create_build_directory(docker_path: &Path) -> Result<(), LabError> {
+        let io_error = |err: std::io::Error| {
+            LabError::docker(format!(
+                "Failed to set up docker build directory {}",
+                docker_path.display()
+            ))
+            .with_source(err)
+        };
+        if docker_path.exists() {
+            fs::remove_dir_all(docker_path).map_err(io_error)?;
+        }
+        fs::create_dir_all(docker_path).map_err(io_error)
+    }
 
-        Ok(child)
+    /// Keeps everything but the paths the Dockerfiles copy from out of the build context
+    fn write_dockerignore(solana_root_path: &Path) -> Result<(), LabError> {
+        let dockerignore = solana_root_path.join(".dockerignore");
+        let content = std::iter::once("*".to_string())
+            .chain(BUILD_CONTEXT_PATHS.iter().map(|path| format!("!{path}")))
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(&dockerignore, content + "\n").map_err(|err| {
+            LabError::docker(format!("Failed to write {}", dockerignore.display())).with_source(err)
+        })
     }
 
-    pub fn push_images<'a, I>(&self, nodes: I) -> Result<(), Box<dyn Error>>
-    where
-        I: IntoIterator<Item = &'a Node>,
-    {
+    /// Pushes `docker_images`, skipping those the registry already holds with the same content
+    /// With a kind or k3d image distribution, they are side-loaded into the cluster's nodes instead
+    pub fn push_images(&self, docker_images: &[DockerImage]) -> Result<PushedImages, LabError> {
+        let mut pushed_images = PushedImages::default();
+        if self.image_distribution.is_side_load() {
+            let cluster = self
+                .local_cluster
+                .as_deref()
+                .or(self.image_distribution.default_cluster_name())
+                .unwrap_or_default();
+            for image in docker_images {
+                info!("Loading {image} into cluster {cluster}");
+                self.image_distribution.side_load(image, cluster)?;
+                pushed_images.pushed.push(image.clone());
+            }
+            return Ok(pushed_images);
+        }
+        if self.builder.pushes_externally() {
+            pushed_images.external = docker_images.to_vec();
+            return Ok(pushed_images);
+        }
+
         info!("Pushing images...");
-        let children: Result<Vec<Child>, _> = nodes
-            .into_iter()
-            .map(|node| Self::push_image(node.image()))
-            .collect();
+        let mut children: Vec<(&DockerImage, Child)> = vec![];
+        for image in docker_images {
+            if self.builder.is_in_registry(image)? {
+                info!("{image} is unchanged in the registry, reusing it");
+                pushed_images.reused.push(image.clone());
+                continue;
+            }
+            if let Some(child) = self.builder.push(image)? {
+                children.push((image, child));
+            }
+            pushed_images.pushed.push(image.clone());
+        }
 
         let progress_bar = new_spinner_progress_bar();
         progress_bar.set_message(format!("{ROCKET}Pushing images to registry..."));
-        for child in children? {
-            let output = child.wait_with_output()?;
-            if !output.status.success() {
-                return Err(String::from_utf8_lossy(&output.stderr).into());
-            }
+        for (image, child) in children {
+            let output = child.wait_with_output().map_err(|err| {
+                LabError::docker(format!("Failed to wait for the push of {image}")).with_source(err)
+            })?;
+            check_output(&format!("push {image}"), output)?;
         }
         progress_bar.finish_and_clear();
 
-        Ok(())
+        Ok(pushed_images)
     }
 }
@@ -0,0 +1,167 @@
+use thiserror::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors returned by the library, grouped by the stage of a deployment they come from.
+/// Each carries a description of what failed and, when there is one, the underlying error
+#[derive(Debug, Error)]
+pub enum LabError {
+    /// building Agave or fetching its source
+    #[error("Build error: {context}")]
+    Build {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// downloading release archives or genesis programs
+    #[error("Download error: {context}")]
+    Download {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// generating accounts or the genesis ledger
+    #[error("Genesis error: {context}")]
+    Genesis {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// building or pushing images
+    #[error("Docker error: {context}")]
+    Docker {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// creating or querying Kubernetes objects
+    #[error("Kubernetes error: {context}")]
+    Kube {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// querying the cluster's RPC endpoint
+    #[error("RPC error: {context}")]
+    Rpc {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// waiting for the cluster to come up
+    #[error("Readiness error: {context}")]
+    Readiness {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// invalid flags or config files
+    #[error("Config error: {context}")]
+    Config {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// saving collected pod logs
+    #[error("Logs error: {context}")]
+    Logs {
+        context: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+}
+
+impl LabError {
+    pub fn build(context: impl Into<String>) -> Self {
+        Self::Build {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn download(context: impl Into<String>) -> Self {
+        Self::Download {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn genesis(context: impl Into<String>) -> Self {
+        Self::Genesis {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn docker(context: impl Into<String>) -> Self {
+        Self::Docker {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn kube(context: impl Into<String>) -> Self {
+        Self::Kube {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn rpc(context: impl Into<String>) -> Self {
+        Self::Rpc {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn readiness(context: impl Into<String>) -> Self {
+        Self::Readiness {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn config(context: impl Into<String>) -> Self {
+        Self::Config {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    pub fn logs(context: impl Into<String>) -> Self {
+        Self::Logs {
+            context: context.into(),
+            source: None,
+        }
+    }
+
+    /// Attaches the error that caused this one
+    pub fn with_source(mut self, err: impl Into<BoxError>) -> Self {
+        match &mut self {
+            Self::Build { source, .. }
+            | Self::Download { source, .. }
+            | Self::Genesis { source, .. }
+            | Self::Docker { source, .. }
+            | Self::Kube { source, .. }
+            | Self::Rpc { source, .. }
+            | Self::Readiness { source, .. }
+            | Self::Config { source, .. }
+            | Self::Logs { source, .. } => *source = Some(err.into()),
+        }
+        self
+    }
+
+    pub fn context(&self) -> &str {
+        match self {
+            Self::Build { context, .. }
+            | Self::Download { context, .. }
+            | Self::Genesis { context, .. }
+            | Self::Docker { context, .. }
+            | Self::Kube { context, .. }
+            | Self::Rpc { context, .. }
+            | Self::Readiness { context, .. }
+            | Self::Config { context, .. }
+            | Self::Logs { context, .. } => context,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use {
+    crate::{
+        artifact_cache::{feature_set_key, is_moving_release, ArtifactCache},
+        error::LabError,
+        genesis::GenesisFlags,
+        release::{DeployMethod, GitCheckout},
+    },
+    log::*,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::BTreeSet,
+        fs,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+};
+
+/// Where the feature ids are declared, newest layout first
+const FEATURE_SET_SOURCES: [&str; 2] = ["feature-set/src/lib.rs", "sdk/src/feature_set.rs"];
+
+/// Extracts every feature id from the source of a version's feature set.
+/// Each feature is a module holding `declare_id!("<pubkey>")`
+pub fn parse_feature_ids(source: &str) -> Result<BTreeSet<Pubkey>, LabError> {
+    const DECLARE_ID: &str = "declare_id!(\"";
+    source
+        .match_indices(DECLARE_ID)
+        .map(|(start, _)| {
+            let id = &source[start + DECLARE_ID.len()..];
+            let id = &id[..id.find('"').unwrap_or(id.len())];
+            Pubkey::from_str(id)
+                .map_err(|err| LabError::genesis(format!("Invalid feature id {id}: {err}")))
+        })
+        .collect()
+}
+
+/// Features supported by every one of `feature_sets`
+pub fn common_features(feature_sets: &[BTreeSet<Pubkey>]) -> BTreeSet<Pubkey> {
+    let mut feature_sets = feature_sets.iter();
+    let Some(first) = feature_sets.next() else {
+        return BTreeSet::new();
+    };
+    feature_sets.fold(first.clone(), |common, features| {
+        common.intersection(features).copied().collect()
+    })
+}
+
+/// Reads a deployment's feature ids from its source.
+/// Releases and commits are fetched from GitHub into the artifact cache.
+/// Git refs are read from their clone in `cluster_root_path`.
+/// Moving release channels are not git refs, so their feature sets cannot be fetched
+pub async fn fetch_feature_ids(
+    deploy_method: &DeployMethod,
+    artifact_cache: &ArtifactCache,
+    cluster_root_path: &Path,
+) -> Result<BTreeSet<Pubkey>, LabError> {
+    let (raw_url, name) = match deploy_method {
+        DeployMethod::Local(path) => {
+            let source = FEATURE_SET_SOURCES
+                .iter()
+                .map(|source| PathBuf::from(path).join(source))
+                .find(|source| source.exists())
+                .ok_or_else(|| {
+                    LabError::genesis(format!("No feature set source found in {path}"))
+                })?;
+            return read_feature_ids(&source);
+        }
+        DeployMethod::Git { remote, git_ref } => {
+            let source = match GitCheckout::for_deploy_method(deploy_method, cluster_root_path) {
+                Some(checkout) => checkout.read_first_file(&FEATURE_SET_SOURCES)?,
+                None => None,
+            };
+            let source = source.ok_or_else(|| {
+                LabError::genesis(format!(
+                    "No feature set source found at {git_ref} of {remote}"
+                ))
+            })?;
+            return parse_feature_ids(&source);
+        }
+        DeployMethod::ReleaseChannel(version) => {
+            if is_moving_release(version) {
+                return Err(LabError::config(format!(
+                    "Cannot read the feature set of release channel {version}, which is not a \
+                    version tag. Deploy a release version such as v1.18.15 to choose features"
+                )));
+            }
+            (
+                format!("https://raw.githubusercontent.com/anza-xyz/agave/{version}"),
+                version.clone(),
+            )
+        }
+        DeployMethod::Commit {
+            commit,
+            username,
+            repo_name,
+        } => (
+            format!("https://raw.githubusercontent.com/{username}/{repo_name}/{commit}"),
+            commit.clone(),
+        ),
+    };
+
+    let key = feature_set_key(&name);
+    let path = match artifact_cache.get(&key)? {
+        Some(path) => path,
+        None => {
+            let mut result = Err(LabError::download(format!(
+                "No feature set source for {name}"
+            )));
+            for source in FEATURE_SET_SOURCES {
+                result = artifact_cache
+                    .download(&key, &format!("{raw_url}/{source}"), None)
+                    .await;
+                if result.is_ok() {
+                    break;
+                }
+            }
+            result?
+        }
+    };
+    read_feature_ids(&path)
+}
+
+/// Adds the features `flags` asks to turn off to its `deactivate_features`:
+/// those outside `active_features`, and with `common_features`, those missing from any deployment.
+/// The first of `deploy_methods` creates genesis
+pub async fn resolve_deactivated_features(
+    flags: &mut GenesisFlags,
+    deploy_methods: &[&DeployMethod],
+    artifact_cache: &ArtifactCache,
+    cluster_root_path: &Path,
+) -> Result<(), LabError> {
+    if !flags.needs_feature_sets() {
+        return Ok(());
+    }
+    let Some((genesis_method, other_methods)) = deploy_methods.split_first() else {
+        return Ok(());
+    };
+    let genesis_features =
+        fetch_feature_ids(genesis_method, artifact_cache, cluster_root_path).await?;
+
+    let mut keep_active = genesis_features.clone();
+    if flags.common_features {
+        let mut feature_sets = vec![genesis_features.clone()];
+        for deploy_method in other_methods {
+            feature_sets
+                .push(fetch_feature_ids(deploy_method, artifact_cache, cluster_root_path).await?);
+        }
+        keep_active = common_features(&feature_sets);
+    }
+    if !flags.active_features.is_empty() {
+        let active_features: BTreeSet<Pubkey> = flags.active_features.iter().copied().collect();
+        keep_active = keep_active
+            .intersection(&active_features)
+            .copied()
+            .collect();
+    }
+
+    flags.deactivate_features_except(&genesis_features, &keep_active);
+    info!(
+        "Deactivating {} of {} features at genesis",
+        flags.deactivate_features.len(),
+        genesis_features.len()
+    );
+    Ok(())
+}
+
+fn read_feature_ids(path: &Path) -> Result<BTreeSet<Pubkey>, LabError> {
+    let source = fs::read_to_string(path).map_err(|err| {
+        LabError::genesis(format!("Failed to read {}", path.display())).with_source(err)
+    })?;
+    let feature_ids = parse_feature_ids(&source)?;
+    debug!("{} features in {}", feature_ids.len(), path.display());
+    Ok(feature_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tempfile::TempDir};
+
+    #[test]
+    fn test_common_features() {
+        let source = r#"
+pub mod deprecate_rewards_sysvar {
+    solana_sdk::declare_id!("GaBtBJvmS4Arjj5W1NmFcyvPjsHN38UGYDq2MDwbs9Qu");
+}
+
+pub mod pico_inflation {
+    solana_sdk::declare_id!("4RWNif6C2WCNiKVW7otP4G7dkmkHGyKQWRpuZ1pxKU5m");
+}
+"#;
+        let older = parse_feature_ids(source).unwrap();
+        assert_eq!(older.len(), 2);
+
+        let newer = parse_feature_ids(&format!(
+            "{source}
+pub mod full_inflation {{
+    solana_sdk::declare_id!(\"DT4n6ABDqs6w4bnfwrXT9rsprcPf6cdDga1egctaPkLC\");
+}}
+"
+        ))
+        .unwrap();
+        assert_eq!(newer.len(), 3);
+
+        assert_eq!(common_features(&[newer.clone(), older.clone()]), older);
+        assert_eq!(common_features(std::slice::from_ref(&newer)), newer);
+        assert!(common_features(&[]).is_empty());
+        assert!(parse_feature_ids("declare_id!(\"not-a-pubkey\")").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_moving_release_channel_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let artifact_cache = ArtifactCache::new(root.join("artifact-cache"), true).unwrap();
+        for channel in ["stable", "beta", "edge"] {
+            let err = fetch_feature_ids(
+                &DeployMethod::ReleaseChannel(channel.to_string()),
+                &artifact_cache,
+                root,
+            )
+            .await
+            .unwrap_err();
+            assert!(err.to_string().contains(channel), "{err}");
+        }
+    }
+}
@@ -1,19 +1,35 @@
 use {
-    crate::{fetch_spl, new_spinner_progress_bar, NodeType, SOLANA_RELEASE, SUN, WRITING},
+    crate::{
+        artifact_cache::ArtifactCache,
+        error::LabError,
+        genesis_programs::{default_genesis_programs, fetch_genesis_programs, GenesisProgram},
+        new_spinner_progress_bar, NodeType, SOLANA_RELEASE, SUN, WRITING,
+    },
+    base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine},
     log::*,
     rand::Rng,
+    serde::{Deserialize, Deserializer, Serialize},
     solana_core::gen_keys::GenKeys,
     solana_sdk::{
-        native_token::sol_to_lamports,
-        signature::{write_keypair_file, Keypair},
+        account::{AccountSharedData, ReadableAccount},
+        hash::{hashv, Hash},
+        native_token::{lamports_to_sol, sol_to_lamports},
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::{read_keypair_file, write_keypair_file, Keypair, Signer},
+        stake::state::StakeStateV2,
+        system_program,
     },
+    solana_stake_program::stake_state,
+    solana_vote_program::vote_state::{self, VoteState},
     std::{
-        error::Error,
+        collections::{BTreeMap, BTreeSet},
         fs::{File, OpenOptions},
-        io::{self, BufRead, BufWriter, Read, Write},
+        io::{self, BufRead, BufWriter, Write},
         path::{Path, PathBuf},
         process::{Child, Command, Stdio},
         result::Result,
+        str::FromStr,
     },
 };
 
@@ -24,35 +40,30 @@ pub const DEFAULT_INTERNAL_NODE_SOL: f64 = 100.0;
 pub const DEFAULT_BOOTSTRAP_NODE_STAKE_SOL: f64 = 10.0;
 pub const DEFAULT_BOOTSTRAP_NODE_SOL: f64 = 100.0;
 pub const DEFAULT_CLIENT_LAMPORTS_PER_SIGNATURE: u64 = 42;
+const VALIDATOR_ACCOUNTS_FILE: &str = "validator-accounts.yml";
+/// Records the keygen seed of the running cluster in the cluster data path
+pub const KEYGEN_SEED_FILE: &str = "keygen-seed";
+/// Counts the deployments that drew keys from the recorded seed
+const KEYGEN_DEPLOYMENTS_FILE: &str = "keygen-deployments";
 
-fn parse_spl_genesis_file(
-    spl_file: &PathBuf,
-    solana_root_path: &Path,
-) -> Result<Vec<String>, Box<dyn Error>> {
-    // Read entire file into a String
-    let mut file = File::open(spl_file)?;
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
-
-    let args = content
-        .split_whitespace()
-        .map(String::from)
-        .map(|arg| {
-            if arg.ends_with(".so") {
-                solana_root_path
-                    .join(&arg)
-                    .into_os_string()
-                    .into_string()
-                    .unwrap()
-            } else {
-                arg
-            }
-        })
-        .collect::<Vec<String>>();
+fn path_to_string(path: PathBuf) -> Result<String, LabError> {
+    path.into_os_string()
+        .into_string()
+        .map_err(|path| LabError::genesis(format!("Invalid Unicode data in path: {path:?}")))
+}
 
-    Ok(args)
+fn deserialize_pubkeys<'de, D>(deserializer: D) -> Result<Vec<Pubkey>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pubkey| Pubkey::from_str(pubkey).map_err(serde::de::Error::custom))
+        .collect()
 }
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
 pub struct GenesisFlags {
     pub hashes_per_tick: String,
     pub slots_per_epoch: Option<u64>,
@@ -64,6 +75,68 @@ pub struct GenesisFlags {
     pub bootstrap_validator_sol: Option<f64>,
     pub bootstrap_validator_stake_sol: Option<f64>,
     pub commission: u8,
+    /// features that start deactivated
+    #[serde(deserialize_with = "deserialize_pubkeys")]
+    pub deactivate_features: Vec<Pubkey>,
+    /// if not empty, every other feature of the genesis version starts deactivated
+    #[serde(deserialize_with = "deserialize_pubkeys")]
+    pub active_features: Vec<Pubkey>,
+    /// deactivate the features that are not supported by every deployment's version
+    pub common_features: bool,
+    /// fund and stake the first deployment's validators in genesis instead of by airdrop at startup
+    pub validators_in_genesis: bool,
+    /// the same seed and deployments always generate the same keys
+    pub keygen_seed: Option<String>,
+    /// programs loaded into genesis. None loads the default SPL programs
+    pub programs: Option<Vec<GenesisProgram>>,
+}
+
+impl Default for GenesisFlags {
+    fn default() -> Self {
+        Self {
+            hashes_per_tick: "auto".to_string(),
+            slots_per_epoch: None,
+            target_lamports_per_signature: None,
+            faucet_lamports: Some(DEFAULT_FAUCET_LAMPORTS),
+            enable_warmup_epochs: true,
+            max_genesis_archive_unpacked_size: Some(DEFAULT_MAX_GENESIS_ARCHIVE_UNPACKED_SIZE),
+            cluster_type: "development".to_string(),
+            bootstrap_validator_sol: Some(DEFAULT_BOOTSTRAP_NODE_SOL),
+            bootstrap_validator_stake_sol: Some(DEFAULT_BOOTSTRAP_NODE_STAKE_SOL),
+            commission: 100,
+            deactivate_features: vec![],
+            active_features: vec![],
+            common_features: false,
+            validators_in_genesis: false,
+            keygen_seed: None,
+            programs: None,
+        }
+    }
+}
+
+impl GenesisFlags {
+    /// Whether the features to deactivate depend on the feature sets of the deployed versions
+    pub fn needs_feature_sets(&self) -> bool {
+        !self.active_features.is_empty() || self.common_features
+    }
+
+    /// Deactivates every feature of `genesis_features` that is not in `keep_active`
+    pub fn deactivate_features_except(
+        &mut self,
+        genesis_features: &BTreeSet<Pubkey>,
+        keep_active: &BTreeSet<Pubkey>,
+    ) {
+        self.deactivate_features
+            .extend(genesis_features.difference(keep_active));
+        self.deactivate_features.sort();
+        self.deactivate_features.dedup();
+    }
+
+    pub fn genesis_programs(&self) -> Vec<GenesisProgram> {
+        self.programs
+            .clone()
+            .unwrap_or_else(default_genesis_programs)
+    }
 }
 
 impl std::fmt::Display for GenesisFlags {
@@ -80,6 +153,10 @@ impl std::fmt::Display for GenesisFlags {
              cluster_type: {}\n\
              bootstrap_validator_sol: {:?},\n\
              bootstrap_validator_stake_sol: {:?},\n\
+             deactivate_features: {},\n\
+             validators_in_genesis: {},\n\
+             keygen_seed: {:?},\n\
+             programs: {:?},\n\
              }}",
             self.hashes_per_tick,
             self.slots_per_epoch,
@@ -90,6 +167,13 @@ impl std::fmt::Display for GenesisFlags {
             self.cluster_type,
             self.bootstrap_validator_sol,
             self.bootstrap_validator_stake_sol,
+            self.deactivate_features.len(),
+            self.validators_in_genesis,
+            self.keygen_seed,
+            self.genesis_programs()
+                .iter()
+                .map(|program| &program.name)
+                .collect::<Vec<_>>(),
         )
     }
 }
@@ -118,38 +202,320 @@ fn append_client_accounts_to_file(
     Ok(())
 }
 
+/// An account in the format of solana-genesis' `--primordial-accounts-file`
+#[derive(Serialize)]
+struct PrimordialAccount {
+    balance: u64,
+    owner: String,
+    data: String,
+    executable: bool,
+}
+
+impl From<&AccountSharedData> for PrimordialAccount {
+    fn from(account: &AccountSharedData) -> Self {
+        Self {
+            balance: account.lamports(),
+            owner: account.owner().to_string(),
+            data: BASE64_STANDARD.encode(account.data()),
+            executable: account.executable(),
+        }
+    }
+}
+
+fn read_keypair(path: &Path) -> Result<Keypair, LabError> {
+    read_keypair_file(path).map_err(|err| {
+        LabError::genesis(format!("Failed to read keypair {}", path.display()))
+            .with_source(err.to_string())
+    })
+}
+
+/// A seed for runs without `--keygen-seed`, recorded the same way so they can be reproduced
+fn random_keygen_seed() -> String {
+    Hash::new(&rand::thread_rng().gen::<[u8; 32]>()).to_string()
+}
+
+/// The keygen seed of a cluster and the number of deployments that drew keys from it.
+/// Both are kept in the cluster data path, so every deployment of the cluster, across runs,
+/// gets its own key stream. The count, unlike the image tag, does not change with the build options
+pub struct KeygenSeed {
+    seed: String,
+    deployments: u64,
+    cluster_data_path: PathBuf,
+}
+
+impl KeygenSeed {
+    /// Starts the keys of a new cluster from `seed`, or a random seed.
+    /// The seed is recorded in `<cluster_data_path>/keygen-seed` and the deployment count reset
+    pub fn start(cluster_data_path: &Path, seed: Option<String>) -> Result<Self, LabError> {
+        let keygen_seed = Self {
+            seed: seed.unwrap_or_else(random_keygen_seed),
+            deployments: 0,
+            cluster_data_path: cluster_data_path.to_path_buf(),
+        };
+        let path = cluster_data_path.join(KEYGEN_SEED_FILE);
+        write_record(&path, &keygen_seed.seed)?;
+        write_record(&cluster_data_path.join(KEYGEN_DEPLOYMENTS_FILE), "0")?;
+        info!("Keygen seed recorded in {}", path.display());
+        Ok(keygen_seed)
+    }
+
+    /// Continues the keys of the running cluster after its recorded deployments.
+    /// `seed` overrides the recorded seed, which is not rewritten
+    pub fn resume(cluster_data_path: &Path, seed: Option<String>) -> Result<Self, LabError> {
+        let seed = match seed {
+            Some(seed) => seed,
+            None => match read_record(&cluster_data_path.join(KEYGEN_SEED_FILE))? {
+                Some(seed) => seed,
+                None => {
+                    warn!(
+                        "No keygen seed recorded in {}. Using a random seed",
+                        cluster_data_path.display()
+                    );
+                    random_keygen_seed()
+                }
+            },
+        };
+        let deployments_path = cluster_data_path.join(KEYGEN_DEPLOYMENTS_FILE);
+        let deployments = read_record(&deployments_path)?
+            .map(|count| {
+                count.parse().map_err(|err| {
+                    LabError::genesis(format!("Invalid count in {}", deployments_path.display()))
+                        .with_source(err)
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+        Ok(Self {
+            seed,
+            deployments,
+            cluster_data_path: cluster_data_path.to_path_buf(),
+        })
+    }
+
+    pub fn seed(&self) -> &str {
+        &self.seed
+    }
+
+    /// Seed for the keys of the next deployment. The deployment is counted in the cluster data path
+    pub fn next_deployment(&mut self) -> Result<[u8; 32], LabError> {
+        let seed = hashv(&[self.seed.as_bytes(), &self.deployments.to_le_bytes()]).to_bytes();
+        self.deployments += 1;
+        write_record(
+            &self.cluster_data_path.join(KEYGEN_DEPLOYMENTS_FILE),
+            &self.deployments.to_string(),
+        )?;
+        Ok(seed)
+    }
+}
+
+fn read_record(path: &Path) -> Result<Option<String>, LabError> {
+    match std::fs::read_to_string(path) {
+        Ok(record) => Ok(Some(record.trim().to_string())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => {
+            Err(LabError::genesis(format!("Failed to read {}", path.display())).with_source(err))
+        }
+    }
+}
+
+fn write_record(path: &Path, record: &str) -> Result<(), LabError> {
+    std::fs::write(path, format!("{record}\n")).map_err(|err| {
+        LabError::genesis(format!("Failed to write {}", path.display())).with_source(err)
+    })
+}
+
+fn write_keypair(keypair: &Keypair, outfile: &Path) -> Result<(), LabError> {
+    write_keypair_file(keypair, outfile).map_err(|err| {
+        LabError::genesis(format!("Failed to write keypair {}", outfile.display()))
+            .with_source(err.to_string())
+    })?;
+    Ok(())
+}
+
+/// `<node_type>/<account>.json` for the bootstrap and `<node_type>-<account>-<index>.json` otherwise.
+/// `account` carries the deployment tag, if any
+fn keypair_filename(node_type: &NodeType, account: &str, index: usize) -> String {
+    match node_type {
+        NodeType::Bootstrap => format!("{node_type}/{account}.json"),
+        _ => format!("{node_type}-{account}-{index}.json"),
+    }
+}
+
+fn account_types(node_type: &NodeType) -> Result<Vec<&'static str>, LabError> {
+    match node_type {
+        NodeType::Bootstrap | NodeType::Standard => {
+            Ok(vec!["identity", "stake-account", "vote-account"])
+        }
+        NodeType::RPC => {
+            Ok(vec!["identity"]) // no vote or stake account for RPC
+        }
+        NodeType::Client(_, _) => Err(LabError::genesis(
+            "Client valdiator_type in generate_accounts not allowed",
+        )),
+    }
+}
+
 pub struct Genesis {
     config_dir: PathBuf,
     key_generator: GenKeys,
     pub flags: GenesisFlags,
+    /// existing keypairs to use in place of generated ones
+    keypair_dir: Option<PathBuf>,
 }
 
 impl Genesis {
-    pub fn new(config_dir: PathBuf, flags: GenesisFlags, retain_previous_genesis: bool) -> Self {
+    pub fn new(
+        config_dir: PathBuf,
+        flags: GenesisFlags,
+        retain_previous_genesis: bool,
+        keygen_seed: Option<[u8; 32]>,
+    ) -> Result<Self, LabError> {
         // if we are deploying a heterogeneous cluster
         // all deployments after the first must retain the original genesis directory
         if !retain_previous_genesis {
+            let io_error = |err: io::Error| {
+                LabError::genesis(format!(
+                    "Failed to reset config directory {}",
+                    config_dir.display()
+                ))
+                .with_source(err)
+            };
             if config_dir.exists() {
-                std::fs::remove_dir_all(&config_dir).unwrap();
+                std::fs::remove_dir_all(&config_dir).map_err(io_error)?;
             }
-            std::fs::create_dir_all(&config_dir).unwrap();
+            std::fs::create_dir_all(&config_dir).map_err(io_error)?;
         }
 
-        let seed: [u8; 32] = rand::thread_rng().gen();
+        let seed = keygen_seed.unwrap_or_else(|| rand::thread_rng().gen());
 
-        Self {
+        Ok(Self {
             config_dir,
             key_generator: GenKeys::new(seed),
             flags,
+            keypair_dir: None,
+        })
+    }
+
+    /// Uses the keypairs in `keypair_dir` in place of generated ones.
+    /// The directory holds `faucet.json`, `bootstrap-validator/{identity,vote-account,stake-account}.json`,
+    /// `validator-{identity,vote-account,stake-account}-<index>.json`, and `rpc-node-identity-<index>.json`.
+    /// Node types without any keypairs in the directory are generated
+    pub fn set_keypair_dir(&mut self, keypair_dir: PathBuf) {
+        self.keypair_dir = Some(keypair_dir);
+    }
+
+    /// How many consecutive nodes of `node_type`, from index 0, have all their keypairs in the keypair dir
+    fn imported_account_count(&self, node_type: &NodeType) -> Result<usize, LabError> {
+        let Some(keypair_dir) = &self.keypair_dir else {
+            return Ok(0);
+        };
+        let account_types = account_types(node_type)?;
+        let max_count = if *node_type == NodeType::Bootstrap {
+            1
+        } else {
+            usize::MAX
+        };
+        Ok((0..max_count)
+            .take_while(|index| {
+                account_types.iter().all(|account| {
+                    keypair_dir
+                        .join(keypair_filename(node_type, account, *index))
+                        .exists()
+                })
+            })
+            .count())
+    }
+
+    /// Checks the keypair dir covers the requested node counts for the node types it has keypairs for
+    pub fn check_imported_keypairs(
+        &self,
+        node_counts: &[(NodeType, usize)],
+    ) -> Result<(), LabError> {
+        for (node_type, count) in node_counts {
+            let imported = self.imported_account_count(node_type)?;
+            if imported > 0 && imported < *count {
+                return Err(LabError::genesis(format!(
+                    "Keypair directory has keypairs for {imported} {node_type}(s) but {count} were requested"
+                )));
+            }
         }
+        Ok(())
     }
 
-    pub fn generate_faucet(&mut self) -> Result<(), Box<dyn Error>> {
-        info!("generating faucet keypair");
+    /// Copies the keypair dir's keypairs for the first `count` nodes of `node_type` into the config dir,
+    /// named as if they were generated. Returns false if the keypair dir has none for `node_type`
+    fn import_accounts(
+        &self,
+        node_type: &NodeType,
+        count: usize,
+        deployment_tag: Option<&str>,
+    ) -> Result<bool, LabError> {
+        let Some(keypair_dir) = &self.keypair_dir else {
+            return Ok(false);
+        };
+        if self.imported_account_count(node_type)? == 0 {
+            return Ok(false);
+        }
+        self.check_imported_keypairs(&[(*node_type, count)])?;
+
+        for index in 0..count {
+            for account in account_types(node_type)? {
+                let source = keypair_dir.join(keypair_filename(node_type, account, index));
+                let account = match deployment_tag {
+                    Some(tag) => format!("{account}-{tag}"),
+                    None => account.to_string(),
+                };
+                let outfile = self
+                    .config_dir
+                    .join(keypair_filename(node_type, &account, index));
+                write_keypair(&read_keypair(&source)?, &outfile)?;
+            }
+        }
+        info!(
+            "Imported {count} {node_type} account(s) from {}",
+            keypair_dir.display()
+        );
+        Ok(true)
+    }
+
+    pub fn generate_faucet(&mut self) -> Result<(), LabError> {
         let outfile = self.config_dir.join("faucet.json");
+        if let Some(keypair_dir) = &self.keypair_dir {
+            let faucet = keypair_dir.join("faucet.json");
+            if faucet.exists() {
+                info!("importing faucet keypair from {}", keypair_dir.display());
+                return write_keypair(&read_keypair(&faucet)?, &outfile);
+            }
+        }
+
+        info!("generating faucet keypair");
         let keypair = self.key_generator.gen_keypair();
 
-        write_keypair_file(&keypair, outfile)?;
+        write_keypair(&keypair, &outfile)
+    }
+
+    /// Validators are airdropped their funds and stake from the faucet when they start,
+    /// so the faucet must hold enough lamports for every validator in the deployment
+    pub fn check_faucet_funds(
+        &self,
+        validator_stakes: &[f64],
+        internal_node_sol: f64,
+    ) -> Result<(), LabError> {
+        let required: u64 = validator_stakes
+            .iter()
+            .map(|stake| sol_to_lamports(internal_node_sol + stake))
+            .sum();
+        let faucet_lamports = self
+            .flags
+            .faucet_lamports
+            .unwrap_or(DEFAULT_FAUCET_LAMPORTS);
+        if required > faucet_lamports {
+            return Err(LabError::genesis(format!(
+                "Faucet holds {} SOL but validators need {} SOL. Increase --faucet-lamports",
+                lamports_to_sol(faucet_lamports),
+                lamports_to_sol(required)
+            )));
+        }
         Ok(())
     }
 
@@ -158,21 +524,25 @@ impl Genesis {
         node_type: NodeType,
         number_of_accounts: usize,
         deployment_tag: Option<&str>,
-    ) -> Result<(), Box<dyn Error>> {
-        info!("generating {number_of_accounts} {node_type} accounts...");
+    ) -> Result<(), LabError> {
+        if self.import_accounts(&node_type, number_of_accounts, deployment_tag)? {
+            return Ok(());
+        }
+        self.generate_accounts_from(node_type, 0, number_of_accounts, deployment_tag)
+    }
 
-        let account_types = match node_type {
-            NodeType::Bootstrap | NodeType::Standard => {
-                vec!["identity", "stake-account", "vote-account"]
-            }
-            NodeType::RPC => {
-                vec!["identity"] // no vote or stake account for RPC
-            }
-            NodeType::Client(_, _) => {
-                return Err("Client valdiator_type in generate_accounts not allowed".into())
-            }
-        };
+    /// Generates accounts for indices `first_index..first_index + number_of_accounts`,
+    /// leaving the keypairs of lower indices untouched. Used to add nodes to a running deployment
+    pub fn generate_accounts_from(
+        &mut self,
+        node_type: NodeType,
+        first_index: usize,
+        number_of_accounts: usize,
+        deployment_tag: Option<&str>,
+    ) -> Result<(), LabError> {
+        info!("generating {number_of_accounts} {node_type} accounts...");
 
+        let account_types = account_types(&node_type)?;
         let account_types: Vec<String> = if let Some(tag) = deployment_tag {
             account_types
                 .into_iter()
@@ -190,7 +560,7 @@ impl Genesis {
             .key_generator
             .gen_n_keypairs(total_accounts_to_generate as u64);
 
-        self.write_accounts_to_file(&node_type, &account_types, &keypairs)?;
+        self.write_accounts_to_file(&node_type, &account_types, &keypairs, first_index)?;
 
         Ok(())
     }
@@ -200,9 +570,10 @@ impl Genesis {
         node_type: &NodeType,
         account_types: &[String],
         keypairs: &[Keypair],
-    ) -> Result<(), Box<dyn Error>> {
+        first_index: usize,
+    ) -> Result<(), LabError> {
         for (i, keypair) in keypairs.iter().enumerate() {
-            let account_index = i / account_types.len();
+            let account_index = first_index + i / account_types.len();
             let account = &account_types[i % account_types.len()];
             let filename = match node_type {
                 NodeType::Bootstrap => {
@@ -215,8 +586,79 @@ impl Genesis {
             };
 
             let outfile = self.config_dir.join(&filename);
-            write_keypair_file(keypair, outfile)?;
+            write_keypair(keypair, &outfile)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the identity, vote, and stake accounts of `validator_stakes.len()` validators
+    /// to a primordial accounts file so they hold their funds and fully active stake from slot 0.
+    /// The identity is the vote authority, withdrawer, and stake authority, as with an airdrop
+    pub fn write_validator_accounts(
+        &self,
+        deployment_tag: &str,
+        validator_stakes: &[f64],
+        internal_node_sol: f64,
+        commission: u8,
+    ) -> Result<(), LabError> {
+        let rent = Rent::default();
+        let vote_lamports = rent.minimum_balance(VoteState::size_of());
+        let stake_reserve = rent.minimum_balance(StakeStateV2::size_of());
+
+        let mut accounts = BTreeMap::new();
+        for (index, stake) in validator_stakes.iter().enumerate() {
+            let keypair_path = |account: &str| {
+                self.config_dir.join(format!(
+                    "{}-{account}-{deployment_tag}-{index}.json",
+                    NodeType::Standard
+                ))
+            };
+            let identity = read_keypair(&keypair_path("identity"))?.pubkey();
+            let vote = read_keypair(&keypair_path("vote-account"))?.pubkey();
+            let stake_account = read_keypair(&keypair_path("stake-account"))?.pubkey();
+
+            let identity_account = AccountSharedData::new(
+                sol_to_lamports(internal_node_sol),
+                0,
+                &system_program::id(),
+            );
+            let vote_account = vote_state::create_account_with_authorized(
+                &identity,
+                &identity,
+                &identity,
+                commission,
+                vote_lamports,
+            );
+            let stake_account_data = stake_state::create_account(
+                &identity,
+                &vote,
+                &vote_account,
+                &rent,
+                sol_to_lamports(*stake) + stake_reserve,
+            );
+
+            accounts.insert(
+                identity.to_string(),
+                PrimordialAccount::from(&identity_account),
+            );
+            accounts.insert(vote.to_string(), PrimordialAccount::from(&vote_account));
+            accounts.insert(
+                stake_account.to_string(),
+                PrimordialAccount::from(&stake_account_data),
+            );
         }
+
+        let path = self.config_dir.join(VALIDATOR_ACCOUNTS_FILE);
+        let write_failed = |err: String| {
+            LabError::genesis(format!("Failed to write {}", path.display())).with_source(err)
+        };
+        let file = File::create(&path).map_err(|err| write_failed(err.to_string()))?;
+        serde_yaml::to_writer(file, &accounts).map_err(|err| write_failed(err.to_string()))?;
+        info!(
+            "Wrote {} validator accounts to {}",
+            validator_stakes.len(),
+            path.display()
+        );
         Ok(())
     }
 
@@ -227,7 +669,7 @@ impl Genesis {
         target_lamports_per_signature: u64,
         config_dir: &Path,
         solana_root_path: &Path,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), LabError> {
         if number_of_clients == 0 {
             return Ok(());
         }
@@ -251,15 +693,29 @@ impl Genesis {
             .collect();
 
         for child in children? {
-            let output = child.wait_with_output()?;
+            let output = child.wait_with_output().map_err(|err| {
+                LabError::genesis("Failed to wait for solana-bench-tps").with_source(err)
+            })?;
             if !output.status.success() {
-                return Err(String::from_utf8_lossy(&output.stderr).into());
+                return Err(LabError::genesis(format!(
+                    "Failed to create client accounts. err: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                )));
             }
         }
 
         for i in 0..number_of_clients {
             let account_path = config_dir.join(format!("bench-tps-{i}.yml"));
-            append_client_accounts_to_file(&account_path, &client_accounts_file)?;
+            append_client_accounts_to_file(&account_path, &client_accounts_file).map_err(
+                |err| {
+                    LabError::genesis(format!(
+                        "Failed to append {} to {}",
+                        account_path.display(),
+                        client_accounts_file.display()
+                    ))
+                    .with_source(err)
+                },
+            )?;
         }
         progress_bar.finish_and_clear();
         info!("client-accounts.yml creation for genesis complete");
@@ -273,18 +729,13 @@ impl Genesis {
         target_lamports_per_signature: u64,
         bench_tps_args: &[String],
         solana_root_path: &Path,
-    ) -> Result<Child, Box<dyn Error>> {
+    ) -> Result<Child, LabError> {
         info!("client account: {client_index}");
         let mut args = Vec::new();
         let account_path = config_dir.join(format!("bench-tps-{client_index}.yml"));
         debug!("account path: {account_path:?}");
         args.push("--write-client-keys".to_string());
-        args.push(account_path.into_os_string().into_string().map_err(|err| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Invalid Unicode data in path: {:?}", err),
-            )
-        })?);
+        args.push(path_to_string(account_path)?);
         args.push("--target-lamports-per-signature".to_string());
         args.push(target_lamports_per_signature.to_string());
 
@@ -298,16 +749,20 @@ impl Genesis {
             debug!("{arg}");
         }
 
-        let child = Command::new(executable_path)
+        let child = Command::new(&executable_path)
             .args(args)
             .stdout(Stdio::null())
             .stderr(Stdio::piped())
-            .spawn()?;
+            .spawn()
+            .map_err(|err| {
+                LabError::genesis(format!("Failed to execute {}", executable_path.display()))
+                    .with_source(err)
+            })?;
 
         Ok(child)
     }
 
-    fn setup_genesis_flags(&self) -> Result<Vec<String>, Box<dyn Error>> {
+    fn setup_genesis_flags(&self) -> Result<Vec<String>, LabError> {
         let mut args = vec![
             "--bootstrap-validator-lamports".to_string(),
             sol_to_lamports(
@@ -336,29 +791,11 @@ impl Genesis {
                 .unwrap_or(DEFAULT_FAUCET_LAMPORTS)
                 .to_string(),
             "--faucet-pubkey".to_string(),
-            self.config_dir
-                .join("faucet.json")
-                .into_os_string()
-                .into_string()
-                .map_err(|err| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        format!("Invalid Unicode data in path: {:?}", err),
-                    )
-                })?,
+            path_to_string(self.config_dir.join("faucet.json"))?,
             "--cluster-type".to_string(),
             self.flags.cluster_type.to_string(),
             "--ledger".to_string(),
-            self.config_dir
-                .join("bootstrap-validator")
-                .into_os_string()
-                .into_string()
-                .map_err(|err| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        format!("Invalid Unicode data in path: {:?}", err),
-                    )
-                })?,
+            path_to_string(self.config_dir.join("bootstrap-validator"))?,
             "--vote-commission-percentage".to_string(),
             self.flags.commission.to_string(),
         ];
@@ -369,12 +806,10 @@ impl Genesis {
 
         args.push("--bootstrap-validator".to_string());
         for account_type in ["identity", "vote-account", "stake-account"].iter() {
-            let path = self
-                .config_dir
-                .join(format!("bootstrap-validator/{account_type}.json"))
-                .into_os_string()
-                .into_string()
-                .map_err(|_| "Failed to convert path to string")?;
+            let path = path_to_string(
+                self.config_dir
+                    .join(format!("bootstrap-validator/{account_type}.json")),
+            )?;
             args.push(path);
         }
 
@@ -388,27 +823,44 @@ impl Genesis {
             args.push(lamports_per_signature.to_string());
         }
 
+        for feature in &self.flags.deactivate_features {
+            args.push("--deactivate-feature".to_string());
+            args.push(feature.to_string());
+        }
+
+        let validator_accounts = self.config_dir.join(VALIDATOR_ACCOUNTS_FILE);
+        if validator_accounts.exists() {
+            args.push("--primordial-accounts-file".to_string());
+            args.push(path_to_string(validator_accounts)?);
+        }
+
         Ok(args)
     }
 
-    pub async fn setup_spl_args(
+    pub async fn setup_program_args(
         &self,
         solana_root_path: &Path,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
-        fetch_spl(solana_root_path).await?;
-
-        let spl_file = solana_root_path.join("spl-genesis-args.sh");
-        parse_spl_genesis_file(&spl_file, solana_root_path)
+        artifact_cache: &ArtifactCache,
+    ) -> Result<Vec<String>, LabError> {
+        fetch_genesis_programs(
+            &self.flags.genesis_programs(),
+            solana_root_path,
+            artifact_cache,
+        )
+        .await
     }
 
     pub async fn generate(
         &mut self,
         solana_root_path: &Path,
         exec_path: &Path,
-    ) -> Result<(), Box<dyn Error>> {
+        artifact_cache: &ArtifactCache,
+    ) -> Result<(), LabError> {
         let mut args = self.setup_genesis_flags()?;
-        let mut spl_args = self.setup_spl_args(solana_root_path).await?;
-        args.append(&mut spl_args);
+        let mut program_args = self
+            .setup_program_args(solana_root_path, artifact_cache)
+            .await?;
+        args.append(&mut program_args);
 
         let progress_bar = new_spinner_progress_bar();
         progress_bar.set_message(format!("{SUN}Building Genesis..."));
@@ -418,22 +870,205 @@ impl Genesis {
             debug!("{arg}");
         }
         let executable_path = exec_path.join("solana-genesis");
-        let output = Command::new(executable_path)
+        let output = Command::new(&executable_path)
             .args(&args)
             .output()
-            .expect("Failed to execute solana-genesis");
+            .map_err(|err| {
+                LabError::genesis(format!("Failed to execute {}", executable_path.display()))
+                    .with_source(err)
+            });
 
         progress_bar.finish_and_clear();
 
+        let output = output?;
         if !output.status.success() {
-            return Err(format!(
-                "Failed to create genesis. err: {:?}",
-                String::from_utf8(output.stderr)
-            )
-            .into());
+            return Err(LabError::genesis(format!(
+                "Failed to create genesis. err: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
         }
         info!("Genesis build complete");
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_sdk::account::Account, tempfile::TempDir};
+
+    const TAG: &str = "v1-18-20";
+
+    fn pubkey_of(path: PathBuf) -> Pubkey {
+        read_keypair(&path).unwrap().pubkey()
+    }
+
+    #[test]
+    fn test_write_validator_accounts() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path();
+        let mut genesis = Genesis::new(
+            config_dir.to_path_buf(),
+            GenesisFlags::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        genesis
+            .generate_accounts(NodeType::Standard, 2, Some(TAG))
+            .unwrap();
+        let stakes = [10.0, 25.5];
+        genesis
+            .write_validator_accounts(TAG, &stakes, 100.0, 5)
+            .unwrap();
+
+        let file = File::open(config_dir.join(VALIDATOR_ACCOUNTS_FILE)).unwrap();
+        let accounts: BTreeMap<String, BTreeMap<String, serde_yaml::Value>> =
+            serde_yaml::from_reader(file).unwrap();
+        assert_eq!(accounts.len(), 3 * stakes.len());
+        let account = |pubkey: &Pubkey| {
+            let fields = &accounts[&pubkey.to_string()];
+            AccountSharedData::from(Account {
+                lamports: fields["balance"].as_u64().unwrap(),
+                data: BASE64_STANDARD
+                    .decode(fields["data"].as_str().unwrap())
+                    .unwrap(),
+                owner: Pubkey::from_str(fields["owner"].as_str().unwrap()).unwrap(),
+                executable: false,
+                rent_epoch: 0,
+            })
+        };
+
+        for (index, stake) in stakes.iter().enumerate() {
+            let keypair = |account: &str| {
+                pubkey_of(config_dir.join(format!("validator-{account}-{TAG}-{index}.json")))
+            };
+            let (identity, vote, stake_account) = (
+                keypair("identity"),
+                keypair("vote-account"),
+                keypair("stake-account"),
+            );
+
+            let identity_account = account(&identity);
+            assert_eq!(identity_account.lamports(), sol_to_lamports(100.0));
+            assert_eq!(identity_account.owner(), &system_program::id());
+
+            let vote_account = account(&vote);
+            assert_eq!(vote_account.owner(), &solana_vote_program::id());
+            let vote_state = VoteState::deserialize(vote_account.data()).unwrap();
+            assert_eq!(vote_state.node_pubkey, identity);
+            assert_eq!(vote_state.authorized_withdrawer, identity);
+            assert_eq!(vote_state.commission, 5);
+
+            let delegation = stake_state::delegation_from(&account(&stake_account)).unwrap();
+            assert_eq!(delegation.voter_pubkey, vote);
+            assert_eq!(delegation.stake, sol_to_lamports(*stake));
+            let authorized = stake_state::authorized_from(&account(&stake_account)).unwrap();
+            assert_eq!(authorized.staker, identity);
+            assert_eq!(authorized.withdrawer, identity);
+        }
+    }
+
+    #[test]
+    fn test_import_keypairs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let keypair_dir = root.join("keypairs");
+        std::fs::create_dir_all(&keypair_dir).unwrap();
+        let faucet = Keypair::new();
+        write_keypair(&faucet, &keypair_dir.join("faucet.json")).unwrap();
+        for index in 0..2 {
+            for account in account_types(&NodeType::Standard).unwrap() {
+                let path = keypair_dir.join(keypair_filename(&NodeType::Standard, account, index));
+                write_keypair(&Keypair::new(), &path).unwrap();
+            }
+        }
+
+        let config_dir = root.join("config-k8s");
+        let mut genesis = Genesis::new(
+            config_dir.to_path_buf(),
+            GenesisFlags::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        genesis.set_keypair_dir(keypair_dir.clone());
+        assert!(genesis
+            .check_imported_keypairs(&[(NodeType::Standard, 2), (NodeType::RPC, 1)])
+            .is_ok());
+        assert!(genesis
+            .check_imported_keypairs(&[(NodeType::Standard, 3)])
+            .is_err());
+
+        genesis.generate_faucet().unwrap();
+        genesis
+            .generate_accounts(NodeType::Standard, 2, Some(TAG))
+            .unwrap();
+        genesis
+            .generate_accounts(NodeType::RPC, 1, Some(TAG))
+            .unwrap();
+
+        assert_eq!(pubkey_of(config_dir.join("faucet.json")), faucet.pubkey());
+        assert_eq!(
+            pubkey_of(config_dir.join(format!("validator-vote-account-{TAG}-1.json"))),
+            pubkey_of(keypair_dir.join("validator-vote-account-1.json"))
+        );
+        // rpc nodes are not in the keypair dir, so they are generated
+        assert!(config_dir
+            .join(format!("rpc-node-identity-{TAG}-0.json"))
+            .exists());
+    }
+
+    #[test]
+    fn test_keygen_seed_reproduces_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let generate = |cluster: &str, keygen_seed: &mut KeygenSeed, bootstrap: bool| {
+            let config_dir = root.join(cluster).join("config-k8s");
+            let mut genesis = Genesis::new(
+                config_dir.clone(),
+                GenesisFlags::default(),
+                !bootstrap,
+                Some(keygen_seed.next_deployment().unwrap()),
+            )
+            .unwrap();
+            let mut files = vec![];
+            if bootstrap {
+                genesis.generate_faucet().unwrap();
+                genesis
+                    .generate_accounts(NodeType::Bootstrap, 1, None)
+                    .unwrap();
+                files.push("faucet.json".to_string());
+                files.push("bootstrap-validator/identity.json".to_string());
+            }
+            genesis
+                .generate_accounts(NodeType::Standard, 2, Some(TAG))
+                .unwrap();
+            files.push(format!("validator-identity-{TAG}-0.json"));
+            files.push(format!("validator-vote-account-{TAG}-1.json"));
+            files
+                .into_iter()
+                .map(|file| pubkey_of(config_dir.join(file)))
+                .collect::<Vec<_>>()
+        };
+        let start = |cluster: &str| {
+            let cluster_data_path = root.join(cluster);
+            std::fs::create_dir_all(&cluster_data_path).unwrap();
+            KeygenSeed::start(&cluster_data_path, Some("lab".to_string())).unwrap()
+        };
+
+        let keys = generate("first", &mut start("first"), true);
+        assert_eq!(generate("second", &mut start("second"), true), keys);
+
+        // a --no-bootstrap run continues the recorded seed without repeating its keys
+        let mut resumed = KeygenSeed::resume(&root.join("first"), None).unwrap();
+        assert_eq!(resumed.seed(), "lab");
+        let next = generate("first", &mut resumed, false);
+        assert!(next.iter().all(|key| !keys.contains(key)));
+        let mut resumed = KeygenSeed::resume(&root.join("first"), None).unwrap();
+        let last = generate("first", &mut resumed, false);
+        assert!(last
+            .iter()
+            .all(|key| !keys.contains(key) && !next.contains(key)));
+    }
+}
@@ -0,0 +1,295 @@
+use {
+    crate::{
+        artifact_cache::{program_key, sha256_file, ArtifactCache},
+        error::LabError,
+    },
+    serde::Deserialize,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+};
+
+pub const UPGRADEABLE_LOADER: &str = "BPFLoaderUpgradeab1e11111111111111111111111";
+const BPF_LOADER_1: &str = "BPFLoader1111111111111111111111111111111111";
+const BPF_LOADER_2: &str = "BPFLoader2111111111111111111111111111111111";
+/// Records the program args passed to solana-genesis
+const GENESIS_PROGRAM_ARGS_FILE: &str = "spl-genesis-args.sh";
+
+/// A program loaded into genesis. Parsed from a `[[programs]]` entry of `--genesis-programs-file`
+/// or `[genesis]` in --config, or from `--genesis-program key=value,key=value,...` with the same keys
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GenesisProgram {
+    /// keys the download in the artifact cache, so pin a new version under a new name
+    pub name: String,
+    pub address: String,
+    #[serde(default = "default_loader")]
+    pub loader: String,
+    /// pubkey or keypair file allowed to upgrade the program. upgradeable loader only, defaults to `none`
+    pub upgrade_authority: Option<String>,
+    pub url: Option<String>,
+    /// local .so, used in place of `url`
+    pub path: Option<PathBuf>,
+    /// expected SHA-256 of the .so, as hex
+    pub sha256: Option<String>,
+}
+
+fn default_loader() -> String {
+    UPGRADEABLE_LOADER.to_string()
+}
+
+fn validate_pubkey(value: &str, field: &str) -> Result<(), String> {
+    Pubkey::from_str(value)
+        .map(|_| ())
+        .map_err(|err| format!("Invalid {field} '{value}': {err}"))
+}
+
+/// SPL programs released by solana-program-library, saved as `spl_<name>-<version>.so`
+fn spl_program(name: &str, version: &str, address: &str, loader: &str) -> GenesisProgram {
+    let name_with_underscores = name.replace('-', "_");
+    GenesisProgram {
+        name: format!("spl_{name_with_underscores}-{version}"),
+        address: address.to_string(),
+        loader: loader.to_string(),
+        upgrade_authority: None,
+        url: Some(format!(
+            "https://github.com/solana-labs/solana-program-library/releases/download/{name}-v{version}/spl_{name_with_underscores}.so"
+        )),
+        path: None,
+        sha256: None,
+    }
+}
+
+/// Programs loaded when none are configured
+pub fn default_genesis_programs() -> Vec<GenesisProgram> {
+    vec![
+        spl_program(
+            "token",
+            "3.5.0",
+            "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+            BPF_LOADER_2,
+        ),
+        spl_program(
+            "token-2022",
+            "1.0.0",
+            "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+            UPGRADEABLE_LOADER,
+        ),
+        spl_program(
+            "memo",
+            "1.0.0",
+            "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo",
+            BPF_LOADER_1,
+        ),
+        spl_program(
+            "associated-token-account",
+            "1.1.2",
+            "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL",
+            BPF_LOADER_2,
+        ),
+        spl_program(
+            "feature-proposal",
+            "1.0.0",
+            "Feat1YXHhH6t1juaWF74WLcfv4XoNocjXA6sPWHNgAse",
+            BPF_LOADER_2,
+        ),
+    ]
+}
+
+impl GenesisProgram {
+    pub fn validate(&self) -> Result<(), String> {
+        validate_pubkey(&self.address, "program address")?;
+        validate_pubkey(&self.loader, "program loader")?;
+        if self.url.is_some() == self.path.is_some() {
+            return Err(format!(
+                "Genesis program {} needs exactly one of url or path",
+                self.name
+            ));
+        }
+        if self.upgrade_authority.is_some() && self.loader != UPGRADEABLE_LOADER {
+            return Err(format!(
+                "Genesis program {} has an upgrade authority but is not owned by the upgradeable loader",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Path of the program's .so, from the artifact cache for `url`
+    async fn fetch(&self, artifact_cache: &ArtifactCache) -> Result<PathBuf, LabError> {
+        let Some(url) = &self.url else {
+            let path = self.path.clone().unwrap_or_default();
+            if !path.exists() {
+                return Err(LabError::genesis(format!(
+                    "Genesis program {} not found at {}",
+                    self.name,
+                    path.display()
+                )));
+            }
+            if let Some(expected) = &self.sha256 {
+                let digest = sha256_file(&path)?;
+                if !expected.eq_ignore_ascii_case(&digest) {
+                    return Err(LabError::genesis(format!(
+                        "Checksum mismatch for genesis program {} at {}: expected sha256 {expected} but found {digest}",
+                        self.name,
+                        path.display()
+                    )));
+                }
+            }
+            return Ok(path);
+        };
+
+        artifact_cache
+            .fetch(&program_key(&self.name), url, self.sha256.as_deref())
+            .await
+    }
+
+    fn genesis_args(&self, so_path: &Path) -> Result<Vec<String>, LabError> {
+        let so_path = so_path
+            .to_str()
+            .ok_or_else(|| LabError::genesis(format!("Invalid Unicode data in path: {so_path:?}")))?
+            .to_string();
+        Ok(if self.loader == UPGRADEABLE_LOADER {
+            vec![
+                "--upgradeable-program".to_string(),
+                self.address.clone(),
+                self.loader.clone(),
+                so_path,
+                self.upgrade_authority
+                    .clone()
+                    .unwrap_or_else(|| "none".to_string()),
+            ]
+        } else {
+            vec![
+                "--bpf-program".to_string(),
+                self.address.clone(),
+                self.loader.clone(),
+                so_path,
+            ]
+        })
+    }
+}
+
+impl FromStr for GenesisProgram {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut program = GenesisProgram {
+            name: String::new(),
+            address: String::new(),
+            loader: default_loader(),
+            upgrade_authority: None,
+            url: None,
+            path: None,
+            sha256: None,
+        };
+        for pair in s.split(',') {
+            let (key, value) = pair.split_once('=').ok_or_else(|| {
+                format!("Invalid genesis program '{s}'. Expected key=value pairs separated by ','")
+            })?;
+            let value = value.to_string();
+            match key {
+                "name" => program.name = value,
+                "address" => program.address = value,
+                "loader" => program.loader = value,
+                "upgrade-authority" => program.upgrade_authority = Some(value),
+                "url" => program.url = Some(value),
+                "path" => program.path = Some(PathBuf::from(value)),
+                "sha256" => program.sha256 = Some(value),
+                _ => {
+                    return Err(format!(
+                    "Unknown genesis program key '{key}'. Expected one of: name, address, loader, \
+                        upgrade-authority, url, path, sha256"
+                ))
+                }
+            }
+        }
+        if program.name.is_empty() {
+            return Err(format!("Genesis program '{s}' needs a name"));
+        }
+        program.validate()?;
+        Ok(program)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GenesisProgramsFile {
+    programs: Vec<GenesisProgram>,
+}
+
+/// Reads the `[[programs]]` of a TOML file
+pub fn load_genesis_programs(path: &Path) -> Result<Vec<GenesisProgram>, LabError> {
+    let contents = fs::read_to_string(path).map_err(|err| {
+        LabError::config(format!("Failed to read {}", path.display())).with_source(err)
+    })?;
+    let file: GenesisProgramsFile = toml::from_str(&contents).map_err(|err| {
+        LabError::config(format!("Failed to parse {}", path.display())).with_source(err)
+    })?;
+    Ok(file.programs)
+}
+
+/// Fetches `programs` and returns their solana-genesis args,
+/// also recorded in `<solana_root_path>/spl-genesis-args.sh`
+pub async fn fetch_genesis_programs(
+    programs: &[GenesisProgram],
+    solana_root_path: &Path,
+    artifact_cache: &ArtifactCache,
+) -> Result<Vec<String>, LabError> {
+    let mut genesis_args = vec![];
+    for program in programs {
+        let so_path = program.fetch(artifact_cache).await?;
+        genesis_args.extend(program.genesis_args(&so_path)?);
+    }
+
+    let args_file = solana_root_path.join(GENESIS_PROGRAM_ARGS_FILE);
+    fs::write(&args_file, format!("{}\n", genesis_args.join(" "))).map_err(|err| {
+        LabError::genesis(format!("Failed to write {}", args_file.display())).with_source(err)
+    })?;
+
+    Ok(genesis_args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_genesis_program() {
+        let program = GenesisProgram::from_str(
+            "name=my-program,address=TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb,path=/tmp/my_program.so",
+        )
+        .unwrap();
+        assert_eq!(program.loader, UPGRADEABLE_LOADER);
+        assert_eq!(
+            program
+                .genesis_args(program.path.as_ref().unwrap())
+                .unwrap(),
+            vec![
+                "--upgradeable-program",
+                "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+                UPGRADEABLE_LOADER,
+                "/tmp/my_program.so",
+                "none",
+            ]
+        );
+
+        // needs exactly one of url or path
+        assert!(GenesisProgram::from_str(
+            "name=p,address=TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"
+        )
+        .is_err());
+        // only upgradeable programs have an upgrade authority
+        assert!(GenesisProgram::from_str(&format!(
+            "name=p,address=TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb,loader={BPF_LOADER_2},\
+            url=https://example.com/p.so,upgrade-authority=none"
+        ))
+        .is_err());
+        assert!(default_genesis_programs()
+            .iter()
+            .all(|program| program.validate().is_ok()));
+    }
+}
@@ -0,0 +1,445 @@
+use {
+    crate::{docker::DockerImage, error::LabError, link_or_copy_dir, SOLANA_RELEASE},
+    log::*,
+    serde::Deserialize,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+        process::{Child, Command, Output, Stdio},
+    },
+    strum_macros::{EnumString, IntoStaticStr, VariantNames},
+};
+
+/// The directories of the build context, the cluster data path, that Dockerfiles copy from.
+/// Everything else, e.g. the artifact and build caches, is left out of the context
+pub const BUILD_CONTEXT_PATHS: [&str; 3] = ["docker-build", SOLANA_RELEASE, "config-k8s"];
+
+#[derive(Clone, Copy, Debug, PartialEq, EnumString, IntoStaticStr, VariantNames, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ImageBuilderKind {
+    Docker,
+    Podman,
+    Buildah,
+    /// write each image's build context for kaniko to build and push
+    Kaniko,
+}
+
+/// Registry the `local-registry` image distribution pushes to, e.g. one started with
+/// `docker run -d -p 5000:5000 registry:2`
+pub const LOCAL_REGISTRY: &str = "localhost:5000";
+
+/// How built images reach the cluster's nodes
+#[derive(Clone, Copy, Debug, PartialEq, EnumString, IntoStaticStr, VariantNames, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageDistribution {
+    /// push to `--registry`
+    Registry,
+    /// push to a registry on localhost:5000
+    LocalRegistry,
+    /// load images straight into the nodes of a kind cluster
+    Kind,
+    /// import images straight into the nodes of a k3d cluster
+    K3d,
+}
+
+impl ImageDistribution {
+    /// Side-loaded images are only on the nodes, so pods must not pull them.
+    /// Registries are always pulled from: the `-ledger` image changes with genesis under the same tag
+    pub fn image_pull_policy(&self) -> &'static str {
+        match self {
+            ImageDistribution::Registry | ImageDistribution::LocalRegistry => "Always",
+            ImageDistribution::Kind | ImageDistribution::K3d => "IfNotPresent",
+        }
+    }
+
+    pub fn is_side_load(&self) -> bool {
+        matches!(self, ImageDistribution::Kind | ImageDistribution::K3d)
+    }
+
+    /// The cluster name the tool uses when none is given
+    pub fn default_cluster_name(&self) -> Option<&'static str> {
+        match self {
+            ImageDistribution::Kind => Some("kind"),
+            ImageDistribution::K3d => Some("k3s-default"),
+            _ => None,
+        }
+    }
+
+    /// Copies `image` from the local docker daemon into the nodes of `cluster`
+    pub fn side_load(&self, image: &DockerImage, cluster: &str) -> Result<(), LabError> {
+        let mut command = match self {
+            ImageDistribution::Kind => {
+                let mut command = Command::new("kind");
+                command.args(["load", "docker-image"]);
+                command.arg(image.to_string()).args(["--name", cluster]);
+                command
+            }
+            ImageDistribution::K3d => {
+                let mut command = Command::new("k3d");
+                command.args(["image", "import"]);
+                command.arg(image.to_string()).args(["--cluster", cluster]);
+                command
+            }
+            _ => {
+                return Err(LabError::docker(format!(
+                    "{self:?} does not side-load images"
+                )))
+            }
+        };
+        command.stdout(Stdio::null()).stderr(Stdio::piped());
+        let description = describe(&command);
+        debug!("side-load command: {description}");
+        let output = command.output().map_err(|err| {
+            LabError::docker(format!("Failed to run `{description}`")).with_source(err)
+        })?;
+        check_output(&description, output)
+    }
+}
+
+/// Builds and pushes container images
+pub trait ImageBuilder {
+    /// Builds `image` from `dockerfile`, which copies from `context`
+    fn build(&self, image: &DockerImage, dockerfile: &Path, context: &Path)
+        -> Result<(), LabError>;
+
+    /// Starts pushing `image` to its registry. None if the builder pushes as it builds
+    fn push(&self, image: &DockerImage) -> Result<Option<Child>, LabError>;
+
+    /// Returns true if the registry already holds `image` with the same content as the local
+    /// build, so pushing it again can be skipped
+    fn is_in_registry(&self, _image: &DockerImage) -> Result<bool, LabError> {
+        Ok(false)
+    }
+
+    /// Returns true if another tool builds and pushes the images after `build`, e.g. kaniko
+    fn pushes_externally(&self) -> bool {
+        false
+    }
+}
+
+pub fn new_image_builder(kind: ImageBuilderKind, kaniko_dir: PathBuf) -> Box<dyn ImageBuilder> {
+    match kind {
+        ImageBuilderKind::Docker => Box::new(CliImageBuilder::new("docker")),
+        ImageBuilderKind::Podman => Box::new(CliImageBuilder::new("podman")),
+        ImageBuilderKind::Buildah => Box::new(CliImageBuilder::new("buildah")),
+        ImageBuilderKind::Kaniko => Box::new(KanikoContextWriter::new(kaniko_dir)),
+    }
+}
+
+fn describe(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Turns a finished command into an error carrying its stderr if it failed
+pub fn check_output(command: &str, output: Output) -> Result<(), LabError> {
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(LabError::docker(format!(
+        "`{command}` exited with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
+
+/// The config digest of an image manifest, which is the image ID of the image it was pushed from.
+/// Reads the plain manifest of `podman manifest inspect`,
+/// as well as the `docker manifest inspect --verbose` descriptor of one or more platforms.
+/// None for a multi-platform manifest, since it does not match a single local image
+pub fn manifest_config_digest(manifest: &str) -> Option<String> {
+    let manifest: serde_json::Value = serde_json::from_str(manifest).ok()?;
+    let manifest = match manifest {
+        serde_json::Value::Array(mut manifests) if manifests.len() == 1 => manifests.pop()?,
+        serde_json::Value::Array(_) => return None,
+        manifest => manifest,
+    };
+    let config = ["SchemaV2Manifest", "OCIManifest"]
+        .iter()
+        .find_map(|key| manifest.get(key))
+        .unwrap_or(&manifest)
+        .get("config")?;
+    config.get("digest")?.as_str().map(str::to_string)
+}
+
+/// Image IDs are printed with or without their algorithm depending on the CLI
+fn normalize_digest(digest: &str) -> String {
+    let digest = digest.trim();
+    if digest.contains(':') {
+        digest.to_string()
+    } else {
+        format!("sha256:{digest}")
+    }
+}
+
+/// Builds with a local container CLI that takes docker's `build` and `push` arguments:
+/// docker, podman, or buildah. Docker-rs is very slow building dockerfiles in large repos
+/// and doesn't support the `--file` flag natively, so we shell out instead
+pub struct CliImageBuilder {
+    program: &'static str,
+}
+
+impl CliImageBuilder {
+    pub fn new(program: &'static str) -> Self {
+        Self { program }
+    }
+
+    /// Stdout of the command, or None if it failed, e.g. for an image that doesn't exist
+    fn query(&self, args: &[&str]) -> Result<Option<String>, LabError> {
+        let mut command = Command::new(self.program);
+        command.args(args).stdin(Stdio::null());
+        let description = describe(&command);
+        let output = command.output().map_err(|err| {
+            LabError::docker(format!("Failed to run `{description}`")).with_source(err)
+        })?;
+        if !output.status.success() {
+            debug!(
+                "`{description}` exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    fn local_image_id(&self, image: &str) -> Result<Option<String>, LabError> {
+        let args: &[&str] = match self.program {
+            "buildah" => &["inspect", "--type", "image", "--format", "{{.FromImageID}}"],
+            _ => &["image", "inspect", "--format", "{{.Id}}"],
+        };
+        let args = [args, &[image]].concat();
+        Ok(self
+            .query(&args)?
+            .filter(|id| !id.trim().is_empty())
+            .map(|id| normalize_digest(&id)))
+    }
+
+    fn registry_config_digest(&self, image: &str) -> Result<Option<String>, LabError> {
+        let args: &[&str] = match self.program {
+            "docker" => &["manifest", "inspect", "--verbose", image],
+            _ => &["manifest", "inspect", image],
+        };
+        Ok(self
+            .query(args)?
+            .and_then(|manifest| manifest_config_digest(&manifest))
+            .map(|digest| normalize_digest(&digest)))
+    }
+}
+
+impl ImageBuilder for CliImageBuilder {
+    fn build(
+        &self,
+        image: &DockerImage,
+        dockerfile: &Path,
+        context: &Path,
+    ) -> Result<(), LabError> {
+        let mut command = Command::new(self.program);
+        command
+            .arg("build")
+            .arg("-t")
+            .arg(image.to_string())
+            .arg("-f")
+            .arg(dockerfile)
+            .arg(context)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        let description = describe(&command);
+        debug!("{} command: {description}", self.program);
+        let output = command.output().map_err(|err| {
+            LabError::docker(format!("Failed to run `{description}`")).with_source(err)
+        })?;
+        check_output(&description, output)
+    }
+
+    fn push(&self, image: &DockerImage) -> Result<Option<Child>, LabError> {
+        let mut command = Command::new(self.program);
+        command
+            .arg("push")
+            .arg(image.to_string())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        let description = describe(&command);
+        let child = command.spawn().map_err(|err| {
+            LabError::docker(format!("Failed to run `{description}`")).with_source(err)
+        })?;
+        Ok(Some(child))
+    }
+
+    fn is_in_registry(&self, image: &DockerImage) -> Result<bool, LabError> {
+        let image = image.to_string();
+        let Some(image_id) = self.local_image_id(&image)? else {
+            return Ok(false);
+        };
+        let registry_digest = self.registry_config_digest(&image)?;
+        debug!("{image}: local image {image_id}, registry config {registry_digest:?}");
+        Ok(registry_digest.as_deref() == Some(image_id.as_str()))
+    }
+}
+
+/// Writes each image's build context to `<output_dir>/<image>/` for an external builder,
+/// along with the `kaniko-args` to build and push it, e.g. from a pod that mounts `output_dir`
+pub struct KanikoContextWriter {
+    output_dir: PathBuf,
+}
+
+impl KanikoContextWriter {
+    pub fn new(output_dir: PathBuf) -> Self {
+        Self { output_dir }
+    }
+
+    /// `registry/name:tag` as a directory name
+    fn context_dir(&self, image: &DockerImage) -> PathBuf {
+        self.output_dir
+            .join(image.to_string().replace(['/', ':'], "_"))
+    }
+}
+
+impl ImageBuilder for KanikoContextWriter {
+    fn build(
+        &self,
+        image: &DockerImage,
+        dockerfile: &Path,
+        context: &Path,
+    ) -> Result<(), LabError> {
+        let context_dir = self.context_dir(image);
+        let io_error = |err: std::io::Error| {
+            LabError::docker(format!(
+                "Failed to write the build context of {image} to {}",
+                context_dir.display()
+            ))
+            .with_source(err)
+        };
+        if context_dir.exists() {
+            fs::remove_dir_all(&context_dir).map_err(io_error)?;
+        }
+        for path in BUILD_CONTEXT_PATHS {
+            let source = context.join(path);
+            if source.exists() {
+                link_or_copy_dir(&source, &context_dir.join(path)).map_err(io_error)?;
+            }
+        }
+
+        let dockerfile = dockerfile.strip_prefix(context).map_err(|_| {
+            LabError::docker(format!(
+                "Dockerfile {} is outside of the build context {}",
+                dockerfile.display(),
+                context.display()
+            ))
+        })?;
+        let args = format!(
+            "--context=dir://{}\n--dockerfile={}\n--destination={image}\n",
+            context_dir.display(),
+            dockerfile.display()
+        );
+        fs::write(context_dir.join("kaniko-args"), args).map_err(io_error)?;
+        info!(
+            "Wrote the build context of {image} to {}",
+            context_dir.display()
+        );
+        Ok(())
+    }
+
+    fn push(&self, _image: &DockerImage) -> Result<Option<Child>, LabError> {
+        Ok(None)
+    }
+
+    fn pushes_externally(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{docker::DockerConfig, NodeType},
+        tempfile::TempDir,
+    };
+
+    #[test]
+    fn test_kaniko_context_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let context = root.join("cluster-data");
+        fs::create_dir_all(context.join("docker-build/validator")).unwrap();
+        fs::create_dir_all(context.join(format!("{SOLANA_RELEASE}/bin"))).unwrap();
+        fs::create_dir_all(context.join("build-cache")).unwrap();
+        let dockerfile = context.join("docker-build/validator/Dockerfile");
+        fs::write(&dockerfile, "FROM ubuntu:22.04\n").unwrap();
+        fs::write(
+            context.join(format!("{SOLANA_RELEASE}/bin/agave-validator")),
+            b"elf",
+        )
+        .unwrap();
+
+        let image = DockerImage::new(
+            "registry".to_string(),
+            NodeType::Standard,
+            "k8s-image".to_string(),
+            "v1-18-20".to_string(),
+        );
+        let writer = KanikoContextWriter::new(root.join("kaniko"));
+        writer.build(&image, &dockerfile, &context).unwrap();
+        assert!(writer.push(&image).unwrap().is_none());
+        // kaniko pushes the images, so none of them count as pushed
+        let docker = DockerConfig::new(
+            String::new(),
+            Box::new(writer),
+            ImageDistribution::Registry,
+            None,
+        );
+        let pushed_images = docker.push_images(std::slice::from_ref(&image)).unwrap();
+        assert!(pushed_images.pushed.is_empty());
+        assert_eq!(pushed_images.external.len(), 1);
+
+        let context_dir = root.join("kaniko/registry_k8s-image_v1-18-20");
+        assert!(context_dir
+            .join("docker-build/validator/Dockerfile")
+            .exists());
+        assert!(context_dir
+            .join(format!("{SOLANA_RELEASE}/bin/agave-validator"))
+            .exists());
+        // caches stay out of the context
+        assert!(!context_dir.join("build-cache").exists());
+        assert_eq!(
+            fs::read_to_string(context_dir.join("kaniko-args")).unwrap(),
+            format!(
+                "--context=dir://{}\n--dockerfile=docker-build/validator/Dockerfile\n--destination={image}\n",
+                context_dir.display()
+            )
+        );
+
+        let output = Command::new("sh")
+            .args(["-c", "echo 'no such image' >&2; exit 3"])
+            .output()
+            .unwrap();
+        let err = check_output("sh", output).unwrap_err();
+        assert!(err.context().contains("no such image"), "{err}");
+    }
+
+    #[test]
+    fn test_manifest_config_digest() {
+        let config = r#"{"mediaType": "application/vnd.docker.container.image.v1+json", "digest": "sha256:abcd"}"#;
+        let manifest = format!(r#"{{"schemaVersion": 2, "config": {config}, "layers": []}}"#);
+        let verbose = format!(
+            r#"{{"Ref": "registry/k8s-image:v1-18-20", "Descriptor": {{"digest": "sha256:ef01"}}, "SchemaV2Manifest": {manifest}}}"#
+        );
+        assert_eq!(manifest_config_digest(&manifest).unwrap(), "sha256:abcd");
+        assert_eq!(manifest_config_digest(&verbose).unwrap(), "sha256:abcd");
+        assert_eq!(
+            manifest_config_digest(&format!("[{verbose}]")).unwrap(),
+            "sha256:abcd"
+        );
+        assert!(manifest_config_digest(&format!("[{verbose}, {verbose}]")).is_none());
+        assert!(manifest_config_digest("no such manifest").is_none());
+
+        assert_eq!(normalize_digest("abcd\n"), "sha256:abcd");
+        assert_eq!(normalize_digest("sha256:abcd"), "sha256:abcd");
+    }
+}
@@ -1,24 +1,25 @@
 use {
-    crate::{docker::DockerImage, kubernetes::ServiceType},
+    crate::{docker::DockerImage, error::LabError, kubernetes::ServiceType},
     k8s_openapi::{
         api::{
             apps::v1::{ReplicaSet, ReplicaSetSpec},
             core::v1::{
-                Container, EnvVar, EnvVarSource, ObjectFieldSelector, PodSecurityContext, PodSpec,
-                PodTemplateSpec, Probe, ResourceRequirements, Secret, Service, ServicePort,
-                ServiceSpec, Volume, VolumeMount,
+                Affinity, ConfigMap, Container, EnvVar, EnvVarSource, ObjectFieldSelector,
+                PodSecurityContext, PodSpec, PodTemplateSpec, Probe, ResourceRequirements, Secret,
+                Service, ServicePort, ServiceSpec, Toleration, TopologySpreadConstraint, Volume,
+                VolumeMount,
             },
         },
         apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::LabelSelector},
         ByteString,
     },
     kube::api::ObjectMeta,
-    std::{collections::BTreeMap, error::Error, path::PathBuf},
+    std::{collections::BTreeMap, path::PathBuf},
 };
 
 pub enum SecretType {
     Value { v: String },    // will be read by pod via ENV variable
-    File { path: PathBuf }, // will be read by pod as .json file
+    File { path: PathBuf }, // will be read by pod as a file with the same extension, .json by default
 }
 
 fn build_secret(name: String, data: BTreeMap<String, ByteString>) -> Secret {
@@ -35,22 +36,46 @@ fn build_secret(name: String, data: BTreeMap<String, ByteString>) -> Secret {
 pub fn create_secret(
     secret_name: String,
     secrets: BTreeMap<String, SecretType>,
-) -> Result<Secret, Box<dyn Error>> {
+) -> Result<Secret, LabError> {
     let data = secrets
         .into_iter()
         .map(|(label, value)| match value {
             SecretType::Value { v } => Ok((label, ByteString(v.into_bytes()))),
             SecretType::File { path } => {
-                let content = std::fs::read(&path)
-                    .map_err(|err| format!("Failed to read file '{:?}': {}", path, err))?;
-                Ok((format!("{label}.json"), ByteString(content)))
+                let content = std::fs::read(&path).map_err(|err| {
+                    LabError::kube(format!("Failed to read file '{:?}'", path)).with_source(err)
+                })?;
+                let extension = path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or("json");
+                Ok((format!("{label}.{extension}"), ByteString(content)))
             }
         })
-        .collect::<Result<BTreeMap<String, ByteString>, Box<dyn Error>>>()?;
+        .collect::<Result<BTreeMap<String, ByteString>, LabError>>()?;
 
     Ok(build_secret(secret_name, data))
 }
 
+pub fn create_config_map(name: String, data: BTreeMap<String, String>) -> ConfigMap {
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+/// Controls which kubernetes nodes a pod can land on
+#[derive(Debug, Clone, Default)]
+pub struct PodScheduling {
+    pub affinity: Option<Affinity>,
+    pub tolerations: Option<Vec<Toleration>>,
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+}
+
 pub fn create_selector(key: &str, value: &str) -> BTreeMap<String, String> {
     let mut btree = BTreeMap::new();
     btree.insert(key.to_string(), value.to_string());
@@ -69,7 +94,9 @@ pub fn create_replica_set(
     volume_mounts: Option<Vec<VolumeMount>>,
     pod_requests: BTreeMap<String, Quantity>,
     readiness_probe: Option<Probe>,
-) -> Result<ReplicaSet, Box<dyn Error>> {
+    scheduling: PodScheduling,
+    image_pull_policy: &str,
+) -> Result<ReplicaSet, LabError> {
     let pod_spec = PodTemplateSpec {
         metadata: Some(ObjectMeta {
             labels: Some(label_selector.clone()),
@@ -79,7 +106,7 @@ pub fn create_replica_set(
             containers: vec![Container {
                 name: format!("{}-container", image_name.node_type()),
                 image: Some(image_name.to_string()),
-                image_pull_policy: Some("Always".to_string()),
+                image_pull_policy: Some(image_pull_policy.to_string()),
                 env: Some(environment_variables),
                 command: Some(command),
                 volume_mounts,
@@ -91,6 +118,9 @@ pub fn create_replica_set(
                 ..Default::default()
             }],
             volumes,
+            affinity: scheduling.affinity,
+            tolerations: scheduling.tolerations,
+            topology_spread_constraints: scheduling.topology_spread_constraints,
             security_context: Some(PodSecurityContext {
                 run_as_user: Some(1000),
                 run_as_group: Some(1000),
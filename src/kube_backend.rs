@@ -0,0 +1,717 @@
+use {
+    crate::{error::LabError, manifest_writer::ManifestWriter},
+    async_trait::async_trait,
+    k8s_openapi::api::{
+        apps::v1::ReplicaSet,
+        core::v1::{ConfigMap, Namespace, Node, Pod, Secret, Service},
+    },
+    kube::{
+        api::{Api, DeleteParams, ListParams, LogParams, Patch, PatchParams, PostParams},
+        Client,
+    },
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::{Arc, Mutex, MutexGuard},
+    },
+};
+
+/// Desired vs available pods of a ReplicaSet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicaSetReadiness {
+    pub desired: i32,
+    pub available: i32,
+}
+
+impl ReplicaSetReadiness {
+    pub fn is_ready(&self) -> bool {
+        self.available >= self.desired
+    }
+}
+
+/// The Kubernetes API operations a deployment needs.
+/// `ClusterBackend` talks to a real cluster, `ManifestWriter` backs `--dry-run`,
+/// and `InMemoryBackend` lets the deploy flow run in tests
+#[async_trait(?Send)]
+pub trait KubeBackend {
+    async fn list_namespaces(&self) -> Result<Vec<String>, LabError>;
+    async fn create_secret(&self, namespace: &str, secret: &Secret) -> Result<Secret, LabError>;
+    /// Creates the ConfigMap, or replaces the one of the same name
+    async fn apply_config_map(
+        &self,
+        namespace: &str,
+        config_map: &ConfigMap,
+    ) -> Result<ConfigMap, LabError>;
+    async fn create_replica_set(
+        &self,
+        namespace: &str,
+        replica_set: &ReplicaSet,
+    ) -> Result<ReplicaSet, LabError>;
+    async fn create_service(&self, namespace: &str, service: &Service)
+        -> Result<Service, LabError>;
+    async fn get_replica_set_readiness(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<ReplicaSetReadiness, LabError>;
+    async fn list_replica_sets(&self, namespace: &str) -> Result<Vec<ReplicaSet>, LabError>;
+    async fn list_services(&self, namespace: &str) -> Result<Vec<Service>, LabError>;
+    async fn list_secrets(&self, namespace: &str) -> Result<Vec<Secret>, LabError>;
+    async fn list_config_maps(&self, namespace: &str) -> Result<Vec<ConfigMap>, LabError>;
+    async fn list_pods(&self, namespace: &str) -> Result<Vec<Pod>, LabError>;
+    async fn list_nodes(&self) -> Result<Vec<Node>, LabError>;
+    /// Logs of the pod's running container, or of the container it replaced if `previous`
+    async fn get_pod_logs(
+        &self,
+        namespace: &str,
+        pod_name: &str,
+        previous: bool,
+    ) -> Result<String, LabError>;
+    /// Deleting a ReplicaSet also deletes its pods
+    async fn delete_replica_set(&self, namespace: &str, name: &str) -> Result<(), LabError>;
+    async fn delete_service(&self, namespace: &str, name: &str) -> Result<(), LabError>;
+    async fn delete_secret(&self, namespace: &str, name: &str) -> Result<(), LabError>;
+    async fn delete_config_map(&self, namespace: &str, name: &str) -> Result<(), LabError>;
+}
+
+fn readiness_of(replica_set: &ReplicaSet) -> ReplicaSetReadiness {
+    ReplicaSetReadiness {
+        desired: replica_set
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.replicas)
+            .unwrap_or(1),
+        available: replica_set
+            .status
+            .as_ref()
+            .and_then(|status| status.available_replicas)
+            .unwrap_or(0),
+    }
+}
+
+fn api_error(verb: &str, kind: &str, name: Option<&String>, err: kube::Error) -> LabError {
+    LabError::kube(format!(
+        "Failed to {verb} {kind} {}",
+        name.map(String::as_str).unwrap_or_default()
+    ))
+    .with_source(err)
+}
+
+/// Owner of the fields we set with server-side apply
+const FIELD_MANAGER: &str = "validator-lab";
+
+pub struct ClusterBackend {
+    client: Client,
+}
+
+impl ClusterBackend {
+    /// Connects using the local kubeconfig or in-cluster config
+    pub async fn new() -> Result<Self, LabError> {
+        let client = Client::try_default()
+            .await
+            .map_err(|err| LabError::kube("Failed to create kubernetes client").with_source(err))?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait(?Send)]
+impl KubeBackend for ClusterBackend {
+    async fn list_namespaces(&self) -> Result<Vec<String>, LabError> {
+        let namespaces: Api<Namespace> = Api::all(self.client.clone());
+        Ok(namespaces
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| LabError::kube("Failed to list namespaces").with_source(err))?
+            .items
+            .into_iter()
+            .filter_map(|ns| ns.metadata.name)
+            .collect())
+    }
+
+    async fn create_secret(&self, namespace: &str, secret: &Secret) -> Result<Secret, LabError> {
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), namespace);
+        api.create(&PostParams::default(), secret)
+            .await
+            .map_err(|err| api_error("create", "secret", secret.metadata.name.as_ref(), err))
+    }
+
+    async fn apply_config_map(
+        &self,
+        namespace: &str,
+        config_map: &ConfigMap,
+    ) -> Result<ConfigMap, LabError> {
+        let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), namespace);
+        let name = config_map
+            .metadata
+            .name
+            .as_deref()
+            .ok_or_else(|| LabError::kube("configmap must have a name"))?;
+        // server-side apply, so redeploying a tag updates the ConfigMap it kept
+        api.patch(
+            name,
+            &PatchParams::apply(FIELD_MANAGER).force(),
+            &Patch::Apply(config_map),
+        )
+        .await
+        .map_err(|err| api_error("apply", "configmap", config_map.metadata.name.as_ref(), err))
+    }
+
+    async fn create_replica_set(
+        &self,
+        namespace: &str,
+        replica_set: &ReplicaSet,
+    ) -> Result<ReplicaSet, LabError> {
+        let api: Api<ReplicaSet> = Api::namespaced(self.client.clone(), namespace);
+        api.create(&PostParams::default(), replica_set)
+            .await
+            .map_err(|err| {
+                api_error(
+                    "create",
+                    "replicaset",
+                    replica_set.metadata.name.as_ref(),
+                    err,
+                )
+            })
+    }
+
+    async fn create_service(
+        &self,
+        namespace: &str,
+        service: &Service,
+    ) -> Result<Service, LabError> {
+        let api: Api<Service> = Api::namespaced(self.client.clone(), namespace);
+        api.create(&PostParams::default(), service)
+            .await
+            .map_err(|err| api_error("create", "service", service.metadata.name.as_ref(), err))
+    }
+
+    async fn get_replica_set_readiness(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<ReplicaSetReadiness, LabError> {
+        let api: Api<ReplicaSet> = Api::namespaced(self.client.clone(), namespace);
+        let replica_set = api
+            .get(name)
+            .await
+            .map_err(|err| api_error("get", "replicaset", Some(&name.to_string()), err))?;
+        Ok(readiness_of(&replica_set))
+    }
+
+    async fn list_replica_sets(&self, namespace: &str) -> Result<Vec<ReplicaSet>, LabError> {
+        let api: Api<ReplicaSet> = Api::namespaced(self.client.clone(), namespace);
+        Ok(api
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| {
+                LabError::kube(format!("Failed to list replicasets in {namespace}"))
+                    .with_source(err)
+            })?
+            .items)
+    }
+
+    async fn list_services(&self, namespace: &str) -> Result<Vec<Service>, LabError> {
+        let api: Api<Service> = Api::namespaced(self.client.clone(), namespace);
+        Ok(api
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| {
+                LabError::kube(format!("Failed to list services in {namespace}")).with_source(err)
+            })?
+            .items)
+    }
+
+    async fn list_secrets(&self, namespace: &str) -> Result<Vec<Secret>, LabError> {
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), namespace);
+        Ok(api
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| {
+                LabError::kube(format!("Failed to list secrets in {namespace}")).with_source(err)
+            })?
+            .items)
+    }
+
+    async fn list_config_maps(&self, namespace: &str) -> Result<Vec<ConfigMap>, LabError> {
+        let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), namespace);
+        Ok(api
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| {
+                LabError::kube(format!("Failed to list configmaps in {namespace}")).with_source(err)
+            })?
+            .items)
+    }
+
+    async fn list_pods(&self, namespace: &str) -> Result<Vec<Pod>, LabError> {
+        let api: Api<Pod> = Api::namespaced(self.client.clone(), namespace);
+        Ok(api
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| {
+                LabError::kube(format!("Failed to list pods in {namespace}")).with_source(err)
+            })?
+            .items)
+    }
+
+    async fn list_nodes(&self) -> Result<Vec<Node>, LabError> {
+        let api: Api<Node> = Api::all(self.client.clone());
+        Ok(api
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| LabError::kube("Failed to list nodes").with_source(err))?
+            .items)
+    }
+
+    async fn get_pod_logs(
+        &self,
+        namespace: &str,
+        pod_name: &str,
+        previous: bool,
+    ) -> Result<String, LabError> {
+        let api: Api<Pod> = Api::namespaced(self.client.clone(), namespace);
+        let log_params = LogParams {
+            previous,
+            ..Default::default()
+        };
+        api.logs(pod_name, &log_params)
+            .await
+            .map_err(|err| api_error("get logs of", "pod", Some(&pod_name.to_string()), err))
+    }
+
+    async fn delete_replica_set(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        let api: Api<ReplicaSet> = Api::namespaced(self.client.clone(), namespace);
+        // background propagation so the ReplicaSet takes its pods down with it
+        api.delete(name, &DeleteParams::background())
+            .await
+            .map(|_| ())
+            .map_err(|err| api_error("delete", "replicaset", Some(&name.to_string()), err))
+    }
+
+    async fn delete_service(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        let api: Api<Service> = Api::namespaced(self.client.clone(), namespace);
+        api.delete(name, &DeleteParams::default())
+            .await
+            .map(|_| ())
+            .map_err(|err| api_error("delete", "service", Some(&name.to_string()), err))
+    }
+
+    async fn delete_secret(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), namespace);
+        api.delete(name, &DeleteParams::default())
+            .await
+            .map(|_| ())
+            .map_err(|err| api_error("delete", "secret", Some(&name.to_string()), err))
+    }
+
+    async fn delete_config_map(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), namespace);
+        api.delete(name, &DeleteParams::default())
+            .await
+            .map(|_| ())
+            .map_err(|err| api_error("delete", "configmap", Some(&name.to_string()), err))
+    }
+}
+
+/// Every object created through `InMemoryBackend`, keyed by (namespace, name)
+#[derive(Debug, Default)]
+pub struct InMemoryObjects {
+    pub namespaces: BTreeSet<String>,
+    pub secrets: BTreeMap<(String, String), Secret>,
+    pub config_maps: BTreeMap<(String, String), ConfigMap>,
+    pub replica_sets: BTreeMap<(String, String), ReplicaSet>,
+    pub services: BTreeMap<(String, String), Service>,
+    pub pods: BTreeMap<(String, String), Pod>,
+    pub nodes: Vec<Node>,
+    /// logs of the running container of each pod
+    pub pod_logs: BTreeMap<(String, String), String>,
+}
+
+/// Fake cluster that keeps objects in memory. Rejects duplicate names like the API server does.
+/// ReplicaSets become available as soon as they are created unless `new_replica_sets_ready` is false.
+/// Clones share the same objects, so a test can hand one to `Kubernetes` and inspect the other
+#[derive(Debug, Clone)]
+pub struct InMemoryBackend {
+    objects: Arc<Mutex<InMemoryObjects>>,
+    new_replica_sets_ready: bool,
+}
+
+impl InMemoryBackend {
+    pub fn new(namespaces: &[&str]) -> Self {
+        let objects = InMemoryObjects {
+            namespaces: namespaces.iter().map(|ns| ns.to_string()).collect(),
+            ..Default::default()
+        };
+        Self {
+            objects: Arc::new(Mutex::new(objects)),
+            new_replica_sets_ready: true,
+        }
+    }
+
+    pub fn with_new_replica_sets_ready(mut self, ready: bool) -> Self {
+        self.new_replica_sets_ready = ready;
+        self
+    }
+
+    /// Adds a Service that was not created by us, e.g. one holding a NodePort
+    pub fn add_existing_service(&self, namespace: &str, service: Service) {
+        let mut objects = self.objects();
+        objects.namespaces.insert(namespace.to_string());
+        let name = service.metadata.name.clone().unwrap_or_default();
+        objects
+            .services
+            .insert((namespace.to_string(), name), service);
+    }
+
+    /// Adds a pod as if a ReplicaSet had started it, with the logs its container has written
+    pub fn add_pod(&self, namespace: &str, pod: Pod, logs: &str) {
+        let mut objects = self.objects();
+        let key = (
+            namespace.to_string(),
+            pod.metadata.name.clone().unwrap_or_default(),
+        );
+        objects.pod_logs.insert(key.clone(), logs.to_string());
+        objects.pods.insert(key, pod);
+    }
+
+    /// Marks a ReplicaSet as having all of its pods available
+    pub fn set_replica_set_ready(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        let mut objects = self.objects();
+        let replica_set = objects
+            .replica_sets
+            .get_mut(&(namespace.to_string(), name.to_string()))
+            .ok_or_else(|| LabError::kube(format!("replicasets \"{name}\" not found")))?;
+        let desired = readiness_of(replica_set).desired;
+        replica_set
+            .status
+            .get_or_insert_with(Default::default)
+            .available_replicas = Some(desired);
+        Ok(())
+    }
+
+    pub fn objects(&self) -> MutexGuard<'_, InMemoryObjects> {
+        self.objects.lock().unwrap()
+    }
+}
+
+fn insert_new<K: Clone>(
+    map: &mut BTreeMap<(String, String), K>,
+    kind: &str,
+    namespace: &str,
+    name: Option<&String>,
+    object: &K,
+) -> Result<K, LabError> {
+    let name = name.ok_or_else(|| LabError::kube(format!("{kind} must have a name")))?;
+    let key = (namespace.to_string(), name.clone());
+    if map.contains_key(&key) {
+        return Err(LabError::kube(format!("{kind} \"{name}\" already exists")));
+    }
+    map.insert(key, object.clone());
+    Ok(object.clone())
+}
+
+fn remove_existing<K>(
+    map: &mut BTreeMap<(String, String), K>,
+    kind: &str,
+    namespace: &str,
+    name: &str,
+) -> Result<(), LabError> {
+    map.remove(&(namespace.to_string(), name.to_string()))
+        .map(|_| ())
+        .ok_or_else(|| LabError::kube(format!("{kind} \"{name}\" not found")))
+}
+
+#[async_trait(?Send)]
+impl KubeBackend for InMemoryBackend {
+    async fn list_namespaces(&self) -> Result<Vec<String>, LabError> {
+        Ok(self.objects().namespaces.iter().cloned().collect())
+    }
+
+    async fn create_secret(&self, namespace: &str, secret: &Secret) -> Result<Secret, LabError> {
+        let mut objects = self.objects();
+        insert_new(
+            &mut objects.secrets,
+            "secrets",
+            namespace,
+            secret.metadata.name.as_ref(),
+            secret,
+        )
+    }
+
+    async fn apply_config_map(
+        &self,
+        namespace: &str,
+        config_map: &ConfigMap,
+    ) -> Result<ConfigMap, LabError> {
+        let name = config_map
+            .metadata
+            .name
+            .clone()
+            .ok_or_else(|| LabError::kube("configmaps must have a name"))?;
+        self.objects()
+            .config_maps
+            .insert((namespace.to_string(), name), config_map.clone());
+        Ok(config_map.clone())
+    }
+
+    async fn create_replica_set(
+        &self,
+        namespace: &str,
+        replica_set: &ReplicaSet,
+    ) -> Result<ReplicaSet, LabError> {
+        let mut replica_set = replica_set.clone();
+        if self.new_replica_sets_ready {
+            let desired = readiness_of(&replica_set).desired;
+            replica_set
+                .status
+                .get_or_insert_with(Default::default)
+                .available_replicas = Some(desired);
+        }
+        let mut objects = self.objects();
+        let name = replica_set.metadata.name.clone();
+        insert_new(
+            &mut objects.replica_sets,
+            "replicasets",
+            namespace,
+            name.as_ref(),
+            &replica_set,
+        )
+    }
+
+    async fn create_service(
+        &self,
+        namespace: &str,
+        service: &Service,
+    ) -> Result<Service, LabError> {
+        let mut objects = self.objects();
+        insert_new(
+            &mut objects.services,
+            "services",
+            namespace,
+            service.metadata.name.as_ref(),
+            service,
+        )
+    }
+
+    async fn get_replica_set_readiness(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<ReplicaSetReadiness, LabError> {
+        self.objects()
+            .replica_sets
+            .get(&(namespace.to_string(), name.to_string()))
+            .map(readiness_of)
+            .ok_or_else(|| LabError::kube(format!("replicasets \"{name}\" not found")))
+    }
+
+    async fn list_replica_sets(&self, namespace: &str) -> Result<Vec<ReplicaSet>, LabError> {
+        Ok(self
+            .objects()
+            .replica_sets
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .map(|(_, replica_set)| replica_set.clone())
+            .collect())
+    }
+
+    async fn list_services(&self, namespace: &str) -> Result<Vec<Service>, LabError> {
+        Ok(self
+            .objects()
+            .services
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .map(|(_, service)| service.clone())
+            .collect())
+    }
+
+    async fn list_secrets(&self, namespace: &str) -> Result<Vec<Secret>, LabError> {
+        Ok(self
+            .objects()
+            .secrets
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .map(|(_, secret)| secret.clone())
+            .collect())
+    }
+
+    async fn list_config_maps(&self, namespace: &str) -> Result<Vec<ConfigMap>, LabError> {
+        Ok(self
+            .objects()
+            .config_maps
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .map(|(_, config_map)| config_map.clone())
+            .collect())
+    }
+
+    async fn list_pods(&self, namespace: &str) -> Result<Vec<Pod>, LabError> {
+        Ok(self
+            .objects()
+            .pods
+            .iter()
+            .filter(|((ns, _), _)| ns == namespace)
+            .map(|(_, pod)| pod.clone())
+            .collect())
+    }
+
+    async fn list_nodes(&self) -> Result<Vec<Node>, LabError> {
+        Ok(self.objects().nodes.clone())
+    }
+
+    async fn get_pod_logs(
+        &self,
+        namespace: &str,
+        pod_name: &str,
+        previous: bool,
+    ) -> Result<String, LabError> {
+        let objects = self.objects();
+        let logs = objects
+            .pod_logs
+            .get(&(namespace.to_string(), pod_name.to_string()))
+            .ok_or_else(|| LabError::kube(format!("pods \"{pod_name}\" not found")))?;
+        // containers never restart here, so there is no previous container
+        if previous {
+            return Err(LabError::kube(format!(
+                "previous terminated container in pod \"{pod_name}\" not found"
+            )));
+        }
+        Ok(logs.clone())
+    }
+
+    async fn delete_replica_set(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        remove_existing(
+            &mut self.objects().replica_sets,
+            "replicasets",
+            namespace,
+            name,
+        )
+    }
+
+    async fn delete_service(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        remove_existing(&mut self.objects().services, "services", namespace, name)
+    }
+
+    async fn delete_secret(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        remove_existing(&mut self.objects().secrets, "secrets", namespace, name)
+    }
+
+    async fn delete_config_map(&self, namespace: &str, name: &str) -> Result<(), LabError> {
+        remove_existing(
+            &mut self.objects().config_maps,
+            "configmaps",
+            namespace,
+            name,
+        )
+    }
+}
+
+/// `--dry-run`: objects are written to disk and the cluster is never contacted
+#[async_trait(?Send)]
+impl KubeBackend for ManifestWriter {
+    async fn list_namespaces(&self) -> Result<Vec<String>, LabError> {
+        Ok(vec![self.namespace().to_string()])
+    }
+
+    async fn create_secret(&self, _namespace: &str, secret: &Secret) -> Result<Secret, LabError> {
+        self.write_secret(secret)?;
+        Ok(secret.clone())
+    }
+
+    async fn apply_config_map(
+        &self,
+        _namespace: &str,
+        config_map: &ConfigMap,
+    ) -> Result<ConfigMap, LabError> {
+        self.write(config_map)?;
+        Ok(config_map.clone())
+    }
+
+    async fn create_replica_set(
+        &self,
+        _namespace: &str,
+        replica_set: &ReplicaSet,
+    ) -> Result<ReplicaSet, LabError> {
+        self.write(replica_set)?;
+        Ok(replica_set.clone())
+    }
+
+    async fn create_service(
+        &self,
+        _namespace: &str,
+        service: &Service,
+    ) -> Result<Service, LabError> {
+        self.write(service)?;
+        Ok(service.clone())
+    }
+
+    async fn get_replica_set_readiness(
+        &self,
+        _namespace: &str,
+        _name: &str,
+    ) -> Result<ReplicaSetReadiness, LabError> {
+        // nothing is deployed, so there is nothing to wait for
+        Ok(ReplicaSetReadiness {
+            desired: 1,
+            available: 1,
+        })
+    }
+
+    async fn list_replica_sets(&self, _namespace: &str) -> Result<Vec<ReplicaSet>, LabError> {
+        Ok(vec![])
+    }
+
+    async fn list_services(&self, _namespace: &str) -> Result<Vec<Service>, LabError> {
+        Ok(vec![])
+    }
+
+    async fn list_secrets(&self, _namespace: &str) -> Result<Vec<Secret>, LabError> {
+        Ok(vec![])
+    }
+
+    async fn list_config_maps(&self, _namespace: &str) -> Result<Vec<ConfigMap>, LabError> {
+        Ok(vec![])
+    }
+
+    async fn list_pods(&self, _namespace: &str) -> Result<Vec<Pod>, LabError> {
+        Ok(vec![])
+    }
+
+    async fn list_nodes(&self) -> Result<Vec<Node>, LabError> {
+        Ok(vec![])
+    }
+
+    async fn get_pod_logs(
+        &self,
+        _namespace: &str,
+        pod_name: &str,
+        _previous: bool,
+    ) -> Result<String, LabError> {
+        Err(LabError::kube(format!(
+            "Cannot get logs of pod {pod_name} in a dry run"
+        )))
+    }
+
+    async fn delete_replica_set(&self, _namespace: &str, name: &str) -> Result<(), LabError> {
+        Err(LabError::kube(format!(
+            "Cannot delete replicaset {name} in a dry run"
+        )))
+    }
+
+    async fn delete_service(&self, _namespace: &str, name: &str) -> Result<(), LabError> {
+        Err(LabError::kube(format!(
+            "Cannot delete service {name} in a dry run"
+        )))
+    }
+
+    async fn delete_secret(&self, _namespace: &str, name: &str) -> Result<(), LabError> {
+        Err(LabError::kube(format!(
+            "Cannot delete secret {name} in a dry run"
+        )))
+    }
+
+    async fn delete_config_map(&self, _namespace: &str, name: &str) -> Result<(), LabError> {
+        Err(LabError::kube(format!(
+            "Cannot delete configmap {name} in a dry run"
+        )))
+    }
+}
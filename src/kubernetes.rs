@@ -1,8 +1,14 @@
 use {
     crate::{
         client_config::ClientConfig,
+        cluster_status::load_balancer_rpc_url,
         docker::DockerImage,
+        error::LabError,
+        image_builder::ImageDistribution,
         k8s_helpers::{self, SecretType},
+        kube_backend::KubeBackend,
+        node_placement::NodePlacement,
+        startup_scripts::{StartupScripts, STARTUP_SCRIPTS_DIR},
         validator_config::ValidatorConfig,
         Metrics, NodeType,
     },
@@ -10,26 +16,24 @@ use {
         api::{
             apps::v1::ReplicaSet,
             core::v1::{
-                EnvVar, EnvVarSource, ExecAction, Namespace, ObjectFieldSelector, Probe, Secret,
-                SecretKeySelector, SecretVolumeSource, Service, Volume, VolumeMount,
+                ConfigMap, ConfigMapVolumeSource, EnvVar, EnvVarSource, ExecAction,
+                ObjectFieldSelector, Probe, Secret, SecretKeySelector, SecretVolumeSource, Service,
+                Volume, VolumeMount,
             },
         },
         apimachinery::pkg::api::resource::Quantity,
     },
-    kube::{
-        api::{Api, ListParams, ObjectList, PostParams},
-        Client,
-    },
     log::*,
+    serde::Deserialize,
     solana_sdk::pubkey::Pubkey,
     std::{
         collections::{BTreeMap, HashSet},
-        error::Error,
         path::Path,
     },
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
 pub struct PodRequests {
     requests: BTreeMap<String, Quantity>,
 }
@@ -43,8 +47,35 @@ impl PodRequests {
             ]),
         }
     }
+
+    pub fn cpu(&self) -> Option<&str> {
+        self.requests.get("cpu").map(|quantity| quantity.0.as_str())
+    }
+
+    pub fn memory(&self) -> Option<&str> {
+        self.requests
+            .get("memory")
+            .map(|quantity| quantity.0.as_str())
+    }
 }
 
+// Names of the Secrets and Services we deploy. Per-node resources are suffixed
+// with `-<deployment_tag>` and, except for the bootstrap, `-<index>`.
+pub const BOOTSTRAP_ACCOUNTS_SECRET: &str = "bootstrap-accounts-secret";
+pub const VALIDATOR_ACCOUNTS_SECRET: &str = "validator-accounts-secret";
+pub const RPC_NODE_ACCOUNT_SECRET: &str = "rpc-node-account-secret";
+pub const CLIENT_ACCOUNTS_SECRET: &str = "client-accounts-secret";
+pub const METRICS_SECRET: &str = "solana-metrics-secret";
+pub const BOOTSTRAP_SERVICE: &str = "bootstrap-validator-service";
+pub const LOAD_BALANCER_SERVICE: &str = "bootstrap-and-rpc-node-lb-service";
+pub const VALIDATOR_SERVICE: &str = "validator-service";
+pub const RPC_NODE_SERVICE: &str = "rpc-node-service";
+pub const CLIENT_SERVICE: &str = "client-service";
+pub const STARTUP_SCRIPTS_CONFIG_MAP: &str = "startup-scripts";
+const STARTUP_SCRIPTS_VOLUME: &str = "startup-scripts-volume";
+/// Tells the validator startup script its accounts are in genesis
+pub const NO_AIRDROP_FLAG: &str = "--no-airdrop";
+
 #[derive(Debug, PartialEq)]
 pub enum ServiceType {
     Standard,
@@ -52,32 +83,39 @@ pub enum ServiceType {
 }
 
 pub struct Kubernetes<'a> {
-    k8s_client: Client,
+    backend: &'a dyn KubeBackend,
     namespace: String,
     validator_config: &'a mut ValidatorConfig,
     client_config: ClientConfig,
     pod_requests: PodRequests,
+    node_placement: NodePlacement,
     pub metrics: Option<Metrics>,
     deployment_tag: String,
+    image_pull_policy: &'static str,
 }
 
 impl<'a> Kubernetes<'a> {
-    pub async fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        backend: &'a dyn KubeBackend,
         namespace: &str,
         validator_config: &'a mut ValidatorConfig,
         client_config: ClientConfig,
         pod_requests: PodRequests,
+        node_placement: NodePlacement,
         metrics: Option<Metrics>,
         deployment_tag: String,
     ) -> Kubernetes<'a> {
         Self {
-            k8s_client: Client::try_default().await.unwrap(),
+            backend,
             namespace: namespace.to_owned(),
             validator_config,
             client_config,
             pod_requests,
+            node_placement,
             metrics,
             deployment_tag,
+            image_pull_policy: ImageDistribution::Registry.image_pull_policy(),
         }
     }
 
@@ -89,27 +127,20 @@ impl<'a> Kubernetes<'a> {
         self.validator_config.shred_version = Some(shred_version);
     }
 
-    async fn get_namespaces(&self) -> Result<ObjectList<Namespace>, kube::Error> {
-        let namespaces: Api<Namespace> = Api::all(self.k8s_client.clone());
-        let namespace_list = namespaces.list(&ListParams::default()).await?;
-        Ok(namespace_list)
+    pub fn set_image_distribution(&mut self, image_distribution: ImageDistribution) {
+        self.image_pull_policy = image_distribution.image_pull_policy();
     }
 
-    pub async fn namespace_exists(&self) -> Result<bool, kube::Error> {
-        let namespace_list = self.get_namespaces().await?;
-        let exists = namespace_list
-            .items
-            .iter()
-            .any(|ns| ns.metadata.name.as_ref() == Some(&self.namespace));
-
-        Ok(exists)
+    pub async fn namespace_exists(&self) -> Result<bool, LabError> {
+        let namespaces = self.backend.list_namespaces().await?;
+        Ok(namespaces.contains(&self.namespace))
     }
 
     pub fn create_bootstrap_secret(
         &mut self,
         secret_name: &str,
         config_dir: &Path,
-    ) -> Result<Secret, Box<dyn Error>> {
+    ) -> Result<Secret, LabError> {
         let secret_name = format!("{secret_name}-{}", self.deployment_tag);
         let faucet_key_path = config_dir.join("faucet.json");
         let identity_key_path = config_dir.join("bootstrap-validator/identity.json");
@@ -149,9 +180,9 @@ impl<'a> Kubernetes<'a> {
         &self,
         validator_index: usize,
         config_dir: &Path,
-    ) -> Result<Secret, Box<dyn Error>> {
+    ) -> Result<Secret, LabError> {
         let secret_name = format!(
-            "validator-accounts-secret-{}-{validator_index}",
+            "{VALIDATOR_ACCOUNTS_SECRET}-{}-{validator_index}",
             self.deployment_tag
         );
 
@@ -186,9 +217,9 @@ impl<'a> Kubernetes<'a> {
         &self,
         rpc_index: usize,
         config_dir: &Path,
-    ) -> Result<Secret, Box<dyn Error>> {
+    ) -> Result<Secret, LabError> {
         let secret_name = format!(
-            "rpc-node-account-secret-{}-{rpc_index}",
+            "{RPC_NODE_ACCOUNT_SECRET}-{}-{rpc_index}",
             self.deployment_tag
         );
 
@@ -216,9 +247,9 @@ impl<'a> Kubernetes<'a> {
         &self,
         client_index: usize,
         config_dir: &Path,
-    ) -> Result<Secret, Box<dyn Error>> {
+    ) -> Result<Secret, LabError> {
         let secret_name = format!(
-            "client-accounts-secret-{}-{client_index}",
+            "{CLIENT_ACCOUNTS_SECRET}-{}-{client_index}",
             self.deployment_tag
         );
         let mut secrets = BTreeMap::new();
@@ -239,6 +270,22 @@ impl<'a> Kubernetes<'a> {
             },
         );
 
+        // bench-tps clients read their funded accounts from client-accounts.yml
+        if let ClientConfig::BenchTps(_) = self.client_config {
+            let client_accounts_path = config_dir.join(format!("bench-tps-{client_index}.yml"));
+            if !client_accounts_path.exists() {
+                return Err(LabError::genesis(format!(
+                    "{client_accounts_path:?} does not exist!"
+                )));
+            }
+            secrets.insert(
+                "client-accounts".to_string(),
+                SecretType::File {
+                    path: client_accounts_path,
+                },
+            );
+        }
+
         k8s_helpers::create_secret(secret_name, secrets)
     }
 
@@ -247,10 +294,51 @@ impl<'a> Kubernetes<'a> {
         info!("pubkey added to known validators: {:?}", pubkey);
     }
 
-    pub async fn deploy_secret(&self, secret: &Secret) -> Result<Secret, kube::Error> {
-        let secrets_api: Api<Secret> =
-            Api::namespaced(self.k8s_client.clone(), self.namespace.as_str());
-        secrets_api.create(&PostParams::default(), secret).await
+    pub async fn deploy_secret(&self, secret: &Secret) -> Result<Secret, LabError> {
+        self.backend.create_secret(&self.namespace, secret).await
+    }
+
+    /// The startup scripts of this deployment's pods, by file name
+    pub fn create_startup_scripts_config_map(
+        &self,
+        scripts: BTreeMap<String, String>,
+    ) -> ConfigMap {
+        k8s_helpers::create_config_map(
+            format!("{STARTUP_SCRIPTS_CONFIG_MAP}-{}", self.deployment_tag),
+            scripts,
+        )
+    }
+
+    pub async fn deploy_config_map(&self, config_map: &ConfigMap) -> Result<ConfigMap, LabError> {
+        self.backend
+            .apply_config_map(&self.namespace, config_map)
+            .await
+    }
+
+    /// Mounts the deployment's startup scripts ConfigMap, executable, in STARTUP_SCRIPTS_DIR
+    fn with_startup_scripts(
+        &self,
+        mut volumes: Vec<Volume>,
+        mut volume_mounts: Vec<VolumeMount>,
+    ) -> (Option<Vec<Volume>>, Option<Vec<VolumeMount>>) {
+        volumes.push(Volume {
+            name: STARTUP_SCRIPTS_VOLUME.to_string(),
+            config_map: Some(ConfigMapVolumeSource {
+                name: Some(format!(
+                    "{STARTUP_SCRIPTS_CONFIG_MAP}-{}",
+                    self.deployment_tag
+                )),
+                default_mode: Some(0o755),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        volume_mounts.push(VolumeMount {
+            name: STARTUP_SCRIPTS_VOLUME.to_string(),
+            mount_path: STARTUP_SCRIPTS_DIR.to_string(),
+            ..Default::default()
+        });
+        (Some(volumes), Some(volume_mounts))
     }
 
     pub fn create_bootstrap_validator_replica_set(
@@ -258,7 +346,7 @@ impl<'a> Kubernetes<'a> {
         image: &DockerImage,
         secret_name: Option<String>,
         label_selector: &BTreeMap<String, String>,
-    ) -> Result<ReplicaSet, Box<dyn Error>> {
+    ) -> Result<ReplicaSet, LabError> {
         let mut env_vars = vec![EnvVar {
             name: "MY_POD_IP".to_string(),
             value_from: Some(EnvVarSource {
@@ -277,28 +365,30 @@ impl<'a> Kubernetes<'a> {
         env_vars.push(self.set_namespace_environment_variable());
         env_vars.append(&mut self.set_load_balancer_environment_variables());
 
-        let accounts_volume = Some(vec![Volume {
+        let accounts_volume = vec![Volume {
             name: "bootstrap-accounts-volume".into(),
             secret: Some(SecretVolumeSource {
                 secret_name,
                 ..Default::default()
             }),
             ..Default::default()
-        }]);
+        }];
 
-        let accounts_volume_mount = Some(vec![VolumeMount {
+        let accounts_volume_mount = vec![VolumeMount {
             name: "bootstrap-accounts-volume".to_string(),
             mount_path: "/home/solana/bootstrap-accounts".to_string(),
             ..Default::default()
-        }]);
+        }];
 
         let command_path = format!(
-            "/home/solana/k8s-cluster-scripts/{}-startup-script.sh",
-            NodeType::Bootstrap
+            "{STARTUP_SCRIPTS_DIR}/{}",
+            StartupScripts::file_name(&NodeType::Bootstrap)
         );
         let mut command = vec![command_path];
         command.extend(self.generate_bootstrap_command_flags());
 
+        let (volumes, volume_mounts) =
+            self.with_startup_scripts(accounts_volume, accounts_volume_mount);
         k8s_helpers::create_replica_set(
             format!("{}-{}", image.node_type(), image.tag()),
             self.namespace.clone(),
@@ -306,10 +396,12 @@ impl<'a> Kubernetes<'a> {
             image.clone(),
             env_vars,
             command.clone(),
-            accounts_volume,
-            accounts_volume_mount,
+            volumes,
+            volume_mounts,
             self.pod_requests.requests.clone(),
             None,
+            self.node_placement.scheduling(&image.node_type()),
+            self.image_pull_policy,
         )
     }
 
@@ -356,12 +448,10 @@ impl<'a> Kubernetes<'a> {
     pub async fn deploy_replicas_set(
         &self,
         replica_set: &ReplicaSet,
-    ) -> Result<ReplicaSet, kube::Error> {
-        let api: Api<ReplicaSet> =
-            Api::namespaced(self.k8s_client.clone(), self.namespace.as_str());
-        let post_params = PostParams::default();
-        // Apply the ReplicaSet
-        api.create(&post_params, replica_set).await
+    ) -> Result<ReplicaSet, LabError> {
+        self.backend
+            .create_replica_set(&self.namespace, replica_set)
+            .await
     }
 
     // Only one bootstrap, so service can remain named without tag
@@ -392,21 +482,43 @@ impl<'a> Kubernetes<'a> {
         )
     }
 
-    pub async fn deploy_service(&self, service: &Service) -> Result<Service, kube::Error> {
-        let post_params = PostParams::default();
-        // Create an API instance for Services in the specified namespace
-        let service_api: Api<Service> =
-            Api::namespaced(self.k8s_client.clone(), self.namespace.as_str());
+    pub async fn deploy_service(&self, service: &Service) -> Result<Service, LabError> {
+        self.backend.create_service(&self.namespace, service).await
+    }
+
+    pub fn deployment_tag(&self) -> &str {
+        &self.deployment_tag
+    }
+
+    pub async fn list_replica_sets(&self) -> Result<Vec<ReplicaSet>, LabError> {
+        self.backend.list_replica_sets(&self.namespace).await
+    }
+
+    pub async fn list_services(&self) -> Result<Vec<Service>, LabError> {
+        self.backend.list_services(&self.namespace).await
+    }
 
-        // Create the Service object in the cluster
-        service_api.create(&post_params, service).await
+    pub async fn list_secrets(&self) -> Result<Vec<Secret>, LabError> {
+        self.backend.list_secrets(&self.namespace).await
+    }
+
+    pub async fn delete_replica_set(&self, name: &str) -> Result<(), LabError> {
+        self.backend.delete_replica_set(&self.namespace, name).await
+    }
+
+    pub async fn delete_service(&self, name: &str) -> Result<(), LabError> {
+        self.backend.delete_service(&self.namespace, name).await
+    }
+
+    pub async fn delete_secret(&self, name: &str) -> Result<(), LabError> {
+        self.backend.delete_secret(&self.namespace, name).await
     }
 
     pub async fn create_validator_load_balancer(
         &self,
         service_name: &str,
         label_selector: &BTreeMap<String, String>,
-    ) -> Result<Service, Box<dyn Error>> {
+    ) -> Result<Service, LabError> {
         let node_port = self.get_open_external_port_for_rpc_service().await?;
         info!("Deploying Load Balancer Service with external port: {node_port}");
 
@@ -418,23 +530,20 @@ impl<'a> Kubernetes<'a> {
         ))
     }
 
-    pub async fn is_replica_set_ready(&self, replica_set_name: &str) -> Result<bool, kube::Error> {
-        let replica_sets: Api<ReplicaSet> =
-            Api::namespaced(self.k8s_client.clone(), self.namespace.as_str());
-        let replica_set = replica_sets.get(replica_set_name).await?;
-
-        let desired_validators = replica_set.spec.as_ref().unwrap().replicas.unwrap_or(1);
-        let available_validators = replica_set
-            .status
-            .as_ref()
-            .unwrap()
-            .available_replicas
-            .unwrap_or(0);
+    /// Where the cluster RPC can be reached through the load balancer
+    pub async fn load_balancer_rpc_url(&self) -> Result<Option<String>, LabError> {
+        load_balancer_rpc_url(self.backend, &self.namespace).await
+    }
 
-        Ok(available_validators >= desired_validators)
+    pub async fn is_replica_set_ready(&self, replica_set_name: &str) -> Result<bool, LabError> {
+        let readiness = self
+            .backend
+            .get_replica_set_readiness(&self.namespace, replica_set_name)
+            .await?;
+        Ok(readiness.is_ready())
     }
 
-    pub fn create_metrics_secret(&self) -> Result<Secret, Box<dyn std::error::Error>> {
+    pub fn create_metrics_secret(&self) -> Result<Secret, LabError> {
         let mut data = BTreeMap::new();
         if let Some(metrics) = &self.metrics {
             data.insert(
@@ -444,14 +553,12 @@ impl<'a> Kubernetes<'a> {
                 },
             );
         } else {
-            return Err(
-                "Called create_metrics_secret() but metrics were not provided."
-                    .to_string()
-                    .into(),
-            );
+            return Err(LabError::config(
+                "Called create_metrics_secret() but metrics were not provided.",
+            ));
         }
 
-        k8s_helpers::create_secret("solana-metrics-secret".to_string(), data)
+        k8s_helpers::create_secret(METRICS_SECRET.to_string(), data)
     }
 
     pub fn get_metrics_env_var_secret(&self) -> EnvVar {
@@ -459,7 +566,7 @@ impl<'a> Kubernetes<'a> {
             name: "SOLANA_METRICS_CONFIG".to_string(),
             value_from: Some(EnvVarSource {
                 secret_key_ref: Some(SecretKeySelector {
-                    name: Some("solana-metrics-secret".to_string()),
+                    name: Some(METRICS_SECRET.to_string()),
                     key: "SOLANA_METRICS_CONFIG".to_string(),
                     ..Default::default()
                 }),
@@ -543,21 +650,27 @@ impl<'a> Kubernetes<'a> {
         }
     }
 
-    fn generate_validator_command_flags(&self) -> Vec<String> {
+    fn generate_validator_command_flags(&self, validator_index: usize) -> Vec<String> {
         let mut flags: Vec<String> = Vec::new();
         self.generate_command_flags(&mut flags);
         if self.validator_config.enable_full_rpc {
             Self::generate_full_rpc_flags(&mut flags);
         }
 
+        let stake_sol = self.validator_config.stake_sol(validator_index);
         flags.push("--internal-node-stake-sol".to_string());
-        flags.push(self.validator_config.internal_node_stake_sol.to_string());
+        flags.push(stake_sol.to_string());
 
         flags.push("--commission".to_string());
         flags.push(self.validator_config.commission.to_string());
 
+        // validators are funded with their stake on top of `internal_node_sol`
         flags.push("--internal-node-sol".to_string());
-        flags.push(self.validator_config.internal_node_sol.to_string());
+        flags.push((self.validator_config.internal_node_sol + stake_sol).to_string());
+
+        if self.validator_config.accounts_in_genesis {
+            flags.push(NO_AIRDROP_FLAG.to_string());
+        }
 
         if let Some(shred_version) = self.validator_config.shred_version {
             flags.push("--expected-shred-version".to_string());
@@ -575,7 +688,7 @@ impl<'a> Kubernetes<'a> {
         secret_name: Option<String>,
         label_selector: &BTreeMap<String, String>,
         validator_index: usize,
-    ) -> Result<ReplicaSet, Box<dyn Error>> {
+    ) -> Result<ReplicaSet, LabError> {
         let mut env_vars = vec![self.set_namespace_environment_variable()];
         if self.metrics.is_some() {
             env_vars.push(self.get_metrics_env_var_secret())
@@ -583,25 +696,29 @@ impl<'a> Kubernetes<'a> {
         env_vars.append(&mut self.set_non_bootstrap_environment_variables());
         env_vars.append(&mut self.set_load_balancer_environment_variables());
 
-        let accounts_volume = Some(vec![Volume {
+        let accounts_volume = vec![Volume {
             name: format!("validator-accounts-volume-{validator_index}"),
             secret: Some(SecretVolumeSource {
                 secret_name,
                 ..Default::default()
             }),
             ..Default::default()
-        }]);
+        }];
 
-        let accounts_volume_mount = Some(vec![VolumeMount {
+        let accounts_volume_mount = vec![VolumeMount {
             name: format!("validator-accounts-volume-{validator_index}"),
             mount_path: "/home/solana/validator-accounts".to_string(),
             ..Default::default()
-        }]);
+        }];
 
-        let mut command =
-            vec!["/home/solana/k8s-cluster-scripts/validator-startup-script.sh".to_string()];
-        command.extend(self.generate_validator_command_flags());
+        let mut command = vec![format!(
+            "{STARTUP_SCRIPTS_DIR}/{}",
+            StartupScripts::file_name(&NodeType::Standard)
+        )];
+        command.extend(self.generate_validator_command_flags(validator_index));
 
+        let (volumes, volume_mounts) =
+            self.with_startup_scripts(accounts_volume, accounts_volume_mount);
         k8s_helpers::create_replica_set(
             format!("{}-{}-{}", image.node_type(), image.tag(), validator_index),
             self.namespace.clone(),
@@ -609,10 +726,12 @@ impl<'a> Kubernetes<'a> {
             image.clone(),
             env_vars,
             command.clone(),
-            accounts_volume,
-            accounts_volume_mount,
+            volumes,
+            volume_mounts,
             self.pod_requests.requests.clone(),
             None,
+            self.node_placement.scheduling(&image.node_type()),
+            self.image_pull_policy,
         )
     }
 
@@ -636,7 +755,7 @@ impl<'a> Kubernetes<'a> {
         secret_name: Option<String>,
         label_selector: &BTreeMap<String, String>,
         rpc_index: usize,
-    ) -> Result<ReplicaSet, Box<dyn Error>> {
+    ) -> Result<ReplicaSet, LabError> {
         let mut env_vars = vec![EnvVar {
             name: "MY_POD_IP".to_string(),
             value_from: Some(EnvVarSource {
@@ -656,23 +775,25 @@ impl<'a> Kubernetes<'a> {
             env_vars.push(self.get_metrics_env_var_secret())
         }
 
-        let accounts_volume = Some(vec![Volume {
+        let accounts_volume = vec![Volume {
             name: format!("rpc-node-accounts-volume-{}", rpc_index),
             secret: Some(SecretVolumeSource {
                 secret_name,
                 ..Default::default()
             }),
             ..Default::default()
-        }]);
+        }];
 
-        let accounts_volume_mount = Some(vec![VolumeMount {
+        let accounts_volume_mount = vec![VolumeMount {
             name: format!("rpc-node-accounts-volume-{}", rpc_index),
             mount_path: "/home/solana/rpc-node-accounts".to_string(),
             ..Default::default()
-        }]);
+        }];
 
-        let mut command =
-            vec!["/home/solana/k8s-cluster-scripts/rpc-node-startup-script.sh".to_string()];
+        let mut command = vec![format!(
+            "{STARTUP_SCRIPTS_DIR}/{}",
+            StartupScripts::file_name(&NodeType::RPC)
+        )];
         command.extend(self.generate_rpc_command_flags());
 
         let exec_action = ExecAction {
@@ -692,6 +813,8 @@ impl<'a> Kubernetes<'a> {
             ..Default::default()
         };
 
+        let (volumes, volume_mounts) =
+            self.with_startup_scripts(accounts_volume, accounts_volume_mount);
         k8s_helpers::create_replica_set(
             format!("{}-{}-{}", image.node_type(), image.tag(), rpc_index),
             self.namespace.clone(),
@@ -699,10 +822,12 @@ impl<'a> Kubernetes<'a> {
             image.clone(),
             env_vars,
             command.clone(),
-            accounts_volume,
-            accounts_volume_mount,
+            volumes,
+            volume_mounts,
             self.pod_requests.requests.clone(),
             Some(readiness_probe),
+            self.node_placement.scheduling(&image.node_type()),
+            self.image_pull_policy,
         )
     }
 
@@ -712,7 +837,7 @@ impl<'a> Kubernetes<'a> {
         secret_name: Option<String>,
         label_selector: &BTreeMap<String, String>,
         client_index: usize,
-    ) -> Result<ReplicaSet, Box<dyn Error>> {
+    ) -> Result<ReplicaSet, LabError> {
         let mut env_vars = vec![self.set_namespace_environment_variable()];
         if self.metrics.is_some() {
             env_vars.push(self.get_metrics_env_var_secret())
@@ -720,23 +845,29 @@ impl<'a> Kubernetes<'a> {
         env_vars.append(&mut self.set_non_bootstrap_environment_variables());
         env_vars.append(&mut self.set_load_balancer_environment_variables());
 
-        let accounts_volume = Some(vec![Volume {
+        let accounts_volume = vec![Volume {
             name: format!("client-accounts-volume-{}", client_index),
             secret: Some(SecretVolumeSource {
                 secret_name,
                 ..Default::default()
             }),
             ..Default::default()
-        }]);
+        }];
 
-        let accounts_volume_mount = Some(vec![VolumeMount {
+        let accounts_volume_mount = vec![VolumeMount {
             name: format!("client-accounts-volume-{}", client_index),
             mount_path: "/home/solana/client-accounts".to_string(),
             ..Default::default()
-        }]);
+        }];
 
         let command = self.client_config.build_command()?;
 
+        // generic clients bring their own image and do not run our startup scripts
+        let (volumes, volume_mounts) = match self.client_config {
+            ClientConfig::Generic(_) => (Some(accounts_volume), Some(accounts_volume_mount)),
+            _ => self.with_startup_scripts(accounts_volume, accounts_volume_mount),
+        };
+
         k8s_helpers::create_replica_set(
             format!("{}-{}-{}", image.node_type(), image.tag(), client_index),
             self.namespace.clone(),
@@ -744,14 +875,16 @@ impl<'a> Kubernetes<'a> {
             image.clone(),
             env_vars,
             command,
-            accounts_volume,
-            accounts_volume_mount,
+            volumes,
+            volume_mounts,
             self.pod_requests.requests.clone(),
             None,
+            self.node_placement.scheduling(&image.node_type()),
+            self.image_pull_policy,
         )
     }
 
-    async fn get_open_external_port_for_rpc_service(&self) -> Result<i32, Box<dyn Error>> {
+    async fn get_open_external_port_for_rpc_service(&self) -> Result<i32, LabError> {
         let used_ports = self.get_all_used_ports().await?;
 
         // This Node Port range is standard for kubernetes
@@ -763,28 +896,21 @@ impl<'a> Kubernetes<'a> {
             available_port += 1;
         }
         if available_port > MAX_NODE_PORT {
-            return Err(format!(
+            return Err(LabError::kube(format!(
                 "No available NodePort found in the range {MIN_NODE_PORT}-{MAX_NODE_PORT}"
-            )
-            .into());
+            )));
         }
 
         Ok(available_port)
     }
 
-    async fn get_all_used_ports(&self) -> Result<HashSet<i32>, kube::Error> {
+    async fn get_all_used_ports(&self) -> Result<HashSet<i32>, LabError> {
         let mut used_ports = HashSet::new();
-        let namespaces = self.get_namespaces().await?;
-        let namespaces: Vec<String> = namespaces
-            .items
-            .into_iter()
-            .filter_map(|ns| ns.metadata.name)
-            .collect();
+        let namespaces = self.backend.list_namespaces().await?;
 
         // Iterate over namespaces to collect used NodePorts
         for ns in namespaces {
-            let services: Api<Service> = Api::namespaced(self.k8s_client.clone(), &ns);
-            let service_list = services.list(&Default::default()).await?;
+            let service_list = self.backend.list_services(&ns).await?;
             for svc in service_list {
                 if let Some(spec) = svc.spec {
                     if let Some(ports) = spec.ports {
@@ -800,3 +926,242 @@ impl<'a> Kubernetes<'a> {
         Ok(used_ports)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            client_config::{BenchTpsConfig, GenericClientConfig},
+            kube_backend::InMemoryBackend,
+        },
+        k8s_openapi::api::core::v1::{ServicePort, ServiceSpec},
+        kube::api::ObjectMeta,
+        std::fs,
+        tempfile::TempDir,
+    };
+
+    const NAMESPACE: &str = "lab";
+    const TAG: &str = "v1-18-15";
+
+    fn image(node_type: NodeType) -> DockerImage {
+        DockerImage::new(
+            "registry".to_string(),
+            node_type,
+            "k8s-image".to_string(),
+            TAG.to_string(),
+        )
+    }
+
+    fn kubernetes<'a>(
+        backend: &'a InMemoryBackend,
+        validator_config: &'a mut ValidatorConfig,
+    ) -> Kubernetes<'a> {
+        Kubernetes::new(
+            backend,
+            NAMESPACE,
+            validator_config,
+            ClientConfig::None,
+            PodRequests::new("1".to_string(), "1Gi".to_string()),
+            NodePlacement::default(),
+            None,
+            TAG.to_string(),
+        )
+    }
+
+    fn node_port_service(name: &str, node_port: i32) -> Service {
+        Service {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            spec: Some(ServiceSpec {
+                ports: Some(vec![ServicePort {
+                    port: 8899,
+                    node_port: Some(node_port),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validator_command_flags() {
+        let flag_value = |flags: &[String], flag: &str| {
+            flags
+                .iter()
+                .position(|arg| arg == flag)
+                .map(|position| flags[position + 1].clone())
+        };
+        let mut validator_config = ValidatorConfig {
+            internal_node_sol: 100.0,
+            validator_stakes: vec![10.0, 25.5],
+            ..ValidatorConfig::default()
+        };
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+
+        let flags = kubernetes(&backend, &mut validator_config).generate_validator_command_flags(1);
+        assert_eq!(
+            flag_value(&flags, "--internal-node-stake-sol").as_deref(),
+            Some("25.5")
+        );
+        // airdropped validators get their stake on top of the internal node SOL
+        assert_eq!(
+            flag_value(&flags, "--internal-node-sol").as_deref(),
+            Some("125.5")
+        );
+        assert!(!flags.iter().any(|arg| arg == NO_AIRDROP_FLAG));
+
+        validator_config.accounts_in_genesis = true;
+        let flags = kubernetes(&backend, &mut validator_config).generate_validator_command_flags(0);
+        assert_eq!(
+            flag_value(&flags, "--internal-node-sol").as_deref(),
+            Some("110")
+        );
+        assert!(flags.iter().any(|arg| arg == NO_AIRDROP_FLAG));
+    }
+
+    #[tokio::test]
+    async fn test_namespace_exists() {
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&["other"]);
+        assert!(!kubernetes(&backend, &mut validator_config)
+            .namespace_exists()
+            .await
+            .unwrap());
+
+        let backend = InMemoryBackend::new(&["other", NAMESPACE]);
+        assert!(kubernetes(&backend, &mut validator_config)
+            .namespace_exists()
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_load_balancer_skips_node_ports_in_all_namespaces() {
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        backend.add_existing_service(NAMESPACE, node_port_service("a", 30000));
+        backend.add_existing_service("other", node_port_service("b", 30001));
+        backend.add_existing_service("other", node_port_service("c", 30003));
+
+        let kub_controller = kubernetes(&backend, &mut validator_config);
+        let selector = kub_controller.create_selector("load-balancer/name", "selector");
+        let load_balancer = kub_controller
+            .create_validator_load_balancer(LOAD_BALANCER_SERVICE, &selector)
+            .await
+            .unwrap();
+
+        let ports = load_balancer.spec.unwrap().ports.unwrap();
+        assert_eq!(ports[0].node_port, Some(30002));
+    }
+
+    #[tokio::test]
+    async fn test_deploy_secret_rejects_duplicates() {
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        let kub_controller = kubernetes(&backend, &mut validator_config);
+        let secret = k8s_helpers::create_secret("secret".to_string(), BTreeMap::new()).unwrap();
+
+        kub_controller.deploy_secret(&secret).await.unwrap();
+        assert!(kub_controller.deploy_secret(&secret).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redeploy_replaces_startup_scripts() {
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        let kub_controller = kubernetes(&backend, &mut validator_config);
+        let scripts = |script: &str| {
+            BTreeMap::from([(
+                StartupScripts::file_name(&NodeType::Standard),
+                script.to_string(),
+            )])
+        };
+
+        // a tagged teardown keeps the scripts while the tag's bootstrap runs
+        let config_map = kub_controller.create_startup_scripts_config_map(scripts("echo 1"));
+        kub_controller.deploy_config_map(&config_map).await.unwrap();
+        let config_map = kub_controller.create_startup_scripts_config_map(scripts("echo 2"));
+        kub_controller.deploy_config_map(&config_map).await.unwrap();
+
+        let objects = backend.objects();
+        let key = (
+            NAMESPACE.to_string(),
+            format!("{STARTUP_SCRIPTS_CONFIG_MAP}-{TAG}"),
+        );
+        assert_eq!(objects.config_maps.len(), 1);
+        assert_eq!(objects.config_maps[&key].data, Some(scripts("echo 2")));
+    }
+
+    #[tokio::test]
+    async fn test_replica_set_readiness() {
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&[NAMESPACE]).with_new_replica_sets_ready(false);
+        let mut kub_controller = kubernetes(&backend, &mut validator_config);
+
+        let replica_set = kub_controller
+            .create_rpc_replica_set(&image(NodeType::RPC), None, &BTreeMap::new(), 0)
+            .unwrap();
+        kub_controller
+            .deploy_replicas_set(&replica_set)
+            .await
+            .unwrap();
+
+        let name = format!("rpc-node-{TAG}-0-replicaset");
+        assert!(!kub_controller.is_replica_set_ready(&name).await.unwrap());
+        backend.set_replica_set_ready(NAMESPACE, &name).unwrap();
+        assert!(kub_controller.is_replica_set_ready(&name).await.unwrap());
+        assert!(kub_controller
+            .is_replica_set_ready("missing")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_image_pull_policy() {
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        let mut kub_controller = kubernetes(&backend, &mut validator_config);
+        let pull_policy = |kub_controller: &mut Kubernetes| {
+            kub_controller
+                .create_rpc_replica_set(&image(NodeType::RPC), None, &BTreeMap::new(), 0)
+                .unwrap()
+                .spec
+                .and_then(|spec| spec.template)
+                .and_then(|template| template.spec)
+                .and_then(|spec| spec.containers[0].image_pull_policy.clone())
+                .unwrap()
+        };
+        assert_eq!(pull_policy(&mut kub_controller), "Always");
+        kub_controller.set_image_distribution(ImageDistribution::Kind);
+        assert_eq!(pull_policy(&mut kub_controller), "IfNotPresent");
+        // the ledger image changes under the same tag, so registries are always pulled from
+        kub_controller.set_image_distribution(ImageDistribution::LocalRegistry);
+        assert_eq!(pull_policy(&mut kub_controller), "Always");
+    }
+
+    #[test]
+    fn test_bench_tps_client_secret_requires_accounts() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path();
+        fs::create_dir_all(config_dir.join("bootstrap-validator")).unwrap();
+        fs::write(config_dir.join("faucet.json"), b"[0]").unwrap();
+        fs::write(config_dir.join("bootstrap-validator/identity.json"), b"[1]").unwrap();
+
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        let mut kub_controller = kubernetes(&backend, &mut validator_config);
+        // generic clients have no bench-tps accounts
+        kub_controller.client_config = ClientConfig::Generic(GenericClientConfig::default());
+        assert!(kub_controller.create_client_secret(0, config_dir).is_ok());
+
+        kub_controller.client_config = ClientConfig::BenchTps(BenchTpsConfig::default());
+        assert!(kub_controller.create_client_secret(0, config_dir).is_err());
+        fs::write(config_dir.join("bench-tps-0.yml"), b"accounts").unwrap();
+        let secret = kub_controller.create_client_secret(0, config_dir).unwrap();
+        assert!(secret.data.unwrap().contains_key("client-accounts.yml"));
+    }
+}
@@ -1,17 +1,15 @@
 use {
-    crate::genesis::DEFAULT_MAX_GENESIS_ARCHIVE_UNPACKED_SIZE,
+    crate::{error::LabError, genesis::DEFAULT_MAX_GENESIS_ARCHIVE_UNPACKED_SIZE},
     solana_accounts_db::hardened_unpack::open_genesis_config,
     solana_sdk::shred_version::compute_shred_version,
-    std::{error::Error, path::Path},
+    std::path::Path,
 };
 
-fn ledger_directory_exists(ledger_dir: &Path) -> Result<(), Box<dyn Error>> {
+fn ledger_directory_exists(ledger_dir: &Path) -> Result<(), LabError> {
     if !ledger_dir.exists() {
-        return Err(
-            "Ledger Directory does not exist, have you created genesis yet??"
-                .to_string()
-                .into(),
-        );
+        return Err(LabError::genesis(
+            "Ledger Directory does not exist, have you created genesis yet??",
+        ));
     }
     Ok(())
 }
@@ -19,11 +17,19 @@ fn ledger_directory_exists(ledger_dir: &Path) -> Result<(), Box<dyn Error>> {
 pub struct LedgerHelper {}
 
 impl LedgerHelper {
-    pub fn get_shred_version(ledger_dir: &Path) -> Result<u16, Box<dyn Error>> {
+    pub fn get_shred_version(ledger_dir: &Path) -> Result<u16, LabError> {
         ledger_directory_exists(ledger_dir)?;
         let genesis_config =
-            open_genesis_config(ledger_dir, DEFAULT_MAX_GENESIS_ARCHIVE_UNPACKED_SIZE);
-        let shred_version = compute_shred_version(&genesis_config?.hash(), None);
+            open_genesis_config(ledger_dir, DEFAULT_MAX_GENESIS_ARCHIVE_UNPACKED_SIZE).map_err(
+                |err| {
+                    LabError::genesis(format!(
+                        "Failed to open the genesis config in {}",
+                        ledger_dir.display()
+                    ))
+                    .with_source(err)
+                },
+            )?;
+        let shred_version = compute_shred_version(&genesis_config.hash(), None);
         Ok(shred_version)
     }
 }
@@ -1,12 +1,14 @@
 use {
+    crate::error::LabError,
     bzip2::bufread::BzDecoder,
     console::Emoji,
     indicatif::{ProgressBar, ProgressStyle},
     log::*,
     reqwest::Client,
+    serde::{Deserialize, Deserializer},
     std::{
         fs::File,
-        io::{BufReader, Cursor, Read, Write},
+        io::{BufReader, Cursor, Read},
         path::{Path, PathBuf},
         time::Duration,
     },
@@ -15,7 +17,6 @@ use {
     url::Url,
 };
 
-const UPGRADEABLE_LOADER: &str = "BPFLoaderUpgradeab1e11111111111111111111111";
 pub const SOLANA_RELEASE: &str = "solana-release";
 
 #[derive(Clone, Debug)]
@@ -38,13 +39,6 @@ impl ClusterDataRoot {
     }
 }
 
-struct GenesisProgram<'a> {
-    name: &'a str,
-    version: &'a str,
-    address: &'a str,
-    loader: &'a str,
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Display)]
 pub enum ClientType {
     #[strum(serialize = "bench-tps-client")]
@@ -76,7 +70,8 @@ impl NodeType {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Metrics {
     pub host: String,
     pub port: String,
@@ -109,16 +104,33 @@ impl Metrics {
     }
 }
 
+pub mod artifact_cache;
 pub mod client_config;
 pub mod cluster_images;
+pub mod cluster_rpc;
+pub mod cluster_spec;
+pub mod cluster_status;
+pub mod deploy;
 pub mod docker;
+pub mod error;
+pub mod feature_set;
 pub mod genesis;
+pub mod genesis_programs;
+pub mod image_builder;
 pub mod k8s_helpers;
+pub mod kube_backend;
 pub mod kubernetes;
 pub mod ledger_helper;
+pub mod log_collector;
+pub mod manifest_writer;
 pub mod node;
+pub mod node_placement;
+pub mod readiness;
 pub mod release;
+pub mod scale;
+pub mod stake_distribution;
 pub mod startup_scripts;
+pub mod teardown;
 pub mod validator_config;
 
 static BUILD: Emoji = Emoji("👷 ", "");
@@ -153,30 +165,41 @@ pub fn cat_file(path: &PathBuf) -> std::io::Result<()> {
 pub async fn download_to_temp(
     url: &str,
     file_path: &Path, // full path to file including filename
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), LabError> {
     let progress_bar = new_spinner_progress_bar();
     progress_bar.set_message(format!("{TRUCK}Downloading..."));
 
-    let url = Url::parse(url).map_err(|err| format!("Unable to parse {url}: {err}"))?;
+    let url = Url::parse(url)
+        .map_err(|err| LabError::download(format!("Unable to parse {url}")).with_source(err))?;
+    let request_failed = |err: reqwest::Error| {
+        LabError::download(format!("Request to {url} failed")).with_source(err)
+    };
 
     let client = Client::builder()
         .connect_timeout(Duration::from_secs(30))
         .https_only(false)
-        .build()?;
-
-    let response = client.get(url.clone()).send().await?;
+        .build()
+        .map_err(request_failed)?;
+
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(request_failed)?;
     if !response.status().is_success() {
-        return Err(format!(
+        return Err(LabError::download(format!(
             "Failed to download release from url: {:?}, response body: {:?}",
             url.to_string(),
-            response.text().await?
-        )
-        .into());
+            response.text().await.map_err(request_failed)?
+        )));
     }
 
-    let mut out = File::create(file_path).expect("failed to create file");
-    let mut content = Cursor::new(response.bytes().await?);
-    std::io::copy(&mut content, &mut out)?;
+    let write_failed = |err: std::io::Error| {
+        LabError::download(format!("Failed to write {}", file_path.display())).with_source(err)
+    };
+    let mut out = File::create(file_path).map_err(write_failed)?;
+    let mut content = Cursor::new(response.bytes().await.map_err(request_failed)?);
+    std::io::copy(&mut content, &mut out).map_err(write_failed)?;
 
     progress_bar.finish_and_clear();
     Ok(())
@@ -185,113 +208,30 @@ pub async fn download_to_temp(
 pub fn extract_release_archive(
     tarball_filename: &Path,
     extract_dir: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), LabError> {
     let progress_bar = new_spinner_progress_bar();
     progress_bar.set_message(format!("{PACKAGE}Extracting..."));
 
-    let tarball_file = File::open(tarball_filename)?;
+    let extract_failed = |err: std::io::Error| {
+        LabError::download(format!(
+            "Unable to extract {} into {}",
+            tarball_filename.display(),
+            extract_dir.display()
+        ))
+        .with_source(err)
+    };
+    let tarball_file = File::open(tarball_filename).map_err(extract_failed)?;
     let decompressed = BzDecoder::new(BufReader::new(tarball_file));
     let mut archive = Archive::new(decompressed);
 
     // Unpack the archive into extract_dir
-    archive.unpack(extract_dir)?;
+    archive.unpack(extract_dir).map_err(extract_failed)?;
 
     progress_bar.finish_and_clear();
 
     Ok(())
 }
 
-async fn fetch_program(
-    name: &str,
-    version: &str,
-    solana_root_path: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let name_with_underscores = name.replace('-', "_");
-    let so_filename = format!("spl_{name_with_underscores}-{version}.so");
-    let download_path = solana_root_path.join(&so_filename);
-    let so_name = format!("spl_{name_with_underscores}.so");
-
-    if !download_path.exists() {
-        info!("Downloading {name} {version}");
-        let url = format!(
-            "https://github.com/solana-labs/solana-program-library/releases/download/{}-v{}/{}",
-            name, version, so_name
-        );
-
-        download_to_temp(&url, &download_path)
-            .await
-            .map_err(|err| format!("Unable to download {url}. Error: {err}"))?;
-    }
-
-    Ok(())
-}
-
-pub async fn fetch_spl(solana_root_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    let mut genesis_args = vec![];
-
-    let programs = vec![
-        GenesisProgram {
-            name: "token",
-            version: "3.5.0",
-            address: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
-            loader: "BPFLoader2111111111111111111111111111111111",
-        },
-        GenesisProgram {
-            name: "token-2022",
-            version: "1.0.0",
-            address: "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
-            loader: UPGRADEABLE_LOADER,
-        },
-        GenesisProgram {
-            name: "memo",
-            version: "1.0.0",
-            address: "Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo",
-            loader: "BPFLoader1111111111111111111111111111111111",
-        },
-        GenesisProgram {
-            name: "associated-token-account",
-            version: "1.1.2",
-            address: "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL",
-            loader: "BPFLoader2111111111111111111111111111111111",
-        },
-        GenesisProgram {
-            name: "feature-proposal",
-            version: "1.0.0",
-            address: "Feat1YXHhH6t1juaWF74WLcfv4XoNocjXA6sPWHNgAse",
-            loader: "BPFLoader2111111111111111111111111111111111",
-        },
-    ];
-
-    for program in programs {
-        fetch_program(program.name, program.version, solana_root_path).await?;
-
-        let arg = if program.loader == UPGRADEABLE_LOADER {
-            format!(
-                "--upgradeable-program {} {} spl_{}-{}.so none",
-                program.address,
-                program.loader,
-                program.name.replace('-', "_"),
-                program.version
-            )
-        } else {
-            format!(
-                "--bpf-program {} {} spl_{}-{}.so",
-                program.address,
-                program.loader,
-                program.name.replace('-', "_"),
-                program.version
-            )
-        };
-        genesis_args.push(arg);
-    }
-
-    // Write genesis args to file
-    let mut file = std::fs::File::create(solana_root_path.join("spl-genesis-args.sh"))?;
-    writeln!(file, "{}", genesis_args.join(" "))?;
-
-    Ok(())
-}
-
 pub fn parse_and_format_transparent_args(bench_tps_args: Option<&str>) -> Vec<String> {
     if let Some(args) = bench_tps_args {
         let mut val_args: Vec<_> = args
@@ -312,13 +252,46 @@ pub fn parse_and_format_transparent_args(bench_tps_args: Option<&str>) -> Vec<St
     }
 }
 
-pub fn check_directory(path: &Path, description: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Deserializes transparent args from the same `'key=value flag'` string used on the command line
+pub fn deserialize_transparent_args<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let args = String::deserialize(deserializer)?;
+    Ok(parse_and_format_transparent_args(Some(&args)))
+}
+
+pub fn check_directory(path: &Path, description: &str) -> Result<(), LabError> {
     if let Ok(metadata) = std::fs::metadata(path) {
         if !metadata.is_dir() {
-            return Err(format!("{} is not a directory: {}", description, path.display()).into());
+            return Err(LabError::config(format!(
+                "{} is not a directory: {}",
+                description,
+                path.display()
+            )));
         }
     } else {
-        return Err(format!("{} directory not found: {}", description, path.display()).into());
+        return Err(LabError::config(format!(
+            "{} directory not found: {}",
+            description,
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Hard links the files of `source` into `destination`, copying where links are not possible,
+/// e.g. across filesystems. Builds are replaced, never written in place, so links are safe
+pub fn link_or_copy_dir(source: &Path, destination: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            link_or_copy_dir(&entry.path(), &target)?;
+        } else if std::fs::hard_link(entry.path(), &target).is_err() {
+            std::fs::copy(entry.path(), &target)?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,263 @@
+use {
+    crate::{
+        error::LabError,
+        kube_backend::KubeBackend,
+        node::{
+            CLIENT_NAME_LABEL, DEPLOYMENT_TAG_LABEL, RPC_NODE_NAME_LABEL, VALIDATOR_NAME_LABEL,
+            VALIDATOR_TYPE_LABEL,
+        },
+        NodeType,
+    },
+    k8s_openapi::api::core::v1::Pod,
+    log::*,
+    std::{
+        collections::HashSet,
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Where a pod's logs are written: `<logs_dir>/<deployment_tag>/<file_stem>.log`
+#[derive(Debug)]
+struct PodLogTarget {
+    pod_name: String,
+    deployment_tag: String,
+    /// `<node-type>-<index>`, or just `bootstrap-validator`
+    file_stem: String,
+    restarted: bool,
+}
+
+impl PodLogTarget {
+    /// Returns None for pods we did not deploy
+    fn from_pod(pod: &Pod) -> Option<Self> {
+        let labels = pod.metadata.labels.as_ref()?;
+        let file_stem =
+            if labels.get(VALIDATOR_TYPE_LABEL) == Some(&NodeType::Bootstrap.to_string()) {
+                NodeType::Bootstrap.to_string()
+            } else {
+                [VALIDATOR_NAME_LABEL, RPC_NODE_NAME_LABEL, CLIENT_NAME_LABEL]
+                    .iter()
+                    .find_map(|key| labels.get(*key))?
+                    .clone()
+            };
+
+        // pods deployed before the deployment tag label was added carry the tag in their image
+        let deployment_tag = labels.get(DEPLOYMENT_TAG_LABEL).cloned().or_else(|| {
+            pod.spec
+                .as_ref()?
+                .containers
+                .first()?
+                .image
+                .as_ref()?
+                .rsplit_once(':')
+                .map(|(_, tag)| tag.replace('.', "-"))
+        })?;
+
+        let restarted = pod
+            .status
+            .as_ref()
+            .and_then(|status| status.container_statuses.as_ref())
+            .map(|statuses| statuses.iter().any(|status| status.restart_count > 0))
+            .unwrap_or(false);
+
+        Some(Self {
+            pod_name: pod.metadata.name.clone()?,
+            deployment_tag,
+            file_stem,
+            restarted,
+        })
+    }
+}
+
+/// Downloads the container logs of every pod we deployed in a namespace.
+/// Containers that restarted also get their previous container's logs in `<file_stem>.previous.log`
+pub struct LogCollector<'a> {
+    backend: &'a dyn KubeBackend,
+    namespace: String,
+    logs_dir: PathBuf,
+    deployment_tag: Option<String>,
+}
+
+impl<'a> LogCollector<'a> {
+    /// With a deployment tag, only that deployment's pods are collected
+    pub fn new(
+        backend: &'a dyn KubeBackend,
+        namespace: &str,
+        cluster_data_path: &Path,
+        deployment_tag: Option<String>,
+    ) -> Self {
+        Self {
+            backend,
+            namespace: namespace.to_string(),
+            logs_dir: cluster_data_path.join("logs"),
+            deployment_tag,
+        }
+    }
+
+    fn write(&self, path: &Path, logs: &str) -> Result<(), LabError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|err| {
+                LabError::logs(format!("Failed to create {}", parent.display())).with_source(err)
+            })?;
+        }
+        fs::write(path, logs).map_err(|err| {
+            LabError::logs(format!("Failed to write {}", path.display())).with_source(err)
+        })
+    }
+
+    /// Returns the log files written. Pods whose logs cannot be fetched, e.g. ones still pending,
+    /// are skipped with a warning so one bad pod does not lose the logs of the rest
+    pub async fn collect(&self) -> Result<Vec<PathBuf>, LabError> {
+        let mut targets: Vec<PodLogTarget> = self
+            .backend
+            .list_pods(&self.namespace)
+            .await?
+            .iter()
+            .filter_map(PodLogTarget::from_pod)
+            .filter(|target| match &self.deployment_tag {
+                Some(tag) => &target.deployment_tag == tag,
+                None => true,
+            })
+            .collect();
+        targets.sort_by(|a, b| a.pod_name.cmp(&b.pod_name));
+
+        let mut written = vec![];
+        let mut used_stems = HashSet::new();
+        for target in targets {
+            let dir = self.logs_dir.join(&target.deployment_tag);
+            // a ReplicaSet can briefly have two pods while one replaces the other
+            let stem = if used_stems.insert(dir.join(&target.file_stem)) {
+                target.file_stem.clone()
+            } else {
+                format!("{}-{}", target.file_stem, target.pod_name)
+            };
+
+            let mut fetches = vec![(false, dir.join(format!("{stem}.log")))];
+            if target.restarted {
+                fetches.push((true, dir.join(format!("{stem}.previous.log"))));
+            }
+            for (previous, path) in fetches {
+                match self
+                    .backend
+                    .get_pod_logs(&self.namespace, &target.pod_name, previous)
+                    .await
+                {
+                    Ok(logs) => {
+                        self.write(&path, &logs)?;
+                        info!("Wrote logs of {} to {}", target.pod_name, path.display());
+                        written.push(path);
+                    }
+                    Err(err) => warn!("Skipping logs of {}: {err}", target.pod_name),
+                }
+            }
+        }
+
+        info!(
+            "Collected {} log file(s) into {}",
+            written.len(),
+            self.logs_dir.display()
+        );
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::kube_backend::InMemoryBackend,
+        k8s_openapi::api::core::v1::{ContainerStatus, PodStatus},
+        kube::api::ObjectMeta,
+        std::collections::BTreeMap,
+        tempfile::TempDir,
+    };
+
+    const NAMESPACE: &str = "test-namespace";
+
+    fn pod(name: &str, labels: &[(&str, &str)], restart_count: i32) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                labels: Some(
+                    labels
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect::<BTreeMap<_, _>>(),
+                ),
+                ..Default::default()
+            },
+            status: Some(PodStatus {
+                container_statuses: Some(vec![ContainerStatus {
+                    restart_count,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_writes_logs_by_tag_and_node() {
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        backend.add_pod(
+            NAMESPACE,
+            pod(
+                "bootstrap-validator-v1-18-20-replicaset-abcde",
+                &[
+                    (VALIDATOR_TYPE_LABEL, "bootstrap-validator"),
+                    (DEPLOYMENT_TAG_LABEL, "v1-18-20"),
+                ],
+                0,
+            ),
+            "bootstrap logs",
+        );
+        backend.add_pod(
+            NAMESPACE,
+            pod(
+                "validator-abcd1234-1-replicaset-fghij",
+                &[
+                    (VALIDATOR_TYPE_LABEL, "validator"),
+                    (VALIDATOR_NAME_LABEL, "validator-1"),
+                    (DEPLOYMENT_TAG_LABEL, "abcd1234"),
+                ],
+                0,
+            ),
+            "validator logs",
+        );
+        backend.add_pod(NAMESPACE, pod("unrelated-pod", &[("app", "other")], 0), "");
+
+        let temp_dir = TempDir::new().unwrap();
+        let cluster_data_path = temp_dir.path();
+        let written = LogCollector::new(&backend, NAMESPACE, cluster_data_path, None)
+            .collect()
+            .await
+            .unwrap();
+
+        let logs_dir = cluster_data_path.join("logs");
+        assert_eq!(
+            written,
+            vec![
+                logs_dir.join("v1-18-20/bootstrap-validator.log"),
+                logs_dir.join("abcd1234/validator-1.log"),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(logs_dir.join("abcd1234/validator-1.log")).unwrap(),
+            "validator logs"
+        );
+
+        // only the tagged deployment's pods
+        fs::remove_dir_all(&logs_dir).unwrap();
+        let written = LogCollector::new(
+            &backend,
+            NAMESPACE,
+            cluster_data_path,
+            Some("abcd1234".to_string()),
+        )
+        .collect()
+        .await
+        .unwrap();
+        assert_eq!(written, vec![logs_dir.join("abcd1234/validator-1.log")]);
+    }
+}
@@ -1,29 +1,51 @@
 use {
-    clap::{command, value_t_or_exit, Arg, ArgGroup, SubCommand},
+    clap::{command, parser::ValueSource, value_t_or_exit, Arg, ArgGroup, ArgMatches, SubCommand},
     log::*,
-    solana_clap_v3_utils::input_parsers::pubkey_of,
+    solana_clap_v3_utils::input_parsers::{pubkey_of, pubkeys_of},
     solana_ledger::blockstore_cleanup_service::{
         DEFAULT_MAX_LEDGER_SHREDS, DEFAULT_MIN_MAX_LEDGER_SHREDS,
     },
-    solana_sdk::{signature::keypair::read_keypair_file, signer::Signer},
-    std::{path::PathBuf, result::Result},
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+        result::Result,
+        str::FromStr,
+        time::Duration,
+    },
     strum::VariantNames,
     validator_lab::{
+        artifact_cache::{program_key, release_key, ArtifactCache, DEFAULT_ARTIFACT_CACHE_DIR},
         check_directory,
         client_config::{BenchTpsConfig, ClientConfig, GenericClientConfig},
         cluster_images::ClusterImages,
+        cluster_spec::{ClusterSpec, DeploymentSpec},
+        cluster_status::ClusterStatus,
+        deploy::{check_namespace, deploy_nodes, NodeDeployment, ReadinessRpc},
         docker::{DockerConfig, DockerImage},
+        feature_set::resolve_deactivated_features,
         genesis::{
-            Genesis, GenesisFlags, DEFAULT_BOOTSTRAP_NODE_SOL, DEFAULT_BOOTSTRAP_NODE_STAKE_SOL,
-            DEFAULT_CLIENT_LAMPORTS_PER_SIGNATURE, DEFAULT_FAUCET_LAMPORTS,
-            DEFAULT_INTERNAL_NODE_SOL, DEFAULT_INTERNAL_NODE_STAKE_SOL,
+            Genesis, GenesisFlags, KeygenSeed, DEFAULT_BOOTSTRAP_NODE_SOL,
+            DEFAULT_BOOTSTRAP_NODE_STAKE_SOL, DEFAULT_CLIENT_LAMPORTS_PER_SIGNATURE,
+            DEFAULT_FAUCET_LAMPORTS, DEFAULT_INTERNAL_NODE_SOL, DEFAULT_INTERNAL_NODE_STAKE_SOL,
             DEFAULT_MAX_GENESIS_ARCHIVE_UNPACKED_SIZE,
         },
+        genesis_programs::{load_genesis_programs, GenesisProgram},
+        image_builder::{new_image_builder, ImageBuilderKind, ImageDistribution, LOCAL_REGISTRY},
+        kube_backend::{ClusterBackend, KubeBackend},
         kubernetes::{Kubernetes, PodRequests},
         ledger_helper::LedgerHelper,
-        node::{LabelType, Node},
+        log_collector::LogCollector,
+        manifest_writer::ManifestWriter,
+        node::Node,
+        node_placement::{NodePlacement, TolerationSpec, DEFAULT_REGION_LABEL},
         parse_and_format_transparent_args,
-        release::{BuildConfig, BuildType, DeployMethod},
+        readiness::{Readiness, DEFAULT_READINESS_TIMEOUT_SECS},
+        release::{BuildConfig, BuildOptions, BuildType, DeployMethod, DEFAULT_BUILD_CACHE_DIR},
+        scale::{Scaler, StakeDeactivator},
+        stake_distribution::StakeDistribution,
+        startup_scripts::StartupScripts,
+        teardown::Teardown,
         validate_docker_image,
         validator_config::ValidatorConfig,
         ClientType, ClusterDataRoot, EnvironmentConfig, Metrics, NodeType, SOLANA_RELEASE,
@@ -31,7 +53,28 @@ use {
 };
 
 fn parse_matches() -> clap::ArgMatches {
+    let matches = get_cluster_matches(true);
+    match matches.subcommand_name() {
+        Some("teardown")
+        | Some("status")
+        | Some("collect-logs")
+        | Some("scale")
+        | Some("cache-artifact") => matches,
+        // deploying still needs all required args, so parse again without negating them
+        _ => get_cluster_matches(false),
+    }
+}
+
+fn get_cluster_matches(subcommand_negates_reqs: bool) -> clap::ArgMatches {
     command!()
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Cluster spec file (.toml, .yaml, or .yml) to read the cluster and its deployments from.
+                Flags passed on the command line override values in the file"),
+        )
         .arg(
             Arg::new("cluster_namespace")
                 .long("namespace")
@@ -55,18 +98,64 @@ fn parse_matches() -> clap::ArgMatches {
                 .help("Specifies the build type: skip, debug, or release.
                 Skip -> Will not build release or local repo"),
         )
+        .arg(
+            Arg::with_name("cargo_features")
+                .long("cargo-features")
+                .takes_value(true)
+                .value_name("FEATURES")
+                .use_value_delimiter(true)
+                .help("Comma separated cargo features to build the validator with"),
+        )
+        .arg(
+            Arg::with_name("rustflags")
+                .long("rustflags")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("RUSTFLAGS for the build. e.g. \"-C target-cpu=native -C force-frame-pointers=yes\""),
+        )
+        .arg(
+            Arg::with_name("build_env")
+                .long("build-env")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("KEY=VALUE")
+                .validator(|value| parse_env_var(value).map(|_| ()))
+                .help("Environment variable for the build. Can be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("full_install")
+                .long("full-install")
+                .help("Install every binary, e.g. agave-ledger-tool, instead of only those a validator needs"),
+        )
+        .arg(
+            Arg::with_name("build_arg")
+                .long("build-arg")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .allow_hyphen_values(true)
+                .value_name("ARG")
+                .help("Extra argument for scripts/cargo-install-all.sh. Can be passed multiple times"),
+        )
         .arg(
             Arg::with_name("release_channel")
                 .long("release-channel")
                 .takes_value(true)
                 .help("Pulls specific release version. e.g. v1.17.2"),
         )
+        .arg(
+            Arg::with_name("release_sha256")
+                .long("release-sha256")
+                .takes_value(true)
+                .value_name("HEX")
+                .requires("release_channel")
+                .help("Expected SHA-256 of the --release-channel tarball. The deploy fails if it does not match"),
+        )
         .arg(
             Arg::with_name("commit")
                 .long("commit")
                 .value_name("HASH")
                 .takes_value(true)
-                .help("Pulls specific commit. must be full commit hash. e.g. 8db8e60c48ab064c88a76013597f99c9eb25ed74"),
+                .help("Pulls specific commit, by full or short hash. e.g. 8db8e60c48ab064c88a76013597f99c9eb25ed74"),
         )
         .arg(
             Arg::with_name("github_username")
@@ -81,10 +170,27 @@ fn parse_matches() -> clap::ArgMatches {
                 .default_value("solana")
                 .help("Dictates the repo name to pull the commit from. e.g. agave"),
         )
+        .arg(
+            Arg::with_name("git_remote")
+                .long("git-remote")
+                .takes_value(true)
+                .value_name("URL")
+                .requires("git_ref")
+                .help("Builds from this git remote, any URL git understands or a local (bare) mirror path.
+                e.g. https://github.com/anza-xyz/agave.git"),
+        )
+        .arg(
+            Arg::with_name("git_ref")
+                .long("git-ref")
+                .takes_value(true)
+                .value_name("REF")
+                .requires("git_remote")
+                .help("Branch, tag, ref, or full or short commit hash of --git-remote to build.
+                e.g. master, v2.0.3, refs/pull/1234/head, 8db8e60c"),
+        )
         .group(
-            ArgGroup::new("required_group")
-                .args(&["local_path", "release_channel", "commit"])
-                .required(true),
+            ArgGroup::new("deploy_method")
+                .args(&["local_path", "release_channel", "commit", "git_remote"]),
         )
         .group(
             ArgGroup::with_name("github_args")
@@ -96,7 +202,7 @@ fn parse_matches() -> clap::ArgMatches {
             Arg::with_name("cluster_data_path")
                 .long("cluster-data-path")
                 .takes_value(true)
-                .required(true)
+                .required_unless_present("config")
                 .value_name("DIRECTORY")
                 .help("Absolute path to cluster_data directory for storing accounts, genesis, etc
                 e.g. /home/sol/validator-lab-build"),
@@ -159,6 +265,85 @@ fn parse_matches() -> clap::ArgMatches {
                     "Selects the features that will be enabled for the cluster"
                 ),
         )
+        .arg(
+            Arg::with_name("deactivate_feature")
+                .long("deactivate-feature")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("FEATURE_PUBKEY")
+                .validator(|s| Pubkey::from_str(s).map(|_| ()).map_err(|err| err.to_string()))
+                .help("Genesis config. Deactivate this feature at genesis. May be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("active_feature")
+                .long("active-feature")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("FEATURE_PUBKEY")
+                .validator(|s| Pubkey::from_str(s).map(|_| ()).map_err(|err| err.to_string()))
+                .help("Genesis config. Keep this feature active at genesis and deactivate every other feature
+                of the genesis version. May be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("common_features")
+                .long("common-features")
+                .help("Genesis config. Deactivate every feature that is not supported by all deployments' versions.
+                Needed for heterogeneous clusters across versions with different feature sets"),
+        )
+        .arg(
+            Arg::with_name("validators_in_genesis")
+                .long("validators-in-genesis")
+                .help("Genesis config. Fund and stake the first deployment's validators in genesis
+                instead of airdropping from the faucet when they start. Their stake is active from slot 0"),
+        )
+        .arg(
+            Arg::with_name("genesis_program")
+                .long("genesis-program")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("KEY=VALUE,...")
+                .validator(|s| GenesisProgram::from_str(s).map(|_| ()))
+                .conflicts_with("no_genesis_programs")
+                .help("Genesis config. Load this program into genesis instead of the default SPL programs,
+                e.g. name=my-program,address=<PUBKEY>,loader=<PUBKEY>,url=<URL>|path=<FILE.so>[,upgrade-authority=<PUBKEY>].
+                loader defaults to the upgradeable loader. May be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("genesis_programs_file")
+                .long("genesis-programs-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("no_genesis_programs")
+                .help("Genesis config. TOML file of [[programs]] to load into genesis instead of the default SPL programs.
+                Each has the keys of --genesis-program"),
+        )
+        .arg(
+            Arg::with_name("no_genesis_programs")
+                .long("no-genesis-programs")
+                .help("Genesis config. Do not load any programs into genesis"),
+        )
+        .arg(
+            Arg::with_name("keypair_dir")
+                .long("keypair-dir")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Use the existing keypairs in this directory instead of generating them for the first deployment:
+                faucet.json, bootstrap-validator/{identity,vote-account,stake-account}.json,
+                validator-{identity,vote-account,stake-account}-<index>.json, and rpc-node-identity-<index>.json.
+                Node types without keypairs in the directory are generated"),
+        )
+        .arg(
+            Arg::with_name("keygen_seed")
+                .long("keygen-seed")
+                .takes_value(true)
+                .value_name("SEED")
+                .help("Generate the faucet, bootstrap, validator, and RPC node keys from this seed,
+                so the same seed and deployments always yield the same pubkeys. Genesis runs without it use a random seed
+                and record it in <cluster-data-path>/keygen-seed. --no-bootstrap runs without it use the recorded seed"),
+        )
         .arg(
             Arg::with_name("bootstrap_validator_sol")
                 .long("bootstrap-validator-sol")
@@ -178,15 +363,15 @@ fn parse_matches() -> clap::ArgMatches {
             Arg::with_name("registry_name")
                 .long("registry")
                 .takes_value(true)
-                .required(true)
-                .help("Registry to push docker image to"),
+                .required_unless_present_any(["config", "image_distribution"])
+                .help("Registry to push docker image to. Defaults to localhost:5000 with a local --image-distribution"),
         )
         .arg(
             Arg::with_name("image_name")
                 .long("image-name")
                 .takes_value(true)
                 .default_value("k8s-image")
-                .help("Docker image name. Will be prepended with node_type (bootstrap or validator)"),
+                .help("Docker image name. The bootstrap validator and RPC nodes run <image-name>-ledger"),
         )
         .arg(
             Arg::with_name("base_image")
@@ -195,6 +380,48 @@ fn parse_matches() -> clap::ArgMatches {
                 .default_value("ubuntu:22.04")
                 .help("Docker base image"),
         )
+        .arg(
+            Arg::with_name("startup_script")
+                .long("startup-script")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("FILE_NAME=PATH")
+                .validator(|value| parse_env_var(value).map(|_| ()))
+                .help("Use a local file instead of a built-in startup script, e.g.
+                validator-startup-script.sh=./my-validator-startup-script.sh. Can be passed multiple times"),
+        )
+        .arg(
+            Arg::with_name("image_builder")
+                .long("image-builder")
+                .takes_value(true)
+                .possible_values(ImageBuilderKind::VARIANTS)
+                .default_value(ImageBuilderKind::Docker.into())
+                .help("How images are built and pushed: docker, podman, buildah, or kaniko.
+                kaniko writes each image's build context to --kaniko-context-dir for kaniko to build and push"),
+        )
+        .arg(
+            Arg::with_name("kaniko_context_dir")
+                .long("kaniko-context-dir")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Where --image-builder kaniko writes build contexts. Defaults to <cluster-data-path>/kaniko"),
+        )
+        .arg(
+            Arg::with_name("image_distribution")
+                .long("image-distribution")
+                .takes_value(true)
+                .possible_values(ImageDistribution::VARIANTS)
+                .help("How images reach the cluster. Defaults to registry, pushing to --registry.
+                local-registry pushes to localhost:5000. kind and k3d load images straight into the nodes
+                of a local cluster. Pods only pull side-loaded images if they are not present"),
+        )
+        .arg(
+            Arg::with_name("local_cluster")
+                .long("local-cluster")
+                .takes_value(true)
+                .value_name("NAME")
+                .help("kind or k3d cluster to load images into. Defaults to kind or k3s-default"),
+        )
         // Bootstrap/Validator Config
         .arg(
             Arg::with_name("limit_ledger_size")
@@ -247,6 +474,21 @@ fn parse_matches() -> clap::ArgMatches {
                 .default_value(&DEFAULT_INTERNAL_NODE_STAKE_SOL.to_string())
                 .help("Amount to stake internal nodes (Sol)."),
         )
+        .arg(
+            Arg::with_name("stake_distribution")
+                .long("stake-distribution")
+                .takes_value(true)
+                .value_name("DISTRIBUTION")
+                .default_value("uniform")
+                .validator(|s| s.parse::<StakeDistribution>().map(|_| ()))
+                .help("How stake is split across validators.
+                uniform -> every validator stakes --internal-node-stake-sol
+                power-law[:<exponent>] -> validator i gets a share proportional to 1 / (i + 1)^exponent. [default exponent: 1]
+                mainnet -> shares follow the top-N mainnet-beta vote accounts by stake
+                list:<sol>,<sol>,... -> explicit stake per validator index. e.g. list:100,50,10
+                file:<path> -> explicit stake per validator index, one SOL amount per line
+                Generated distributions keep the total stake at num-validators * internal-node-stake-sol"),
+        )
         .arg(
             Arg::with_name("commission")
                 .long("commission")
@@ -396,12 +638,228 @@ fn parse_matches() -> clap::ArgMatches {
                     to the generic client executable."),
             )
         )
+        .subcommand(SubCommand::with_name("teardown")
+            .about("Delete the ReplicaSets, Services, and Secrets deployed in --namespace")
+            .arg(
+                Arg::with_name("deployment_tag")
+                    .long("tag")
+                    .takes_value(true)
+                    .value_name("TAG")
+                    .help("Only tear down the nodes of this deployment tag, <tag-or-commit8>[-<options fingerprint>][-dirty-<hash>]
+                    as logged by the deploy. e.g. v1.18.15 or 8db8e60c-1a2b3c4d.
+                    Leaves the bootstrap validator, load balancer, and metrics secret running"),
+            )
+            .arg(
+                Arg::with_name("collect_logs")
+                    .long("collect-logs")
+                    .help("Save the logs of the pods being torn down to <cluster-data-path>/logs first.
+                    Requires --cluster-data-path"),
+            )
+        )
+        .subcommand(SubCommand::with_name("collect-logs")
+            .about("Save the container logs of every pod in --namespace to
+            <cluster-data-path>/logs/<deployment-tag>/<node-type>-<index>.log")
+            .arg(
+                Arg::with_name("deployment_tag")
+                    .long("tag")
+                    .takes_value(true)
+                    .value_name("TAG")
+                    .help("Only collect the logs of this deployment tag, <tag-or-commit8>[-<options fingerprint>][-dirty-<hash>]
+                    as logged by the deploy. e.g. v1.18.15 or 8db8e60c-1a2b3c4d"),
+            )
+        )
+        .subcommand(SubCommand::with_name("status")
+            .about("Report the readiness, gossip membership, and voting of the nodes in --namespace")
+            .arg(
+                Arg::with_name("json")
+                    .long("json")
+                    .help("Print the status as JSON"),
+            )
+            .arg(
+                Arg::with_name("rpc_url")
+                    .long("rpc-url")
+                    .takes_value(true)
+                    .value_name("URL")
+                    .help("Query this RPC url instead of the load balancer's external address.
+                    e.g. http://localhost:8899 when port-forwarding"),
+            )
+        )
+        .subcommand(SubCommand::with_name("scale")
+            .about("Add or remove validators, rpc nodes, or clients of a running deployment.
+            New nodes copy the highest-indexed node of their type and reuse its pushed image.
+            Requires --cluster-data-path")
+            .arg(
+                Arg::with_name("deployment_tag")
+                    .long("tag")
+                    .takes_value(true)
+                    .value_name("TAG")
+                    .required(true)
+                    .help("Deployment tag to scale, <tag-or-commit8>[-<options fingerprint>][-dirty-<hash>]
+                    as logged by the deploy. e.g. v1.18.15 or 8db8e60c-1a2b3c4d"),
+            )
+            .arg(
+                Arg::with_name("validators")
+                    .long("validators")
+                    .takes_value(true)
+                    .value_name("+N|-N")
+                    .allow_hyphen_values(true)
+                    .validator(|s| s.parse::<i64>().map(|_| ()).map_err(|err| err.to_string()))
+                    .help("Number of validators to add (+N) or remove (-N). The highest indices are removed first"),
+            )
+            .arg(
+                Arg::with_name("rpc_nodes")
+                    .long("rpc-nodes")
+                    .takes_value(true)
+                    .value_name("+N|-N")
+                    .allow_hyphen_values(true)
+                    .validator(|s| s.parse::<i64>().map(|_| ()).map_err(|err| err.to_string()))
+                    .help("Number of rpc nodes to add (+N) or remove (-N)"),
+            )
+            .arg(
+                Arg::with_name("clients")
+                    .long("clients")
+                    .takes_value(true)
+                    .value_name("+N|-N")
+                    .allow_hyphen_values(true)
+                    .validator(|s| s.parse::<i64>().map(|_| ()).map_err(|err| err.to_string()))
+                    .help("Number of clients to add (+N) or remove (-N).
+                    Added clients copy the Secret, and so the faucet and bench-tps accounts, of the highest-indexed client"),
+            )
+            .arg(
+                Arg::with_name("deactivate_stake")
+                    .long("deactivate-stake")
+                    .help("Deactivate the stake of validators being removed before deleting them"),
+            )
+            .arg(
+                Arg::with_name("rpc_url")
+                    .long("rpc-url")
+                    .takes_value(true)
+                    .value_name("URL")
+                    .requires("deactivate_stake")
+                    .help("RPC url to deactivate stake through. Defaults to the load balancer's external address"),
+            )
+        )
+        .subcommand(SubCommand::with_name("cache-artifact")
+            .about("Add a local release tarball or genesis program to the artifact cache,
+            so deploys use it without downloading, e.g. with --offline.
+            Requires --artifact-cache or --cluster-data-path")
+            .arg(
+                Arg::with_name("release")
+                    .long("release")
+                    .takes_value(true)
+                    .value_name("RELEASE_CHANNEL")
+                    .help("Cache --file as the solana-release tarball of this release channel. e.g. v1.18.20"),
+            )
+            .arg(
+                Arg::with_name("program")
+                    .long("program")
+                    .takes_value(true)
+                    .value_name("NAME")
+                    .help("Cache --file as the .so of the genesis program with this name. e.g. spl_token-3.5.0"),
+            )
+            .group(
+                ArgGroup::new("artifact")
+                    .args(&["release", "program"])
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("file")
+                    .long("file")
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .required(true)
+                    .help("Local file to cache"),
+            )
+            .arg(
+                Arg::with_name("sha256")
+                    .long("sha256")
+                    .takes_value(true)
+                    .value_name("HEX")
+                    .help("Expected SHA-256 of --file. The file is not cached if it does not match"),
+            )
+        )
+        .arg(
+            Arg::with_name("artifact_cache")
+                .long("artifact-cache")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Directory caching release tarballs, genesis programs, and feature sets across runs.
+                Defaults to <cluster-data-path>/artifact-cache"),
+        )
+        .arg(
+            Arg::with_name("build_cache")
+                .long("build-cache")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Directory keeping the builds of clean commits as <commit>-<debug|release>,
+                reused when the same commit is built again. Defaults to <cluster-data-path>/build-cache"),
+        )
+        .arg(
+            Arg::with_name("offline")
+                .long("offline")
+                .help("Do not download anything. Fail fast if an artifact is missing from --artifact-cache"),
+        )
+        .arg(
+            Arg::with_name("collect_logs_on_failure")
+                .long("collect-logs-on-failure")
+                .help("If a deployment fails, save the logs of every pod in --namespace to
+                <cluster-data-path>/logs before exiting"),
+        )
+        // Dry Run Config
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .requires("output_dir")
+                .help("Build and create genesis, but do not push docker images or touch the kubernetes cluster.
+                Every Secret, ReplicaSet, and Service is written to --output-dir as YAML instead"),
+        )
+        .arg(
+            Arg::with_name("output_dir")
+                .long("output-dir")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .requires("dry_run")
+                .help("Directory to write --dry-run manifests to"),
+        )
+        .arg(
+            Arg::with_name("show_secrets")
+                .long("show-secrets")
+                .requires("dry_run")
+                .help("Write Secret data to --dry-run manifests. Redacted by default"),
+        )
         // Heterogeneous Cluster Config
         .arg(
             Arg::with_name("no_bootstrap")
                 .long("no-bootstrap")
                 .help("Do not deploy a bootstrap validator. Used when deploying heterogeneous clusters"),
         )
+        // Readiness Config
+        .arg(
+            Arg::with_name("readiness_timeout")
+                .long("readiness-timeout")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .default_value(&DEFAULT_READINESS_TIMEOUT_SECS.to_string())
+                .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("How long each readiness wait of a deployment may take before failing the deploy.
+                The bootstrap, RPC node, and validator ReplicaSets becoming available and the
+                cluster converging are each waited for separately, so a deploy can wait several times as long"),
+        )
+        .arg(
+            Arg::with_name("readiness_rpc_url")
+                .long("readiness-rpc-url")
+                .takes_value(true)
+                .value_name("URL")
+                .help("RPC url to check convergence through. Defaults to the load balancer's
+                external address, or its external port on one of the kubernetes nodes"),
+        )
+        .arg(
+            Arg::with_name("skip_readiness_check")
+                .long("skip-readiness-check")
+                .conflicts_with("readiness_rpc_url")
+                .help("Return once the ReplicaSets are available without waiting for
+                validators to join gossip, vote, and advance roots"),
+        )
         // kubernetes config
         .arg(
             Arg::with_name("cpu_requests")
@@ -421,6 +879,58 @@ fn parse_matches() -> clap::ArgMatches {
                     Can specify unit here (B, Ki, Mi, Gi, Ti) for bytes, kilobytes, etc (2^N notation)
                     e.g. 1Gi == 1024Mi == 1024Ki == 1,047,576B. [default: 70Gi]"),
         )
+        .arg(
+            Arg::with_name("node_affinity")
+                .long("node-affinity")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NODE_TYPE:KEY=VALUE")
+                .help("Kubernetes pod config. Only schedule pods of NODE_TYPE on nodes labeled KEY=VALUE.
+                    NODE_TYPE is one of bootstrap-validator, validator, rpc-node, client, or all.
+                    e.g. --node-affinity bootstrap-validator:topology.kubernetes.io/region=us-east"),
+        )
+        .arg(
+            Arg::with_name("preferred_node_affinity")
+                .long("preferred-node-affinity")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NODE_TYPE:KEY=VALUE")
+                .help("Kubernetes pod config. Prefer scheduling pods of NODE_TYPE on nodes labeled KEY=VALUE,
+                    falling back to any node. e.g. --preferred-node-affinity validator:provider=equinix"),
+        )
+        .arg(
+            Arg::with_name("toleration")
+                .long("toleration")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NODE_TYPE:KEY[=VALUE][:EFFECT]")
+                .help("Kubernetes pod config. Let pods of NODE_TYPE schedule on nodes with a matching taint.
+                    e.g. --toleration validator:dedicated=validators:NoSchedule"),
+        )
+        .arg(
+            Arg::with_name("spread_across_regions")
+                .long("spread-across-regions")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("NODE_TYPE")
+                .help("Kubernetes pod config. Spread pods of NODE_TYPE evenly across regions, as labeled by --region-label"),
+        )
+        .arg(
+            Arg::with_name("region_label")
+                .long("region-label")
+                .takes_value(true)
+                .default_value(DEFAULT_REGION_LABEL)
+                .help("Kubernetes pod config. Node label holding the node's region"),
+        )
+        .arg(
+            Arg::with_name("validator_anti_affinity")
+                .long("validator-anti-affinity")
+                .help("Kubernetes pod config. Never schedule two validators (including the bootstrap) on the same host"),
+        )
         //Metrics Config
         .arg(
             Arg::with_name("metrics_host")
@@ -453,27 +963,95 @@ fn parse_matches() -> clap::ArgMatches {
                 .takes_value(true)
                 .help("Metrics Config. Optional: Specify metrics password"),
         )
+        .subcommand_negates_reqs(subcommand_negates_reqs)
         .get_matches()
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "INFO");
+/// Settings shared by every deployment in the cluster
+struct ClusterSettings<'a> {
+    environment_config: EnvironmentConfig<'a>,
+    genesis_flags: GenesisFlags,
+    /// existing keypairs for the deployment that creates genesis
+    keypair_dir: Option<PathBuf>,
+    artifact_cache: ArtifactCache,
+    build_cache: PathBuf,
+    /// the scripts published in each deployment's startup scripts ConfigMap, by file name
+    startup_scripts: BTreeMap<String, String>,
+    pod_requests: PodRequests,
+    node_placement: NodePlacement,
+    metrics: Option<Metrics>,
+    docker: DockerConfig,
+    image_distribution: ImageDistribution,
+    registry_name: String,
+    image_name: String,
+    dry_run: Option<ManifestWriter>,
+    readiness: Readiness,
+    /// where to reach the cluster RPC while waiting for it to converge. None skips the wait
+    readiness_rpc: Option<ReadinessRpc>,
+}
+
+/// Returns the command line value of `name` if it should override the config file.
+/// i.e. it was passed explicitly, or there is no config file so its default applies.
+fn cli_value<'a>(matches: &'a ArgMatches, name: &str, has_config: bool) -> Option<&'a str> {
+    if has_config && matches.value_source(name) != Some(ValueSource::CommandLine) {
+        return None;
     }
-    solana_logger::setup();
-    let matches = parse_matches();
-    let environment_config = EnvironmentConfig {
-        namespace: matches.value_of("cluster_namespace").unwrap_or_default(),
-        cluster_data_path: matches
-            .value_of("cluster_data_path")
-            .map(PathBuf::from)
-            .unwrap(),
-    };
+    matches.value_of(name)
+}
+
+/// `cli_value` for flags that take several values
+fn cli_values<'a>(
+    matches: &'a ArgMatches,
+    name: &str,
+    has_config: bool,
+) -> Option<impl Iterator<Item = &'a str>> {
+    if has_config && matches.value_source(name) != Some(ValueSource::CommandLine) {
+        return None;
+    }
+    matches.values_of(name)
+}
+
+fn cli_value_t<T>(matches: &ArgMatches, name: &str, has_config: bool) -> Option<T>
+where
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    cli_value(matches, name, has_config).map(|_| matches.value_of_t_or_exit(name))
+}
 
-    let num_validators = value_t_or_exit!(matches, "number_of_validators", usize);
-    let num_rpc_nodes = value_t_or_exit!(matches, "number_of_rpc_nodes", usize);
-    let client_config = if let Some(matches) = matches.subcommand_matches("bench-tps") {
+/// Flags can only be turned on from the command line, so an absent flag keeps the config file value
+fn cli_flag(matches: &ArgMatches, name: &str, has_config: bool) -> Option<bool> {
+    let present = matches.is_present(name);
+    (present || !has_config).then_some(present)
+}
+
+fn deploy_method_from_cli(matches: &ArgMatches) -> Option<DeployMethod> {
+    if let Some(local_path) = matches.value_of("local_path") {
+        Some(DeployMethod::Local(local_path.to_owned()))
+    } else if let Some(release_channel) = matches.value_of("release_channel") {
+        Some(DeployMethod::ReleaseChannel(release_channel.to_owned()))
+    } else if let Some(commit) = matches.value_of("commit") {
+        let github_username = matches
+            .value_of("github_username")
+            .expect("User should pass in --github-username <username>");
+        let repo_name = matches.value_of("repo_name").unwrap();
+        Some(DeployMethod::Commit {
+            commit: commit.to_owned(),
+            username: github_username.to_owned(),
+            repo_name: repo_name.to_owned(),
+        })
+    } else {
+        matches
+            .value_of("git_remote")
+            .map(|remote| DeployMethod::Git {
+                remote: remote.to_owned(),
+                git_ref: matches.value_of("git_ref").unwrap().to_owned(),
+            })
+    }
+}
+
+fn client_config_from_cli(matches: &ArgMatches) -> Option<ClientConfig> {
+    if let Some(matches) = matches.subcommand_matches("bench-tps") {
         let bench_tps_config = BenchTpsConfig {
             num_clients: value_t_or_exit!(matches, "number_of_clients", usize),
             client_duration_seconds: value_t_or_exit!(matches, "client_duration_seconds", u64),
@@ -490,7 +1068,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             client_target_node: pubkey_of(matches, "client_target_node"),
         };
 
-        ClientConfig::BenchTps(bench_tps_config)
+        Some(ClientConfig::BenchTps(bench_tps_config))
     } else if let Some(matches) = matches.subcommand_matches("generic-client") {
         let generic_config = GenericClientConfig {
             num_clients: value_t_or_exit!(matches, "number_of_clients", usize),
@@ -501,179 +1079,833 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             delay_start: value_t_or_exit!(matches, "delay_start", u64),
         };
 
-        ClientConfig::Generic(generic_config)
+        Some(ClientConfig::Generic(generic_config))
     } else {
-        ClientConfig::None
-    };
+        None
+    }
+}
 
-    let deploy_method = if let Some(local_path) = matches.value_of("local_path") {
-        DeployMethod::Local(local_path.to_owned())
-    } else if let Some(release_channel) = matches.value_of("release_channel") {
-        DeployMethod::ReleaseChannel(release_channel.to_owned())
-    } else if let Some(commit) = matches.value_of("commit") {
-        let github_username = matches
-            .value_of("github_username")
-            .expect("User should pass in --github-username <username>");
-        let repo_name = matches.value_of("repo_name").unwrap();
-        DeployMethod::Commit {
-            commit: commit.to_owned(),
-            username: github_username.to_owned(),
-            repo_name: repo_name.to_owned(),
-        }
-    } else {
-        unreachable!("One of --local-path, --release-channel, or --commit must be provided.");
-    };
+fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!(
+            "Invalid environment variable '{var}'. Expected KEY=VALUE"
+        )),
+    }
+}
 
-    let cluster_data_root = ClusterDataRoot::new_from_path(environment_config.cluster_data_path);
-    check_directory(cluster_data_root.get_root_path(), "Cluster data root")?;
-    let exec_path = cluster_data_root
-        .get_root_path()
-        .join(format!("{SOLANA_RELEASE}/bin"));
-    match &deploy_method {
-        DeployMethod::Local(agave_path) => {
-            let agave_path: PathBuf = agave_path.into();
-            check_directory(&agave_path, "Agave repo")?;
+/// Applies command line overrides to a deployment.
+/// `has_config` is false for the deployment described by the command line itself
+fn apply_deployment_overrides(
+    matches: &ArgMatches,
+    deployment: &mut DeploymentSpec,
+    has_config: bool,
+) {
+    if let Some(build_type) = cli_value_t(matches, "build_type", has_config) {
+        deployment.build_type = Some(build_type);
+    }
+    // Build options add to those of the config file's deployments built from source.
+    // On a --release-channel deployment they are left for BuildConfig to reject
+    let is_release = matches!(deployment.deploy_method, DeployMethod::ReleaseChannel(_));
+    if !(has_config && is_release) {
+        let build_options = &mut deployment.build_options;
+        if let Some(features) = cli_values(matches, "cargo_features", has_config) {
+            for feature in features {
+                if !build_options.features.iter().any(|f| f == feature) {
+                    build_options.features.push(feature.to_string());
+                }
+            }
         }
-        DeployMethod::ReleaseChannel(_) | DeployMethod::Commit { .. } => (),
-    };
-
-    let build_type: BuildType = matches.value_of_t("build_type").unwrap();
-
-    let build_config = BuildConfig::new(
-        deploy_method.clone(),
-        build_type.clone(),
-        cluster_data_root.get_root_path(),
-    );
-
-    let commission = value_t_or_exit!(matches, "commission", u8);
+        if let Some(rustflags) = cli_value(matches, "rustflags", has_config) {
+            build_options
+                .env
+                .insert("RUSTFLAGS".to_string(), rustflags.to_string());
+        }
+        if let Some(env) = cli_values(matches, "build_env", has_config) {
+            for (key, value) in env.filter_map(|var| parse_env_var(var).ok()) {
+                build_options.env.insert(key, value);
+            }
+        }
+        if cli_flag(matches, "full_install", has_config) == Some(true) {
+            build_options.full_install = true;
+        }
+        if let Some(args) = cli_values(matches, "build_arg", has_config) {
+            build_options.extra_args.extend(args.map(String::from));
+        }
+    }
+    if let Some(num_validators) = cli_value_t(matches, "number_of_validators", has_config) {
+        deployment.num_validators = num_validators;
+    }
+    if let Some(num_rpc_nodes) = cli_value_t(matches, "number_of_rpc_nodes", has_config) {
+        deployment.num_rpc_nodes = num_rpc_nodes;
+    }
+    if let Some(stake_distribution) = cli_value_t(matches, "stake_distribution", has_config) {
+        deployment.stake_distribution = stake_distribution;
+    }
+    if let Some(client_config) = client_config_from_cli(matches) {
+        deployment.client = Some(client_config);
+    }
+}
 
-    let genesis_flags = GenesisFlags {
-        hashes_per_tick: matches
-            .value_of("hashes_per_tick")
-            .unwrap_or_default()
-            .to_string(),
-        slots_per_epoch: matches.value_of("slots_per_epoch").map(|value_str| {
-            value_str
-                .parse()
-                .expect("Invalid value for slots_per_epoch")
-        }),
-        target_lamports_per_signature: matches.value_of("target_lamports_per_signature").map(
-            |value_str| {
-                value_str
-                    .parse()
-                    .expect("Invalid value for target_lamports_per_signature")
-            },
-        ),
-        faucet_lamports: matches.value_of("faucet_lamports").map(|value_str| {
-            value_str
-                .parse()
-                .expect("Invalid value for faucet_lamports")
-        }),
-        enable_warmup_epochs: !matches.is_present("disable_warmup_epochs"),
-        max_genesis_archive_unpacked_size: matches
-            .value_of("max_genesis_archive_unpacked_size")
-            .map(|value_str| {
-                value_str
-                    .parse()
-                    .expect("Invalid value for max_genesis_archive_unpacked_size")
-            }),
-        cluster_type: matches
-            .value_of("cluster_type")
-            .unwrap_or_default()
-            .to_string(),
-        bootstrap_validator_sol: matches
-            .value_of("bootstrap_validator_sol")
-            .map(|value_str| {
-                value_str
-                    .parse()
-                    .expect("Invalid value for bootstrap_validator_sol")
-            }),
-        bootstrap_validator_stake_sol: matches.value_of("bootstrap_validator_stake_sol").map(
-            |value_str| {
-                value_str
-                    .parse()
-                    .expect("Invalid value for bootstrap_validator_stake_sol")
-            },
-        ),
-        commission,
-    };
+fn apply_genesis_overrides(matches: &ArgMatches, flags: &mut GenesisFlags, has_config: bool) {
+    if let Some(hashes_per_tick) = cli_value(matches, "hashes_per_tick", has_config) {
+        flags.hashes_per_tick = hashes_per_tick.to_string();
+    }
+    if let Some(slots_per_epoch) = cli_value_t(matches, "slots_per_epoch", has_config) {
+        flags.slots_per_epoch = Some(slots_per_epoch);
+    }
+    if let Some(lamports) = cli_value_t(matches, "target_lamports_per_signature", has_config) {
+        flags.target_lamports_per_signature = Some(lamports);
+    }
+    if let Some(faucet_lamports) = cli_value_t(matches, "faucet_lamports", has_config) {
+        flags.faucet_lamports = Some(faucet_lamports);
+    }
+    if let Some(disable_warmup_epochs) = cli_flag(matches, "disable_warmup_epochs", has_config) {
+        flags.enable_warmup_epochs = !disable_warmup_epochs;
+    }
+    if let Some(size) = cli_value_t(matches, "max_genesis_archive_unpacked_size", has_config) {
+        flags.max_genesis_archive_unpacked_size = Some(size);
+    }
+    if let Some(cluster_type) = cli_value(matches, "cluster_type", has_config) {
+        flags.cluster_type = cluster_type.to_string();
+    }
+    if let Some(sol) = cli_value_t(matches, "bootstrap_validator_sol", has_config) {
+        flags.bootstrap_validator_sol = Some(sol);
+    }
+    if let Some(sol) = cli_value_t(matches, "bootstrap_validator_stake_sol", has_config) {
+        flags.bootstrap_validator_stake_sol = Some(sol);
+    }
+    if let Some(features) = pubkeys_of(matches, "deactivate_feature") {
+        flags.deactivate_features = features;
+    }
+    if let Some(features) = pubkeys_of(matches, "active_feature") {
+        flags.active_features = features;
+    }
+    if matches.is_present("common_features") {
+        flags.common_features = true;
+    }
+    if matches.is_present("validators_in_genesis") {
+        flags.validators_in_genesis = true;
+    }
+    if let Some(seed) = cli_value(matches, "keygen_seed", has_config) {
+        flags.keygen_seed = Some(seed.to_string());
+    }
+}
+
+fn apply_validator_overrides(matches: &ArgMatches, config: &mut ValidatorConfig, has_config: bool) {
+    if let Some(sol) = cli_value_t(matches, "internal_node_sol", has_config) {
+        config.internal_node_sol = sol;
+    }
+    if let Some(sol) = cli_value_t(matches, "internal_node_stake_sol", has_config) {
+        config.internal_node_stake_sol = sol;
+    }
+    if let Some(commission) = cli_value_t(matches, "commission", has_config) {
+        config.commission = commission;
+    }
+    if let Some(limit_ledger_size) = cli_value_t(matches, "limit_ledger_size", has_config) {
+        config.max_ledger_size = Some(limit_ledger_size);
+    }
+    if let Some(skip_poh_verify) = cli_flag(matches, "skip_poh_verify", has_config) {
+        config.skip_poh_verify = skip_poh_verify;
+    }
+    if let Some(no_snapshot_fetch) = cli_flag(matches, "no_snapshot_fetch", has_config) {
+        config.no_snapshot_fetch = no_snapshot_fetch;
+    }
+    if let Some(require_tower) = cli_flag(matches, "require_tower", has_config) {
+        config.require_tower = require_tower;
+    }
+    if let Some(enable_full_rpc) = cli_flag(matches, "enable_full_rpc", has_config) {
+        config.enable_full_rpc = enable_full_rpc;
+    }
+    if let Some(no_restart) = cli_flag(matches, "no_restart", has_config) {
+        config.restart = !no_restart;
+    }
+}
+
+/// Splits `<node_type>:<rule>` from the placement flags
+fn split_node_type<'a>(value: &'a str, flag: &str) -> Result<(&'a str, &'a str), String> {
+    value
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid --{flag} '{value}'. Expected <NODE_TYPE>:<rule>"))
+}
+
+fn split_node_label<'a>(value: &'a str, flag: &str) -> Result<(&'a str, String, String), String> {
+    let (node_type, label) = split_node_type(value, flag)?;
+    let (key, label_value) = label
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid --{flag} '{value}'. Expected <NODE_TYPE>:<KEY>=<VALUE>"))?;
+    Ok((node_type, key.to_string(), label_value.to_string()))
+}
+
+/// Adds command line placement rules to those from the config file
+fn apply_placement_overrides(
+    matches: &ArgMatches,
+    placement: &mut NodePlacement,
+    has_config: bool,
+) -> Result<(), String> {
+    for value in matches.values_of("node_affinity").into_iter().flatten() {
+        let (node_type, key, label_value) = split_node_label(value, "node-affinity")?;
+        for rules in placement.rules_mut(node_type)? {
+            rules
+                .required_node_labels
+                .insert(key.clone(), label_value.clone());
+        }
+    }
+    for value in matches
+        .values_of("preferred_node_affinity")
+        .into_iter()
+        .flatten()
+    {
+        let (node_type, key, label_value) = split_node_label(value, "preferred-node-affinity")?;
+        for rules in placement.rules_mut(node_type)? {
+            rules
+                .preferred_node_labels
+                .insert(key.clone(), label_value.clone());
+        }
+    }
+    for value in matches.values_of("toleration").into_iter().flatten() {
+        let (node_type, toleration) = split_node_type(value, "toleration")?;
+        let toleration: TolerationSpec = toleration.parse()?;
+        for rules in placement.rules_mut(node_type)? {
+            rules.tolerations.push(toleration.clone());
+        }
+    }
+    for node_type in matches
+        .values_of("spread_across_regions")
+        .into_iter()
+        .flatten()
+    {
+        for rules in placement.rules_mut(node_type)? {
+            rules.spread_across_regions = true;
+        }
+    }
+    if let Some(region_label) = cli_value(matches, "region_label", has_config) {
+        placement.region_label = region_label.to_string();
+    }
+    if matches.is_present("validator_anti_affinity") {
+        placement.validator_anti_affinity = true;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "INFO");
+    }
+    solana_logger::setup();
+    let matches = parse_matches();
+
+    if let Some(teardown_matches) = matches.subcommand_matches("teardown") {
+        // deployment tags use "-" in place of "." in k8s names
+        let deployment_tag = teardown_matches
+            .value_of("deployment_tag")
+            .map(|tag| tag.replace('.', "-"));
+        let (namespace, cluster_data_path) = cluster_location(&matches)?;
+        if teardown_matches.is_present("collect_logs") {
+            let cluster_data_path =
+                cluster_data_path.ok_or("Must provide --cluster-data-path to collect logs")?;
+            collect_logs(&namespace, &cluster_data_path, deployment_tag.clone()).await?;
+        }
+        let backend = ClusterBackend::new().await?;
+        Teardown::new(&backend, &namespace, deployment_tag)
+            .await?
+            .run()
+            .await?;
+        return Ok(());
+    }
+
+    if let Some(collect_logs_matches) = matches.subcommand_matches("collect-logs") {
+        let deployment_tag = collect_logs_matches
+            .value_of("deployment_tag")
+            .map(|tag| tag.replace('.', "-"));
+        let (namespace, cluster_data_path) = cluster_location(&matches)?;
+        let cluster_data_path =
+            cluster_data_path.ok_or("Must provide --cluster-data-path to collect logs")?;
+        return collect_logs(&namespace, &cluster_data_path, deployment_tag).await;
+    }
+
+    if let Some(status_matches) = matches.subcommand_matches("status") {
+        let (namespace, _) = cluster_location(&matches)?;
+        let backend = ClusterBackend::new().await?;
+        let status = ClusterStatus::collect(
+            &backend,
+            &namespace,
+            status_matches.value_of("rpc_url").map(String::from),
+        )
+        .await?;
+        if status_matches.is_present("json") {
+            println!("{}", serde_json::to_string_pretty(&status)?);
+        } else {
+            print!("{status}");
+        }
+        return Ok(());
+    }
+
+    if let Some(scale_matches) = matches.subcommand_matches("scale") {
+        return scale(&matches, scale_matches).await;
+    }
+
+    if let Some(cache_matches) = matches.subcommand_matches("cache-artifact") {
+        let cluster_data_path = matches.value_of("cluster_data_path").map(PathBuf::from);
+        let artifact_cache = ArtifactCache::new(
+            artifact_cache_dir(&matches, None, cluster_data_path.as_deref())?,
+            true,
+        )?;
+        let key = match cache_matches.value_of("release") {
+            Some(release_channel) => release_key(release_channel),
+            None => program_key(cache_matches.value_of("program").unwrap()),
+        };
+        artifact_cache.insert(
+            &key,
+            Path::new(cache_matches.value_of("file").unwrap()),
+            cache_matches.value_of("sha256"),
+        )?;
+        return Ok(());
+    }
+
+    let (spec, has_config) = load_spec(&matches)?;
+    let (namespace, cluster_data_path) = spec_location(&matches, &spec, has_config);
+    let cluster_data_path = cluster_data_path
+        .ok_or("Must provide --cluster-data-path or `cluster-data-path` in --config")?;
+    let environment_config = EnvironmentConfig {
+        namespace: &namespace,
+        cluster_data_path,
+    };
+
+    // A deploy method on the command line describes a single deployment on its own.
+    // Otherwise deploy each of the config file deployments, in order.
+    let deployments = match deploy_method_from_cli(&matches) {
+        Some(deploy_method) => {
+            let mut deployment = DeploymentSpec {
+                deploy_method,
+                build_type: None,
+                build_options: BuildOptions::default(),
+                release_sha256: matches.value_of("release_sha256").map(String::from),
+                num_validators: 0,
+                num_rpc_nodes: 0,
+                stake_distribution: StakeDistribution::default(),
+                client: None,
+            };
+            apply_deployment_overrides(&matches, &mut deployment, false);
+            vec![deployment]
+        }
+        None => spec
+            .deployments
+            .into_iter()
+            .map(|mut deployment| {
+                apply_deployment_overrides(&matches, &mut deployment, has_config);
+                deployment
+            })
+            .collect(),
+    };
+    if deployments.is_empty() {
+        return Err(
+            "One of --local-path, --release-channel, --commit, or --git-remote must be provided, \
+            or at least one `deployments` entry in --config"
+                .into(),
+        );
+    }
 
-    let internal_node_stake_sol = value_t_or_exit!(matches, "internal_node_stake_sol", f64);
-    let internal_node_sol =
-        value_t_or_exit!(matches, "internal_node_sol", f64) + internal_node_stake_sol;
-
-    let limit_ledger_size = value_t_or_exit!(matches, "limit_ledger_size", u64);
-    let mut validator_config = ValidatorConfig {
-        internal_node_sol,
-        internal_node_stake_sol,
-        commission,
-        shred_version: None, // set after genesis created
-        max_ledger_size: if limit_ledger_size < DEFAULT_MIN_MAX_LEDGER_SHREDS {
+    let cluster_data_root =
+        ClusterDataRoot::new_from_path(environment_config.cluster_data_path.clone());
+    check_directory(cluster_data_root.get_root_path(), "Cluster data root")?;
+    for deployment in &deployments {
+        if let DeployMethod::Local(agave_path) = &deployment.deploy_method {
+            let agave_path: PathBuf = agave_path.into();
+            check_directory(&agave_path, "Agave repo")?;
+        }
+    }
+
+    let mut validator_config = spec.validator.unwrap_or_default();
+    apply_validator_overrides(&matches, &mut validator_config, has_config);
+    if let Some(max_ledger_size) = validator_config.max_ledger_size {
+        if max_ledger_size < DEFAULT_MIN_MAX_LEDGER_SHREDS {
             clap::Error::with_description(
                     format!("The provided --limit-ledger-size value was too small, the minimum value is {DEFAULT_MIN_MAX_LEDGER_SHREDS}"),
                     clap::ErrorKind::ArgumentNotFound,
                 )
                 .exit();
-        } else {
-            Some(limit_ledger_size)
-        },
-        skip_poh_verify: matches.is_present("skip_poh_verify"),
-        no_snapshot_fetch: matches.is_present("no_snapshot_fetch"),
-        require_tower: matches.is_present("require_tower"),
-        enable_full_rpc: matches.is_present("enable_full_rpc"),
-        known_validators: vec![],
-        restart: !matches.is_present("no_restart"),
-    };
+        }
+    }
+    let artifact_cache = ArtifactCache::new(
+        artifact_cache_dir(
+            &matches,
+            spec.artifact_cache.as_deref(),
+            Some(cluster_data_root.get_root_path()),
+        )?,
+        cli_flag(&matches, "offline", has_config).unwrap_or(spec.offline),
+    )?;
+    let build_cache = cli_value(&matches, "build_cache", has_config)
+        .map(PathBuf::from)
+        .or(spec.build_cache.map(PathBuf::from))
+        .unwrap_or_else(|| {
+            cluster_data_root
+                .get_root_path()
+                .join(DEFAULT_BUILD_CACHE_DIR)
+        });
+
+    let mut genesis_flags = spec.genesis.unwrap_or_default();
+    apply_genesis_overrides(&matches, &mut genesis_flags, has_config);
+    genesis_flags.commission = validator_config.commission;
+    if matches.is_present("no_genesis_programs") {
+        genesis_flags.programs = Some(vec![]);
+    } else {
+        let mut programs = vec![];
+        if let Some(path) = matches.value_of("genesis_programs_file") {
+            programs = load_genesis_programs(Path::new(path))?;
+        }
+        if let Some(values) = matches.values_of("genesis_program") {
+            for value in values {
+                programs.push(GenesisProgram::from_str(value)?);
+            }
+        }
+        if !programs.is_empty() {
+            genesis_flags.programs = Some(programs);
+        }
+    }
+    for program in genesis_flags.programs.iter().flatten() {
+        program.validate()?;
+    }
 
-    if num_rpc_nodes == 0 && !validator_config.enable_full_rpc {
-        return Err("Must have at least one RPC node and/or enable full RPC services `--full-rpc`. Exiting...".into());
+    // only the first deployment creates genesis and the bootstrap validator
+    let mut deploy_bootstrap_validator = !(matches.is_present("no_bootstrap") || spec.no_bootstrap);
+    // a new cluster records its seed. later runs against it continue the recorded keys
+    let mut keygen_seed = if deploy_bootstrap_validator {
+        KeygenSeed::start(
+            cluster_data_root.get_root_path(),
+            genesis_flags.keygen_seed.take(),
+        )?
+    } else {
+        KeygenSeed::resume(
+            cluster_data_root.get_root_path(),
+            genesis_flags.keygen_seed.take(),
+        )?
+    };
+    genesis_flags.keygen_seed = Some(keygen_seed.seed().to_string());
+    if deploy_bootstrap_validator {
+        let deploy_methods: Vec<&DeployMethod> = deployments
+            .iter()
+            .map(|deployment| &deployment.deploy_method)
+            .collect();
+        resolve_deactivated_features(
+            &mut genesis_flags,
+            &deploy_methods,
+            &artifact_cache,
+            cluster_data_root.get_root_path(),
+        )
+        .await?;
     }
 
-    let pod_requests = PodRequests::new(
-        matches.value_of("cpu_requests").unwrap().to_string(),
-        matches.value_of("memory_requests").unwrap().to_string(),
-    );
+    let pod_requests = pod_requests(&matches, spec.pod_requests.as_ref(), has_config);
+
+    let mut node_placement = spec.placement.unwrap_or_default();
+    apply_placement_overrides(&matches, &mut node_placement, has_config)?;
 
-    let metrics = matches.value_of("metrics_host").map(|host| {
-        Metrics::new(
-            host.to_string(),
-            matches.value_of("metrics_port").unwrap().to_string(),
-            matches.value_of("metrics_db").unwrap().to_string(),
-            matches.value_of("metrics_username").unwrap().to_string(),
-            matches.value_of("metrics_password").unwrap().to_string(),
+    let metrics = matches
+        .value_of("metrics_host")
+        .map(|host| {
+            Metrics::new(
+                host.to_string(),
+                matches.value_of("metrics_port").unwrap().to_string(),
+                matches.value_of("metrics_db").unwrap().to_string(),
+                matches.value_of("metrics_username").unwrap().to_string(),
+                matches.value_of("metrics_password").unwrap().to_string(),
+            )
+        })
+        .or(spec.metrics);
+
+    let image_distribution = cli_value_t(&matches, "image_distribution", has_config)
+        .or(spec.image_distribution)
+        .unwrap_or(ImageDistribution::Registry);
+    let local_cluster = cli_value(&matches, "local_cluster", has_config)
+        .map(String::from)
+        .or(spec.local_cluster);
+    let registry_name = matches
+        .value_of("registry_name")
+        .map(String::from)
+        .or(spec.registry)
+        .or_else(|| {
+            (image_distribution != ImageDistribution::Registry).then(|| LOCAL_REGISTRY.to_string())
+        })
+        .ok_or("Must provide --registry or `registry` in --config")?;
+
+    let image_builder = cli_value_t(&matches, "image_builder", has_config)
+        .or(spec.image_builder)
+        .unwrap_or(ImageBuilderKind::Docker);
+    // kind and k3d load images from the local docker daemon
+    if image_distribution.is_side_load() && image_builder != ImageBuilderKind::Docker {
+        return Err(format!(
+            "--image-distribution {} needs --image-builder docker",
+            <&str>::from(image_distribution)
         )
-    });
+        .into());
+    }
+    let kaniko_context_dir = cli_value(&matches, "kaniko_context_dir", has_config)
+        .map(PathBuf::from)
+        .or(spec.kaniko_context_dir.map(PathBuf::from))
+        .unwrap_or_else(|| cluster_data_root.get_root_path().join("kaniko"));
+    let base_image = cli_value(&matches, "base_image", has_config)
+        .or(spec.base_image.as_deref())
+        .unwrap_or_else(|| matches.value_of("base_image").unwrap())
+        .to_string();
+    let image_name = cli_value(&matches, "image_name", has_config)
+        .or(spec.image_name.as_deref())
+        .unwrap_or_else(|| matches.value_of("image_name").unwrap())
+        .to_string();
+
+    let dry_run = matches
+        .value_of("output_dir")
+        .map(|output_dir| {
+            ManifestWriter::new(
+                Path::new(output_dir),
+                &namespace,
+                !matches.is_present("show_secrets"),
+            )
+        })
+        .transpose()?;
+
+    let readiness_timeout = cli_value_t::<u64>(&matches, "readiness_timeout", has_config)
+        .or(spec.readiness_timeout)
+        .unwrap_or(DEFAULT_READINESS_TIMEOUT_SECS);
+    let readiness_rpc = if matches.is_present("skip_readiness_check") || dry_run.is_some() {
+        None
+    } else {
+        Some(match matches.value_of("readiness_rpc_url") {
+            Some(url) => ReadinessRpc::Url(url.to_string()),
+            None => ReadinessRpc::LoadBalancer,
+        })
+    };
 
-    let image_tag = build_config.prepare().await?.replace('.', "-"); // can't use "." or "_" in k8s names;
-    info!("Setup Validator Environment. Image tag: {image_tag}");
+    let mut startup_script_overrides: BTreeMap<String, PathBuf> = spec
+        .startup_scripts
+        .into_iter()
+        .map(|(file_name, path)| (file_name, PathBuf::from(path)))
+        .collect();
+    if let Some(overrides) = matches.values_of("startup_script") {
+        for (file_name, path) in overrides.filter_map(|value| parse_env_var(value).ok()) {
+            startup_script_overrides.insert(file_name, PathBuf::from(path));
+        }
+    }
+    let startup_scripts = StartupScripts::load(&startup_script_overrides)?;
 
-    let mut kub_controller = Kubernetes::new(
-        environment_config.namespace,
-        &mut validator_config,
-        client_config.clone(),
+    let keypair_dir = cli_value(&matches, "keypair_dir", has_config)
+        .map(PathBuf::from)
+        .or(spec.keypair_dir.map(PathBuf::from));
+    if let Some(keypair_dir) = &keypair_dir {
+        check_directory(keypair_dir, "Keypair")?;
+    }
+
+    let settings = ClusterSettings {
+        environment_config,
+        genesis_flags,
+        keypair_dir,
+        artifact_cache,
+        build_cache,
+        startup_scripts,
         pod_requests,
+        node_placement,
         metrics,
-        image_tag.clone(),
-    )
-    .await;
+        docker: DockerConfig::new(
+            base_image,
+            new_image_builder(image_builder, kaniko_context_dir),
+            image_distribution,
+            local_cluster,
+        ),
+        image_distribution,
+        registry_name,
+        image_name,
+        dry_run,
+        readiness: Readiness::new(Duration::from_secs(readiness_timeout)),
+        readiness_rpc,
+    };
 
-    let exists = kub_controller.namespace_exists().await?;
-    if !exists {
-        return Err(format!(
-            "Namespace: '{}' doesn't exist. Exiting...",
-            environment_config.namespace
+    for deployment in deployments {
+        let result = deploy(
+            &settings,
+            &mut validator_config,
+            deployment,
+            &mut keygen_seed,
+            deploy_bootstrap_validator,
         )
-        .into());
+        .await;
+        if let Err(err) = result {
+            if matches.is_present("collect_logs_on_failure") && settings.dry_run.is_none() {
+                error!("Deployment failed: {err}. Collecting logs");
+                if let Err(collect_err) = collect_logs(
+                    settings.environment_config.namespace,
+                    &settings.environment_config.cluster_data_path,
+                    None,
+                )
+                .await
+                {
+                    warn!("Failed to collect logs: {collect_err}");
+                }
+            }
+            return Err(err);
+        }
+        deploy_bootstrap_validator = false;
+    }
+
+    Ok(())
+}
+
+/// --artifact-cache, else `artifact-cache` in --config, else <cluster-data-path>/artifact-cache
+fn artifact_cache_dir(
+    matches: &ArgMatches,
+    spec_dir: Option<&str>,
+    cluster_data_path: Option<&Path>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match (
+        matches.value_of("artifact_cache").or(spec_dir),
+        cluster_data_path,
+    ) {
+        (Some(dir), _) => Ok(PathBuf::from(dir)),
+        (None, Some(cluster_data_path)) => Ok(cluster_data_path.join(DEFAULT_ARTIFACT_CACHE_DIR)),
+        (None, None) => Err("Must provide --artifact-cache or --cluster-data-path".into()),
+    }
+}
+
+/// --config, and whether it was given
+fn load_spec(matches: &ArgMatches) -> Result<(ClusterSpec, bool), Box<dyn std::error::Error>> {
+    let spec = matches
+        .value_of("config")
+        .map(|path| ClusterSpec::load(Path::new(path)))
+        .transpose()?;
+    let has_config = spec.is_some();
+    Ok((spec.unwrap_or_default(), has_config))
+}
+
+/// The pod requests from the command line, else from the config file, else the flag defaults
+fn pod_requests(
+    matches: &ArgMatches,
+    spec_requests: Option<&PodRequests>,
+    has_config: bool,
+) -> PodRequests {
+    PodRequests::new(
+        cli_value(matches, "cpu_requests", has_config)
+            .or(spec_requests.and_then(PodRequests::cpu))
+            .unwrap_or_else(|| matches.value_of("cpu_requests").unwrap())
+            .to_string(),
+        cli_value(matches, "memory_requests", has_config)
+            .or(spec_requests.and_then(PodRequests::memory))
+            .unwrap_or_else(|| matches.value_of("memory_requests").unwrap())
+            .to_string(),
+    )
+}
+
+/// The namespace and cluster data path from the command line, else from `spec`
+fn spec_location(
+    matches: &ArgMatches,
+    spec: &ClusterSpec,
+    has_config: bool,
+) -> (String, Option<PathBuf>) {
+    let namespace = cli_value(matches, "cluster_namespace", has_config)
+        .map(String::from)
+        .or(spec.namespace.clone())
+        .unwrap_or_else(|| "default".to_string());
+    let cluster_data_path = matches
+        .value_of("cluster_data_path")
+        .map(PathBuf::from)
+        .or(spec.cluster_data_path.as_ref().map(PathBuf::from));
+    (namespace, cluster_data_path)
+}
+
+/// The namespace and cluster data path the subcommands act on, resolved like a deploy's
+fn cluster_location(
+    matches: &ArgMatches,
+) -> Result<(String, Option<PathBuf>), Box<dyn std::error::Error>> {
+    let (spec, has_config) = load_spec(matches)?;
+    Ok(spec_location(matches, &spec, has_config))
+}
+
+/// Saves the logs of the pods in `namespace` to <cluster-data-path>/logs
+async fn collect_logs(
+    namespace: &str,
+    cluster_data_path: &Path,
+    deployment_tag: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = ClusterBackend::new().await?;
+    LogCollector::new(&backend, namespace, cluster_data_path, deployment_tag)
+        .collect()
+        .await?;
+    Ok(())
+}
+
+/// Adds or removes nodes of a running deployment, waiting for the new ReplicaSets to be available
+async fn scale(
+    matches: &ArgMatches,
+    scale_matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (spec, has_config) = load_spec(matches)?;
+    let (namespace, cluster_data_path) = spec_location(matches, &spec, has_config);
+    let cluster_data_path = cluster_data_path.ok_or(
+        "Must provide --cluster-data-path or `cluster-data-path` in --config to scale a deployment",
+    )?;
+    let namespace = namespace.as_str();
+    // deployment tags use "-" in place of "." in k8s names
+    let deployment_tag = scale_matches
+        .value_of("deployment_tag")
+        .unwrap()
+        .replace('.', "-");
+
+    // new nodes copy an existing ReplicaSet, so only the secrets and keys use the rest of the config
+    let mut validator_config = spec.validator.unwrap_or_default();
+    apply_validator_overrides(matches, &mut validator_config, has_config);
+    let mut genesis_flags = spec.genesis.unwrap_or_default();
+    apply_genesis_overrides(matches, &mut genesis_flags, has_config);
+    let backend = ClusterBackend::new().await?;
+    let kub_controller = Kubernetes::new(
+        &backend,
+        namespace,
+        &mut validator_config,
+        ClientConfig::None,
+        pod_requests(matches, spec.pod_requests.as_ref(), has_config),
+        NodePlacement::default(),
+        None,
+        deployment_tag.clone(),
+    );
+    if !kub_controller.namespace_exists().await? {
+        return Err(format!("Namespace: '{namespace}' doesn't exist. Exiting...").into());
+    }
+
+    // added nodes continue the keys of the cluster's recorded seed
+    let mut keygen_seed = KeygenSeed::resume(&cluster_data_path, genesis_flags.keygen_seed.take())?;
+    genesis_flags.keygen_seed = Some(keygen_seed.seed().to_string());
+    let config_directory = cluster_data_path.join("config-k8s");
+    let mut genesis = Genesis::new(
+        config_directory.clone(),
+        genesis_flags,
+        true,
+        Some(keygen_seed.next_deployment()?),
+    )?;
+    let scaler = Scaler::new(&kub_controller, &config_directory);
+
+    let stake_deactivator = if scale_matches.is_present("deactivate_stake") {
+        let rpc_url = match scale_matches.value_of("rpc_url") {
+            Some(url) => url.to_string(),
+            None => kub_controller
+                .load_balancer_rpc_url()
+                .await?
+                .ok_or("Could not find an address for the load balancer. Pass --rpc-url")?,
+        };
+        Some(StakeDeactivator::new(
+            cluster_data_path.join(format!("{SOLANA_RELEASE}/bin/solana")),
+            rpc_url,
+        ))
+    } else {
+        None
+    };
+
+    let mut new_replica_sets = vec![];
+    for (arg, node_type) in [
+        ("validators", NodeType::Standard),
+        ("rpc_nodes", NodeType::RPC),
+        ("clients", NodeType::Client(ClientType::BenchTps, 0)),
+    ] {
+        let delta = scale_matches
+            .value_of(arg)
+            .map(i64::from_str)
+            .transpose()?
+            .unwrap_or(0);
+        let count = delta.unsigned_abs() as usize;
+        if delta > 0 {
+            new_replica_sets.extend(scaler.scale_up(&mut genesis, node_type, count).await?);
+        } else if delta < 0 {
+            scaler
+                .scale_down(node_type, count, stake_deactivator.as_ref())
+                .await?;
+        }
+    }
+    info!("Scaled deployment {deployment_tag}");
+
+    if !new_replica_sets.is_empty() && !matches.is_present("skip_readiness_check") {
+        let timeout = cli_value_t::<u64>(matches, "readiness_timeout", has_config)
+            .or(spec.readiness_timeout)
+            .unwrap_or(DEFAULT_READINESS_TIMEOUT_SECS);
+        Readiness::new(Duration::from_secs(timeout))
+            .wait_for_replica_sets(&kub_controller, &new_replica_sets, true)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn deploy(
+    settings: &ClusterSettings<'_>,
+    validator_config: &mut ValidatorConfig,
+    deployment: DeploymentSpec,
+    keygen_seed: &mut KeygenSeed,
+    deploy_bootstrap_validator: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let num_validators = deployment.num_validators;
+    let num_rpc_nodes = deployment.num_rpc_nodes;
+    let client_config = deployment.client.unwrap_or(ClientConfig::None);
+
+    if num_rpc_nodes == 0 && !validator_config.enable_full_rpc {
+        return Err("Must have at least one RPC node and/or enable full RPC services `--full-rpc`. Exiting...".into());
     }
 
-    let deploy_bootstrap_validator = !matches.is_present("no_bootstrap");
+    let validator_stakes = deployment
+        .stake_distribution
+        .stakes(num_validators, validator_config.internal_node_stake_sol)
+        .await?;
+    info!("Validator stakes (SOL): {validator_stakes:?}");
+    validator_config.validator_stakes = validator_stakes.clone();
+    let internal_node_sol = validator_config.internal_node_sol;
+    let commission = validator_config.commission;
+    // only the validators deployed alongside the bootstrap can be in genesis
+    let accounts_in_genesis =
+        deploy_bootstrap_validator && settings.genesis_flags.validators_in_genesis;
+    validator_config.accounts_in_genesis = accounts_in_genesis;
+
+    let cluster_data_root =
+        ClusterDataRoot::new_from_path(settings.environment_config.cluster_data_path.clone());
+    let exec_path = cluster_data_root
+        .get_root_path()
+        .join(format!("{SOLANA_RELEASE}/bin"));
+
+    let build_config = BuildConfig::new(
+        deployment.deploy_method,
+        deployment.build_type.unwrap_or(BuildType::Release),
+        cluster_data_root.get_root_path(),
+        settings.artifact_cache.clone(),
+        settings.build_cache.clone(),
+        deployment.build_options,
+        deployment.release_sha256,
+    )?;
+
+    let image_tag = build_config.prepare().await?.replace('.', "-"); // can't use "." or "_" in k8s names;
+    info!("Setup Validator Environment. Image tag: {image_tag}");
+
+    let backend: Box<dyn KubeBackend> = match &settings.dry_run {
+        Some(manifest_writer) => Box::new(manifest_writer.clone()),
+        None => Box::new(ClusterBackend::new().await?),
+    };
+    check_namespace(backend.as_ref(), settings.environment_config.namespace).await?;
+
     let config_directory = cluster_data_root.get_root_path().join("config-k8s");
     let retain_previous_genesis = !deploy_bootstrap_validator;
     let mut genesis = Genesis::new(
         config_directory.clone(),
-        genesis_flags,
+        settings.genesis_flags.clone(),
         retain_previous_genesis,
-    );
+        Some(keygen_seed.next_deployment()?),
+    )?;
+    if let Some(keypair_dir) = settings
+        .keypair_dir
+        .as_ref()
+        .filter(|_| deploy_bootstrap_validator)
+    {
+        genesis.set_keypair_dir(keypair_dir.clone());
+        genesis.check_imported_keypairs(&[
+            (NodeType::Bootstrap, 1),
+            (NodeType::Standard, num_validators),
+            (NodeType::RPC, num_rpc_nodes),
+        ])?;
+    }
+    if !accounts_in_genesis {
+        genesis.check_faucet_funds(&validator_stakes, internal_node_sol)?;
+    }
 
     if deploy_bootstrap_validator {
         genesis.generate_faucet()?;
@@ -682,15 +1914,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         genesis.generate_accounts(NodeType::Bootstrap, 1, None)?;
         info!("Generated bootstrap account");
 
+        if accounts_in_genesis {
+            genesis.generate_accounts(NodeType::Standard, num_validators, Some(&image_tag))?;
+            genesis.write_validator_accounts(
+                &image_tag,
+                &validator_stakes,
+                internal_node_sol,
+                commission,
+            )?;
+        }
+
         // creates genesis and writes to binary file
         genesis
-            .generate(cluster_data_root.get_root_path(), &exec_path)
+            .generate(
+                cluster_data_root.get_root_path(),
+                &exec_path,
+                &settings.artifact_cache,
+            )
             .await?;
         info!("Genesis created");
     }
 
     // generate standard validator accounts
-    genesis.generate_accounts(NodeType::Standard, num_validators, Some(&image_tag))?;
+    if !accounts_in_genesis {
+        genesis.generate_accounts(NodeType::Standard, num_validators, Some(&image_tag))?;
+    }
     info!("Generated {num_validators} validator account(s)");
 
     genesis.generate_accounts(NodeType::RPC, num_rpc_nodes, Some(&image_tag))?;
@@ -698,14 +1946,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let ledger_dir = config_directory.join("bootstrap-validator");
     let shred_version = LedgerHelper::get_shred_version(&ledger_dir)?;
-    kub_controller.set_shred_version(shred_version);
     info!("Shred Version: {shred_version}");
 
-    //unwraps are safe here. since their requirement is enforced by argmatches
-    let docker = DockerConfig::new(matches.value_of("base_image").unwrap().to_string());
-
-    let registry_name = matches.value_of("registry_name").unwrap().to_string();
-    let image_name = matches.value_of("image_name").unwrap().to_string();
+    let docker = &settings.docker;
+    let registry_name = settings.registry_name.clone();
+    let image_name = settings.image_name.clone();
 
     let mut cluster_images = ClusterImages::default();
     if deploy_bootstrap_validator {
@@ -767,300 +2012,49 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         cluster_images.set_item(client);
     }
 
-    for v in cluster_images.get_all() {
-        if let NodeType::Client(ClientType::Generic, _) = v.node_type() {
-            continue;
-        }
-        docker.build_image(cluster_data_root.get_root_path(), v.image())?;
-        info!("Built {} image", v.node_type());
-    }
-
-    let images_to_push: Vec<&Node> = cluster_images
-        .get_all()
-        .filter(|v| !matches!(v.node_type(), NodeType::Client(ClientType::Generic, _)))
-        .collect();
-    docker.push_images(images_to_push)?;
-    info!("Pushed {} docker images", cluster_images.get_all().count());
-
-    // metrics secret create once and use by all pods
-    // do not redploy this service for heterogeneous clusters
-    if kub_controller.metrics.is_some() && deploy_bootstrap_validator {
-        let metrics_secret = kub_controller.create_metrics_secret()?;
-        kub_controller.deploy_secret(&metrics_secret).await?;
-    };
-
-    if deploy_bootstrap_validator {
-        let bootstrap_validator = cluster_images.bootstrap()?;
-        let secret = kub_controller
-            .create_bootstrap_secret("bootstrap-accounts-secret", &config_directory)?;
-        bootstrap_validator.set_secret(secret);
-
-        kub_controller
-            .deploy_secret(bootstrap_validator.secret())
-            .await?;
-        info!("Deployed Bootstrap Secret");
-
-        // Create Bootstrap labels
-        // Bootstrap needs two labels, one for each service.
-        // One for Load Balancer, one direct
-        let identity_path = config_directory.join("bootstrap-validator/identity.json");
-        let bootstrap_keypair =
-            read_keypair_file(identity_path).expect("Failed to read bootstrap keypair file");
-        kub_controller.add_known_validator(bootstrap_keypair.pubkey());
-
-        if kub_controller.validator_supports_full_rpc() {
-            bootstrap_validator.add_label(
-                "load-balancer/name",
-                "load-balancer-selector",
-                LabelType::Service,
-            );
-        }
-        bootstrap_validator.add_label(
-            "service/name",
-            "bootstrap-validator-selector",
-            LabelType::Service,
-        );
-        bootstrap_validator.add_label(
-            "validator/type",
-            bootstrap_validator.node_type().to_string(),
-            LabelType::Info,
-        );
-        bootstrap_validator.add_label(
-            "validator/identity",
-            bootstrap_keypair.pubkey().to_string(),
-            LabelType::Info,
-        );
-
-        // create bootstrap replica set
-        let replica_set = kub_controller.create_bootstrap_validator_replica_set(
-            bootstrap_validator.image(),
-            bootstrap_validator.secret().metadata.name.clone(),
-            &bootstrap_validator.all_labels(),
-        )?;
-        bootstrap_validator.set_replica_set(replica_set);
-
-        // deploy bootstrap replica set
-        kub_controller
-            .deploy_replicas_set(bootstrap_validator.replica_set())
-            .await?;
-        info!("Deployed {}", bootstrap_validator.replica_set_name());
-
-        // create and deploy bootstrap-service
-        let bootstrap_service = kub_controller.create_bootstrap_service(
-            "bootstrap-validator-service",
-            bootstrap_validator.service_labels(),
-        );
-        kub_controller.deploy_service(&bootstrap_service).await?;
-        info!("Deployed Bootstrap Validator Service");
-
-        // load balancer service. only create one and use for all bootstrap/rpc nodes
-        // service selector matches bootstrap selector
-        let load_balancer_label =
-            kub_controller.create_selector("load-balancer/name", "load-balancer-selector");
-        //create load balancer
-        let load_balancer = kub_controller
-            .create_validator_load_balancer(
-                "bootstrap-and-rpc-node-lb-service",
-                &load_balancer_label,
-            )
-            .await?;
-
-        //deploy load balancer
-        kub_controller.deploy_service(&load_balancer).await?;
-        info!("Deployed Load Balancer Service");
-
-        // wait for bootstrap replicaset to deploy
-        while !kub_controller
-            .is_replica_set_ready(bootstrap_validator.replica_set_name().as_str())
-            .await?
-        {
+    if settings.dry_run.is_some() {
+        info!("Dry run. Skipping docker build and push");
+    } else {
+        let images_to_build = cluster_images
+            .get_all()
+            .filter(|v| !matches!(v.node_type(), NodeType::Client(ClientType::Generic, _)))
+            .map(|v| v.image());
+        let images = docker.build_images(cluster_data_root.get_root_path(), images_to_build)?;
+        info!("Built {} docker images", images.len());
+
+        let pushed_images = docker.push_images(&images)?;
+        info!("{pushed_images}");
+        if !pushed_images.external.is_empty() {
             info!(
-                "replica set: {} not ready...",
-                bootstrap_validator.replica_set_name()
+                "Deploying before the images are pushed. Pods retry pulling them until the \
+                 external builder pushes them, for up to --readiness-timeout"
             );
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         }
     }
 
-    if num_rpc_nodes > 0 {
-        let rpc_node = cluster_images.rpc()?;
-        let mut rpc_nodes = vec![];
-        // Create and deploy rpc secrets
-        for rpc_index in 0..num_rpc_nodes {
-            let rpc_secret = kub_controller.create_rpc_secret(rpc_index, &config_directory)?;
-            rpc_node.set_secret(rpc_secret);
-            kub_controller.deploy_secret(rpc_node.secret()).await?;
-            info!("Deployed RPC Node {rpc_index} Secret");
-
-            let identity_path =
-                config_directory.join(format!("rpc-node-identity-{image_tag}-{rpc_index}.json"));
-            let rpc_keypair =
-                read_keypair_file(identity_path).expect("Failed to read rpc-node keypair file");
-
-            rpc_node.add_label(
-                "rpc-node/name",
-                &format!("rpc-node-{rpc_index}"),
-                LabelType::Service,
-            );
-
-            rpc_node.add_label(
-                "rpc-node/type",
-                rpc_node.node_type().to_string(),
-                LabelType::Info,
-            );
-
-            rpc_node.add_label(
-                "rpc-node/identity",
-                rpc_keypair.pubkey().to_string(),
-                LabelType::Info,
-            );
-
-            rpc_node.add_label(
-                "load-balancer/name",
-                "load-balancer-selector",
-                LabelType::Service,
-            );
-
-            let replica_set = kub_controller.create_rpc_replica_set(
-                rpc_node.image(),
-                rpc_node.secret().metadata.name.clone(),
-                &rpc_node.all_labels(),
-                rpc_index,
-            )?;
-            rpc_node.set_replica_set(replica_set);
-
-            kub_controller
-                .deploy_replicas_set(rpc_node.replica_set())
-                .await?;
-            info!("Deployed RPC Node Replica Set ({rpc_index})");
-
-            let rpc_service = kub_controller.create_service(
-                "rpc-node-service",
-                rpc_index,
-                rpc_node.service_labels(),
-            );
-            kub_controller.deploy_service(&rpc_service).await?;
-            info!("Deployed RPC Node Service ({rpc_index})");
-
-            rpc_nodes.push(rpc_node.replica_set_name().clone());
-        }
-
-        // wait for at least one rpc node to deploy
-        'outer: loop {
-            for rpc_name in &rpc_nodes {
-                if kub_controller.is_replica_set_ready(rpc_name).await? {
-                    break 'outer;
-                }
-            }
-
-            info!("RPC Nodes not ready yet");
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-        }
-    }
-
-    if num_validators > 0 {
-        let validator = cluster_images.validator()?;
-        for validator_index in 0..num_validators {
-            // Create and deploy validators secrets
-            let validator_secret =
-                kub_controller.create_validator_secret(validator_index, &config_directory)?;
-            validator.set_secret(validator_secret);
-            kub_controller.deploy_secret(validator.secret()).await?;
-            info!("Deployed Validator {validator_index} Secret");
-
-            let identity_path = config_directory.join(format!(
-                "validator-identity-{image_tag}-{validator_index}.json"
-            ));
-            let validator_keypair =
-                read_keypair_file(identity_path).expect("Failed to read validator keypair file");
-
-            validator.add_label(
-                "validator/name",
-                &format!("validator-{validator_index}"),
-                LabelType::Service,
-            );
-            validator.add_label(
-                "validator/type",
-                validator.node_type().to_string(),
-                LabelType::Info,
-            );
-            validator.add_label(
-                "validator/identity",
-                validator_keypair.pubkey().to_string(),
-                LabelType::Info,
-            );
-
-            let replica_set = kub_controller.create_validator_replica_set(
-                validator.image(),
-                validator.secret().metadata.name.clone(),
-                &validator.all_labels(),
-                validator_index,
-            )?;
-            validator.set_replica_set(replica_set);
-
-            kub_controller
-                .deploy_replicas_set(validator.replica_set())
-                .await?;
-            info!("Deployed Validator Replica Set ({validator_index})");
-
-            let validator_service = kub_controller.create_service(
-                "validator-service",
-                validator_index,
-                validator.service_labels(),
-            );
-            kub_controller.deploy_service(&validator_service).await?;
-            info!("Deployed Validator Service ({validator_index})");
-        }
-    }
-
-    if let ClientConfig::Generic(ref generic_config) = client_config {
-        info!(
-            "Waiting {}s before deploying client",
-            generic_config.delay_start
-        );
-        std::thread::sleep(std::time::Duration::from_secs(generic_config.delay_start));
-    }
-
-    for client_node in cluster_images.get_clients_mut() {
-        let client_index = if let NodeType::Client(_, index) = client_node.node_type() {
-            *index
-        } else {
-            return Err("Invalid Validator Type in Client".into());
-        };
-
-        let client_secret = kub_controller.create_client_secret(client_index, &config_directory)?;
-        client_node.set_secret(client_secret);
-
-        kub_controller.deploy_secret(client_node.secret()).await?;
-        info!("Deployed Client {client_index} Secret");
-
-        client_node.add_label(
-            "client/name",
-            format!("client-{client_index}"),
-            LabelType::Service,
-        );
-
-        let client_replica_set = kub_controller.create_client_replica_set(
-            client_node.image(),
-            client_node.secret().metadata.name.clone(),
-            &client_node.all_labels(),
-            client_index,
-        )?;
-        client_node.set_replica_set(client_replica_set);
-
-        kub_controller
-            .deploy_replicas_set(client_node.replica_set())
-            .await?;
-        info!("Deployed Client Replica Set ({client_index})");
-
-        let client_service = kub_controller.create_service(
-            "client-service",
-            client_index,
-            client_node.service_labels(),
-        );
-        kub_controller.deploy_service(&client_service).await?;
-        info!("Deployed Client Service ({client_index})");
-    }
+    deploy_nodes(
+        backend.as_ref(),
+        validator_config,
+        NodeDeployment {
+            namespace: settings.environment_config.namespace,
+            deployment_tag: image_tag,
+            config_directory,
+            cluster_images,
+            client_config,
+            num_validators,
+            num_rpc_nodes,
+            deploy_bootstrap_validator,
+            shred_version,
+            startup_scripts: settings.startup_scripts.clone(),
+            pod_requests: settings.pod_requests.clone(),
+            node_placement: settings.node_placement.clone(),
+            metrics: settings.metrics.clone(),
+            image_distribution: settings.image_distribution,
+            readiness: &settings.readiness,
+            readiness_rpc: settings.readiness_rpc.as_ref(),
+        },
+    )
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,139 @@
+use {
+    crate::error::LabError,
+    k8s_openapi::{api::core::v1::Secret, serde::Serialize},
+    kube::Resource,
+    log::*,
+    std::{
+        collections::BTreeMap,
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+const REDACTED: &str = "REDACTED";
+
+/// Writes Kubernetes objects to `<output_dir>/<kind>-<name>.yaml` instead of applying them.
+/// Used by `--dry-run` so manifests can be reviewed, diffed, or applied with kubectl
+#[derive(Debug, Clone)]
+pub struct ManifestWriter {
+    output_dir: PathBuf,
+    namespace: String,
+    redact_secrets: bool,
+}
+
+impl ManifestWriter {
+    pub fn new(output_dir: &Path, namespace: &str, redact_secrets: bool) -> Result<Self, LabError> {
+        fs::create_dir_all(output_dir).map_err(|err| {
+            LabError::config(format!(
+                "Failed to create manifest output directory {output_dir:?}"
+            ))
+            .with_source(err)
+        })?;
+        Ok(Self {
+            output_dir: output_dir.to_path_buf(),
+            namespace: namespace.to_string(),
+            redact_secrets,
+        })
+    }
+
+    /// The namespace the manifests are meant to be applied to
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn write<K>(&self, object: &K) -> Result<PathBuf, LabError>
+    where
+        K: Resource<DynamicType = ()> + Serialize,
+    {
+        let kind = K::kind(&()).to_lowercase();
+        let name = object.meta().name.as_deref().ok_or_else(|| {
+            LabError::kube(format!("Cannot write a {kind} manifest without a name"))
+        })?;
+        let path = self.output_dir.join(format!("{kind}-{name}.yaml"));
+
+        let yaml = serde_yaml::to_string(object).map_err(|err| {
+            LabError::kube(format!("Failed to serialize {kind} {name}")).with_source(err)
+        })?;
+        fs::write(&path, yaml)
+            .map_err(|err| LabError::kube(format!("Failed to write {path:?}")).with_source(err))?;
+        info!("Wrote {path:?}");
+        Ok(path)
+    }
+
+    /// Secret values are replaced with a placeholder unless `--show-secrets` is set
+    pub fn write_secret(&self, secret: &Secret) -> Result<PathBuf, LabError> {
+        if !self.redact_secrets {
+            return self.write(secret);
+        }
+
+        let keys = secret
+            .data
+            .iter()
+            .flat_map(BTreeMap::keys)
+            .chain(secret.string_data.iter().flat_map(BTreeMap::keys));
+        let redacted = Secret {
+            data: None,
+            string_data: Some(
+                keys.map(|key| (key.clone(), REDACTED.to_string()))
+                    .collect(),
+            ),
+            ..secret.clone()
+        };
+        self.write(&redacted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine},
+        k8s_openapi::ByteString,
+        kube::api::ObjectMeta,
+        tempfile::TempDir,
+    };
+
+    #[test]
+    fn test_write_secret() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path();
+        let secret = Secret {
+            metadata: ObjectMeta {
+                name: Some("validator-accounts-secret-0".to_string()),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([(
+                "identity".to_string(),
+                ByteString(b"identity-keypair".to_vec()),
+            )])),
+            string_data: Some(BTreeMap::from([(
+                "metrics".to_string(),
+                "metrics-password".to_string(),
+            )])),
+            ..Default::default()
+        };
+        let read = |path: PathBuf| {
+            let yaml = fs::read_to_string(path).unwrap();
+            (yaml.clone(), serde_yaml::from_str::<Secret>(&yaml).unwrap())
+        };
+
+        let writer = ManifestWriter::new(output_dir, "lab", true).unwrap();
+        let (yaml, redacted) = read(writer.write_secret(&secret).unwrap());
+        assert!(!yaml.contains("metrics-password"));
+        assert!(!yaml.contains(&BASE64_STANDARD.encode("identity-keypair")));
+        assert_eq!(redacted.data, None);
+        assert_eq!(
+            redacted.string_data,
+            Some(BTreeMap::from([
+                ("identity".to_string(), REDACTED.to_string()),
+                ("metrics".to_string(), REDACTED.to_string()),
+            ]))
+        );
+
+        // --show-secrets
+        let writer = ManifestWriter::new(output_dir, "lab", false).unwrap();
+        let (_, shown) = read(writer.write_secret(&secret).unwrap());
+        assert_eq!(shown.data, secret.data);
+        assert_eq!(shown.string_data, secret.string_data);
+    }
+}
@@ -4,6 +4,17 @@ use {
     std::{collections::BTreeMap, string::String},
 };
 
+// Pod labels added with `add_label` at deploy time.
+// `status` and `collect-logs` read them back to tell which node a pod runs
+pub const VALIDATOR_TYPE_LABEL: &str = "validator/type";
+pub const VALIDATOR_NAME_LABEL: &str = "validator/name";
+pub const VALIDATOR_IDENTITY_LABEL: &str = "validator/identity";
+pub const RPC_NODE_TYPE_LABEL: &str = "rpc-node/type";
+pub const RPC_NODE_NAME_LABEL: &str = "rpc-node/name";
+pub const RPC_NODE_IDENTITY_LABEL: &str = "rpc-node/identity";
+pub const CLIENT_NAME_LABEL: &str = "client/name";
+pub const DEPLOYMENT_TAG_LABEL: &str = "deployment/tag";
+
 pub enum LabelType {
     Info,
     Service,
@@ -0,0 +1,387 @@
+use {
+    crate::{k8s_helpers::PodScheduling, NodeType},
+    k8s_openapi::{
+        api::core::v1::{
+            Affinity, NodeAffinity, NodeSelector, NodeSelectorRequirement, NodeSelectorTerm,
+            PodAffinityTerm, PodAntiAffinity, PreferredSchedulingTerm, Toleration,
+            TopologySpreadConstraint,
+        },
+        apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement},
+    },
+    serde::Deserialize,
+    std::{collections::BTreeMap, str::FromStr},
+};
+
+pub const DEFAULT_REGION_LABEL: &str = "topology.kubernetes.io/region";
+const HOSTNAME_LABEL: &str = "kubernetes.io/hostname";
+const PREFERRED_NODE_LABEL_WEIGHT: i32 = 100;
+
+/// A taint the pods tolerate, written `key[=value][:effect]`.
+/// e.g. `dedicated=validators:NoSchedule`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TolerationSpec {
+    key: String,
+    value: Option<String>,
+    effect: Option<String>,
+}
+
+impl FromStr for TolerationSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key_value, effect) = match s.split_once(':') {
+            Some((key_value, effect)) => (key_value, Some(effect)),
+            None => (s, None),
+        };
+        if let Some(effect) = effect {
+            if !["NoSchedule", "PreferNoSchedule", "NoExecute"].contains(&effect) {
+                return Err(format!(
+                    "Invalid toleration effect '{effect}' in '{s}'. \
+                    Expected NoSchedule, PreferNoSchedule, or NoExecute"
+                ));
+            }
+        }
+        let (key, value) = match key_value.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_string())),
+            None => (key_value, None),
+        };
+        if key.is_empty() {
+            return Err(format!("Toleration '{s}' is missing a key"));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value,
+            effect: effect.map(String::from),
+        })
+    }
+}
+
+impl TryFrom<String> for TolerationSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<&TolerationSpec> for Toleration {
+    fn from(spec: &TolerationSpec) -> Self {
+        let operator = if spec.value.is_some() {
+            "Equal"
+        } else {
+            "Exists"
+        };
+        Toleration {
+            key: Some(spec.key.clone()),
+            operator: Some(operator.to_string()),
+            value: spec.value.clone(),
+            effect: spec.effect.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Where the pods of one node type may be scheduled
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PlacementRules {
+    /// pods are only scheduled on nodes with all of these labels
+    pub required_node_labels: BTreeMap<String, String>,
+    /// pods favor nodes with these labels but fall back to any node
+    pub preferred_node_labels: BTreeMap<String, String>,
+    pub tolerations: Vec<TolerationSpec>,
+    /// spread pods of this node type evenly across regions
+    pub spread_across_regions: bool,
+}
+
+impl PlacementRules {
+    fn node_affinity(&self) -> Option<NodeAffinity> {
+        if self.required_node_labels.is_empty() && self.preferred_node_labels.is_empty() {
+            return None;
+        }
+
+        let requirement = |key: &String, value: &String| NodeSelectorRequirement {
+            key: key.clone(),
+            operator: "In".to_string(),
+            values: Some(vec![value.clone()]),
+        };
+
+        // requirements within a single term are ANDed together
+        let required = (!self.required_node_labels.is_empty()).then(|| NodeSelector {
+            node_selector_terms: vec![NodeSelectorTerm {
+                match_expressions: Some(
+                    self.required_node_labels
+                        .iter()
+                        .map(|(key, value)| requirement(key, value))
+                        .collect(),
+                ),
+                ..Default::default()
+            }],
+        });
+        let preferred = (!self.preferred_node_labels.is_empty()).then(|| {
+            self.preferred_node_labels
+                .iter()
+                .map(|(key, value)| PreferredSchedulingTerm {
+                    weight: PREFERRED_NODE_LABEL_WEIGHT,
+                    preference: NodeSelectorTerm {
+                        match_expressions: Some(vec![requirement(key, value)]),
+                        ..Default::default()
+                    },
+                })
+                .collect()
+        });
+
+        Some(NodeAffinity {
+            required_during_scheduling_ignored_during_execution: required,
+            preferred_during_scheduling_ignored_during_execution: preferred,
+        })
+    }
+}
+
+/// Selects every pod that has the label `key`, whatever its value
+fn label_exists_selector(key: &str) -> LabelSelector {
+    LabelSelector {
+        match_expressions: Some(vec![LabelSelectorRequirement {
+            key: key.to_string(),
+            operator: "Exists".to_string(),
+            values: None,
+        }]),
+        ..Default::default()
+    }
+}
+
+/// Per node type scheduling rules for the cluster's pods.
+/// Read from `placement` in --config and the `--node-affinity`, `--preferred-node-affinity`,
+/// `--toleration`, `--spread-across-regions`, and `--validator-anti-affinity` flags
+///
+/// e.g. cluster.toml
+/// ```toml
+/// [placement]
+/// validator-anti-affinity = true
+///
+/// [placement.bootstrap-validator]
+/// required-node-labels = { "topology.kubernetes.io/region" = "us-east" }
+///
+/// [placement.validator]
+/// preferred-node-labels = { provider = "equinix" }
+/// tolerations = ["dedicated=validators:NoSchedule"]
+/// spread-across-regions = true
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct NodePlacement {
+    pub bootstrap_validator: PlacementRules,
+    pub validator: PlacementRules,
+    pub rpc_node: PlacementRules,
+    pub client: PlacementRules,
+    /// never schedule two validators (including the bootstrap) on the same host
+    pub validator_anti_affinity: bool,
+    /// node label holding the node's region
+    pub region_label: String,
+}
+
+impl Default for NodePlacement {
+    fn default() -> Self {
+        Self {
+            bootstrap_validator: PlacementRules::default(),
+            validator: PlacementRules::default(),
+            rpc_node: PlacementRules::default(),
+            client: PlacementRules::default(),
+            validator_anti_affinity: false,
+            region_label: DEFAULT_REGION_LABEL.to_string(),
+        }
+    }
+}
+
+impl NodePlacement {
+    pub fn rules(&self, node_type: &NodeType) -> &PlacementRules {
+        match node_type {
+            NodeType::Bootstrap => &self.bootstrap_validator,
+            NodeType::Standard => &self.validator,
+            NodeType::RPC => &self.rpc_node,
+            NodeType::Client(_, _) => &self.client,
+        }
+    }
+
+    /// Returns the rules for a node type name, or all of them for "all"
+    pub fn rules_mut(&mut self, node_type: &str) -> Result<Vec<&mut PlacementRules>, String> {
+        match node_type {
+            "bootstrap-validator" => Ok(vec![&mut self.bootstrap_validator]),
+            "validator" => Ok(vec![&mut self.validator]),
+            "rpc-node" => Ok(vec![&mut self.rpc_node]),
+            "client" => Ok(vec![&mut self.client]),
+            "all" => Ok(vec![
+                &mut self.bootstrap_validator,
+                &mut self.validator,
+                &mut self.rpc_node,
+                &mut self.client,
+            ]),
+            _ => Err(format!(
+                "Invalid node type '{node_type}'. Expected bootstrap-validator, validator, \
+                rpc-node, client, or all"
+            )),
+        }
+    }
+
+    /// Label key shared by all pods of the same kind, used to find a pod's peers
+    fn group_label(node_type: &NodeType) -> &'static str {
+        match node_type {
+            NodeType::Bootstrap | NodeType::Standard => "validator/type",
+            NodeType::RPC => "rpc-node/type",
+            NodeType::Client(_, _) => "client/name",
+        }
+    }
+
+    pub fn scheduling(&self, node_type: &NodeType) -> PodScheduling {
+        let rules = self.rules(node_type);
+
+        let pod_anti_affinity = (self.validator_anti_affinity
+            && matches!(node_type, NodeType::Bootstrap | NodeType::Standard))
+        .then(|| PodAntiAffinity {
+            required_during_scheduling_ignored_during_execution: Some(vec![PodAffinityTerm {
+                label_selector: Some(label_exists_selector(Self::group_label(node_type))),
+                topology_key: HOSTNAME_LABEL.to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let node_affinity = rules.node_affinity();
+        let affinity = (node_affinity.is_some() || pod_anti_affinity.is_some()).then(|| Affinity {
+            node_affinity,
+            pod_anti_affinity,
+            ..Default::default()
+        });
+
+        let tolerations = (!rules.tolerations.is_empty())
+            .then(|| rules.tolerations.iter().map(Toleration::from).collect());
+
+        // best effort so pods still schedule if a region runs out of capacity
+        let topology_spread_constraints = rules.spread_across_regions.then(|| {
+            vec![TopologySpreadConstraint {
+                max_skew: 1,
+                topology_key: self.region_label.clone(),
+                when_unsatisfiable: "ScheduleAnyway".to_string(),
+                label_selector: Some(label_exists_selector(Self::group_label(node_type))),
+                ..Default::default()
+            }]
+        });
+
+        PodScheduling {
+            affinity,
+            tolerations,
+            topology_spread_constraints,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ClientType};
+
+    #[test]
+    fn test_toleration_spec_from_str() {
+        let toleration = |key: &str, value: Option<&str>, effect: Option<&str>| TolerationSpec {
+            key: key.to_string(),
+            value: value.map(String::from),
+            effect: effect.map(String::from),
+        };
+        assert_eq!(
+            TolerationSpec::from_str("dedicated"),
+            Ok(toleration("dedicated", None, None))
+        );
+        assert_eq!(
+            TolerationSpec::from_str("dedicated=validators"),
+            Ok(toleration("dedicated", Some("validators"), None))
+        );
+        assert_eq!(
+            TolerationSpec::from_str("dedicated:NoSchedule"),
+            Ok(toleration("dedicated", None, Some("NoSchedule")))
+        );
+        assert_eq!(
+            TolerationSpec::from_str("dedicated=validators:NoExecute"),
+            Ok(toleration(
+                "dedicated",
+                Some("validators"),
+                Some("NoExecute")
+            ))
+        );
+        assert!(TolerationSpec::from_str("dedicated:NoRun").is_err());
+        assert!(TolerationSpec::from_str("=validators").is_err());
+        assert!(TolerationSpec::from_str(":NoSchedule").is_err());
+
+        let exists = Toleration::from(&toleration("dedicated", None, None));
+        assert_eq!(exists.operator.as_deref(), Some("Exists"));
+        let equal = Toleration::from(&toleration("dedicated", Some("validators"), None));
+        assert_eq!(equal.operator.as_deref(), Some("Equal"));
+    }
+
+    #[test]
+    fn test_scheduling() {
+        let mut placement = NodePlacement {
+            validator_anti_affinity: true,
+            ..NodePlacement::default()
+        };
+        placement.validator.required_node_labels =
+            BTreeMap::from([("provider".to_string(), "equinix".to_string())]);
+        placement.validator.preferred_node_labels =
+            BTreeMap::from([(DEFAULT_REGION_LABEL.to_string(), "us-east".to_string())]);
+        placement.validator.tolerations = vec!["dedicated=validators:NoSchedule".parse().unwrap()];
+        placement.rpc_node.spread_across_regions = true;
+
+        // validators get node affinity, anti-affinity to each other, and tolerations
+        let scheduling = placement.scheduling(&NodeType::Standard);
+        let affinity = scheduling.affinity.unwrap();
+        let node_affinity = affinity.node_affinity.unwrap();
+        let required = &node_affinity
+            .required_during_scheduling_ignored_during_execution
+            .unwrap()
+            .node_selector_terms;
+        assert_eq!(required.len(), 1);
+        let expressions = required[0].match_expressions.as_ref().unwrap();
+        assert_eq!(expressions[0].key, "provider");
+        assert_eq!(expressions[0].operator, "In");
+        assert_eq!(expressions[0].values, Some(vec!["equinix".to_string()]));
+        let preferred = node_affinity
+            .preferred_during_scheduling_ignored_during_execution
+            .unwrap();
+        assert_eq!(preferred.len(), 1);
+        assert_eq!(preferred[0].weight, PREFERRED_NODE_LABEL_WEIGHT);
+        let anti_affinity = affinity
+            .pod_anti_affinity
+            .unwrap()
+            .required_during_scheduling_ignored_during_execution
+            .unwrap();
+        assert_eq!(anti_affinity[0].topology_key, HOSTNAME_LABEL);
+        assert_eq!(
+            anti_affinity[0].label_selector,
+            Some(label_exists_selector("validator/type"))
+        );
+        assert_eq!(scheduling.tolerations.unwrap().len(), 1);
+        assert!(scheduling.topology_spread_constraints.is_none());
+
+        // the bootstrap keeps away from validators without their other rules
+        let scheduling = placement.scheduling(&NodeType::Bootstrap);
+        let affinity = scheduling.affinity.unwrap();
+        assert!(affinity.node_affinity.is_none());
+        assert!(affinity.pod_anti_affinity.is_some());
+        assert!(scheduling.tolerations.is_none());
+
+        // rpc nodes are spread across regions but may share hosts
+        let scheduling = placement.scheduling(&NodeType::RPC);
+        assert!(scheduling.affinity.is_none());
+        let spread = scheduling.topology_spread_constraints.unwrap();
+        assert_eq!(spread.len(), 1);
+        assert_eq!(spread[0].topology_key, DEFAULT_REGION_LABEL);
+        assert_eq!(spread[0].when_unsatisfiable, "ScheduleAnyway");
+        assert_eq!(
+            spread[0].label_selector,
+            Some(label_exists_selector("rpc-node/type"))
+        );
+
+        let scheduling = placement.scheduling(&NodeType::Client(ClientType::BenchTps, 0));
+        assert!(scheduling.affinity.is_none());
+        assert!(scheduling.tolerations.is_none());
+        assert!(scheduling.topology_spread_constraints.is_none());
+    }
+}
@@ -0,0 +1,329 @@
+use {
+    crate::{
+        cluster_rpc::{ClusterNode, ClusterRpc, VoteAccounts},
+        error::LabError,
+        kubernetes::Kubernetes,
+    },
+    log::*,
+    std::{
+        collections::HashSet,
+        fmt::{self, Display, Formatter},
+        time::{Duration, Instant},
+    },
+};
+
+pub const DEFAULT_READINESS_TIMEOUT_SECS: u64 = 900;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Once every validator is voting, roots must move this many slots past where they were
+const MIN_ROOT_ADVANCE: u64 = 10;
+
+/// Which of the validators we expect are in gossip and voting
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvergenceProgress {
+    pub expected: usize,
+    pub missing_from_gossip: Vec<String>,
+    /// not in the current (non-delinquent) vote accounts
+    pub not_voting: Vec<String>,
+}
+
+impl ConvergenceProgress {
+    pub fn new(
+        identities: &[String],
+        cluster_nodes: &[ClusterNode],
+        vote_accounts: &VoteAccounts,
+    ) -> Self {
+        let in_gossip: HashSet<&str> = cluster_nodes
+            .iter()
+            .map(|node| node.pubkey.as_str())
+            .collect();
+        let voting: HashSet<&str> = vote_accounts
+            .current
+            .iter()
+            .map(|account| account.node_pubkey.as_str())
+            .collect();
+        Self {
+            expected: identities.len(),
+            missing_from_gossip: identities
+                .iter()
+                .filter(|identity| !in_gossip.contains(identity.as_str()))
+                .cloned()
+                .collect(),
+            not_voting: identities
+                .iter()
+                .filter(|identity| !voting.contains(identity.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing_from_gossip.is_empty() && self.not_voting.is_empty()
+    }
+}
+
+impl Display for ConvergenceProgress {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} validators in gossip, {}/{} voting",
+            self.expected - self.missing_from_gossip.len(),
+            self.expected,
+            self.expected - self.not_voting.len(),
+            self.expected
+        )
+    }
+}
+
+/// Waits, up to a timeout, for ReplicaSets to become available and for the cluster to converge:
+/// every expected validator in gossip, voting, and roots advancing
+pub struct Readiness {
+    timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl Readiness {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            poll_interval: POLL_INTERVAL,
+        }
+    }
+
+    /// Waits for all of `replica_set_names` to be available, or any one of them if `wait_for_all` is false
+    pub async fn wait_for_replica_sets(
+        &self,
+        kubernetes: &Kubernetes<'_>,
+        replica_set_names: &[String],
+        wait_for_all: bool,
+    ) -> Result<(), LabError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let mut not_ready = vec![];
+            for name in replica_set_names {
+                if !kubernetes.is_replica_set_ready(name).await? {
+                    not_ready.push(name.as_str());
+                }
+            }
+            let ready = replica_set_names.len() - not_ready.len();
+            if not_ready.is_empty() || (!wait_for_all && ready > 0) {
+                return Ok(());
+            }
+            info!(
+                "{ready}/{} replica sets ready. Waiting for: {}",
+                replica_set_names.len(),
+                not_ready.join(", ")
+            );
+
+            if Instant::now() >= deadline {
+                return Err(LabError::readiness(format!(
+                    "Timed out after {}s waiting for replica sets: {}",
+                    self.timeout.as_secs(),
+                    not_ready.join(", ")
+                )));
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn poll(
+        rpc: &ClusterRpc,
+        identities: &[String],
+    ) -> Result<(ConvergenceProgress, u64), LabError> {
+        let cluster_nodes = rpc.get_cluster_nodes().await?;
+        let vote_accounts = rpc.get_vote_accounts().await?;
+        let root = rpc.get_slot("finalized").await?;
+        Ok((
+            ConvergenceProgress::new(identities, &cluster_nodes, &vote_accounts),
+            root,
+        ))
+    }
+
+    /// Waits until every validator in `identities` is in gossip with a current vote account,
+    /// then until roots advance. RPC errors are retried until the timeout since the RPC
+    /// nodes may still be starting
+    pub async fn wait_for_convergence(
+        &self,
+        rpc: &ClusterRpc,
+        identities: &[String],
+    ) -> Result<(), LabError> {
+        info!(
+            "Waiting up to {}s for {} validators to converge via {}",
+            self.timeout.as_secs(),
+            identities.len(),
+            rpc.url()
+        );
+        let deadline = Instant::now() + self.timeout;
+        let mut root_target = None;
+        loop {
+            let status = match Self::poll(rpc, identities).await {
+                Ok((progress, root)) if progress.is_complete() => {
+                    let target = *root_target.get_or_insert(root + MIN_ROOT_ADVANCE);
+                    if root >= target {
+                        info!("Cluster converged: {progress}, root {root}");
+                        return Ok(());
+                    }
+                    format!("{progress}, root {root} waiting for root {target}")
+                }
+                Ok((progress, root)) => {
+                    let mut status = format!("{progress}, root {root}");
+                    if !progress.missing_from_gossip.is_empty() {
+                        status.push_str(&format!(
+                            ". Missing from gossip: {}",
+                            progress.missing_from_gossip.join(", ")
+                        ));
+                    }
+                    if !progress.not_voting.is_empty() {
+                        status
+                            .push_str(&format!(". Not voting: {}", progress.not_voting.join(", ")));
+                    }
+                    status
+                }
+                Err(err) => err.to_string(),
+            };
+            info!("{status}");
+
+            if Instant::now() >= deadline {
+                return Err(LabError::readiness(format!(
+                    "Cluster did not converge within {}s: {status}",
+                    self.timeout.as_secs()
+                )));
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            client_config::ClientConfig,
+            cluster_rpc::VoteAccount,
+            kube_backend::{InMemoryBackend, KubeBackend},
+            kubernetes::PodRequests,
+            node_placement::NodePlacement,
+            validator_config::ValidatorConfig,
+        },
+        k8s_openapi::api::apps::v1::ReplicaSet,
+        kube::api::ObjectMeta,
+    };
+
+    const NAMESPACE: &str = "lab";
+
+    fn cluster_node(pubkey: &str) -> ClusterNode {
+        ClusterNode {
+            pubkey: pubkey.to_string(),
+            gossip: None,
+            rpc: None,
+            version: None,
+        }
+    }
+
+    fn vote_account(node_pubkey: &str) -> VoteAccount {
+        VoteAccount {
+            vote_pubkey: format!("vote-{node_pubkey}"),
+            node_pubkey: node_pubkey.to_string(),
+            activated_stake: 1,
+            last_vote: 10,
+            root_slot: 5,
+        }
+    }
+
+    #[test]
+    fn test_convergence_progress() {
+        let identities = ["a", "b", "c"].map(String::from);
+        let progress = ConvergenceProgress::new(
+            &identities,
+            &[cluster_node("a"), cluster_node("b"), cluster_node("other")],
+            &VoteAccounts {
+                current: vec![vote_account("a")],
+                // delinquent validators are not voting
+                delinquent: vec![vote_account("b")],
+            },
+        );
+        assert_eq!(progress.missing_from_gossip, vec!["c".to_string()]);
+        assert_eq!(progress.not_voting, vec!["b".to_string(), "c".to_string()]);
+        assert!(!progress.is_complete());
+        assert_eq!(progress.to_string(), "2/3 validators in gossip, 1/3 voting");
+
+        let progress = ConvergenceProgress::new(
+            &identities,
+            &identities
+                .iter()
+                .map(|identity| cluster_node(identity))
+                .collect::<Vec<_>>(),
+            &VoteAccounts {
+                current: identities
+                    .iter()
+                    .map(|identity| vote_account(identity))
+                    .collect(),
+                delinquent: vec![],
+            },
+        );
+        assert!(progress.is_complete());
+        assert_eq!(progress.to_string(), "3/3 validators in gossip, 3/3 voting");
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_replica_sets() {
+        let backend = InMemoryBackend::new(&[NAMESPACE]).with_new_replica_sets_ready(false);
+        let names = ["validator-0", "validator-1"].map(String::from);
+        for name in &names {
+            let replica_set = ReplicaSet {
+                metadata: ObjectMeta {
+                    name: Some(name.clone()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            backend
+                .create_replica_set(NAMESPACE, &replica_set)
+                .await
+                .unwrap();
+        }
+        let mut validator_config = ValidatorConfig::default();
+        let kubernetes = Kubernetes::new(
+            &backend,
+            NAMESPACE,
+            &mut validator_config,
+            ClientConfig::None,
+            PodRequests::new("1".to_string(), "1Gi".to_string()),
+            NodePlacement::default(),
+            None,
+            "v1-18-15".to_string(),
+        );
+        let readiness = Readiness {
+            timeout: Duration::ZERO,
+            poll_interval: Duration::from_millis(1),
+        };
+
+        let err = readiness
+            .wait_for_replica_sets(&kubernetes, &names, false)
+            .await
+            .unwrap_err();
+        assert!(err.context().contains("validator-0, validator-1"), "{err}");
+
+        // one is enough unless all are waited for
+        backend
+            .set_replica_set_ready(NAMESPACE, "validator-0")
+            .unwrap();
+        assert!(readiness
+            .wait_for_replica_sets(&kubernetes, &names, false)
+            .await
+            .is_ok());
+        let err = readiness
+            .wait_for_replica_sets(&kubernetes, &names, true)
+            .await
+            .unwrap_err();
+        assert!(err.context().ends_with(": validator-1"), "{err}");
+
+        backend
+            .set_replica_set_ready(NAMESPACE, "validator-1")
+            .unwrap();
+        assert!(readiness
+            .wait_for_replica_sets(&kubernetes, &names, true)
+            .await
+            .is_ok());
+    }
+}
@@ -1,12 +1,19 @@
 use {
     crate::{
-        cat_file, download_to_temp, extract_release_archive, new_spinner_progress_bar, CLONE,
-        SOLANA_RELEASE,
+        artifact_cache::{is_moving_release, release_key, ArtifactCache},
+        cat_file,
+        error::LabError,
+        extract_release_archive, link_or_copy_dir, new_spinner_progress_bar, CLONE, SOLANA_RELEASE,
+    },
+    git2::{
+        DiffFormat, DiffOptions, Direction, FetchOptions, Oid, RemoteCallbacks, Repository,
+        StatusOptions,
     },
-    git2::{FetchOptions, Oid, Remote, RemoteCallbacks, Repository},
     log::*,
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
     std::{
-        error::Error,
+        collections::BTreeMap,
         fs,
         path::{Path, PathBuf},
         time::Instant,
@@ -14,7 +21,8 @@ use {
     strum_macros::{EnumString, IntoStaticStr, VariantNames},
 };
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", rename_all_fields = "kebab-case")]
 pub enum DeployMethod {
     Local(String),
     ReleaseChannel(String),
@@ -23,10 +31,215 @@ pub enum DeployMethod {
         username: String,
         repo_name: String,
     },
+    /// any git remote, including a local bare mirror, and a branch, tag, `refs/...` ref, or commit hash
+    Git {
+        remote: String,
+        #[serde(rename = "ref")]
+        git_ref: String,
+    },
+}
+
+/// Where fetched refs are stored in the local clone
+const FETCHED_REF: &str = "refs/validator-lab/fetched";
+
+/// A git remote and a ref to build from, cloned into `repo_path`
+pub struct GitCheckout {
+    repo_path: PathBuf,
+    remote: String,
+    git_ref: String,
+}
+
+fn is_hex(git_ref: &str) -> bool {
+    git_ref.len() >= 4 && git_ref.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl GitCheckout {
+    /// None for deploy methods that are not built from a git remote
+    pub fn for_deploy_method(
+        deploy_method: &DeployMethod,
+        cluster_root_path: &Path,
+    ) -> Option<Self> {
+        match deploy_method {
+            DeployMethod::Commit {
+                commit,
+                username,
+                repo_name,
+            } => Some(Self {
+                repo_path: cluster_root_path.join(format!("{repo_name}_{username}")),
+                remote: format!("https://github.com/{username}/{repo_name}.git"),
+                git_ref: commit.clone(),
+            }),
+            DeployMethod::Git { remote, git_ref } => {
+                let repo_dir_name: String = remote
+                    .trim_end_matches('/')
+                    .trim_end_matches(".git")
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+                Some(Self {
+                    repo_path: cluster_root_path.join(format!("git_{repo_dir_name}")),
+                    remote: remote.clone(),
+                    git_ref: git_ref.clone(),
+                })
+            }
+            DeployMethod::Local(_) | DeployMethod::ReleaseChannel(_) => None,
+        }
+    }
+
+    pub fn repo_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    fn git_error(&self, context: &str) -> impl Fn(git2::Error) -> LabError + '_ {
+        let context = format!("{context} {} from {}", self.git_ref, self.remote);
+        move |err| LabError::build(context.clone()).with_source(err)
+    }
+
+    fn open_or_init(&self) -> Result<Repository, LabError> {
+        let git_error = self.git_error("Failed to set up a clone for");
+        let repo = if self.repo_path.exists() {
+            Repository::open(&self.repo_path).map_err(&git_error)?
+        } else {
+            fs::create_dir_all(&self.repo_path).map_err(|err| {
+                LabError::build(format!("Failed to create {}", self.repo_path.display()))
+                    .with_source(err)
+            })?;
+            Repository::init(&self.repo_path).map_err(&git_error)?
+        };
+        if repo.find_remote("origin").is_err() {
+            repo.remote("origin", &self.remote).map_err(&git_error)?;
+        } else {
+            repo.remote_set_url("origin", &self.remote)
+                .map_err(&git_error)?;
+        }
+        Ok(repo)
+    }
+
+    fn fetch(&self, repo: &Repository, refspecs: &[String]) -> Result<(), LabError> {
+        let mut remote = repo
+            .find_remote("origin")
+            .map_err(self.git_error("Failed to find remote for"))?;
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(RemoteCallbacks::new());
+        remote
+            .fetch(refspecs, Some(&mut fetch_options), None)
+            .map_err(self.git_error("Failed to fetch"))
+    }
+
+    /// The full name of the remote ref `git_ref` names, trying it as a branch, then a tag
+    fn find_remote_ref(&self, repo: &Repository) -> Result<Option<String>, LabError> {
+        let git_error = self.git_error("Failed to list the refs of");
+        let mut remote = repo.find_remote("origin").map_err(&git_error)?;
+        remote.connect(Direction::Fetch).map_err(&git_error)?;
+        let candidates = [
+            self.git_ref.clone(),
+            format!("refs/heads/{}", self.git_ref),
+            format!("refs/tags/{}", self.git_ref),
+            format!("refs/{}", self.git_ref),
+        ];
+        let remote_refs: Vec<String> = remote
+            .list()
+            .map_err(&git_error)?
+            .iter()
+            .map(|head| head.name().to_string())
+            .collect();
+        remote.disconnect().map_err(&git_error)?;
+        Ok(candidates
+            .into_iter()
+            .find(|candidate| remote_refs.contains(candidate)))
+    }
+
+    /// Fetches `git_ref` and returns the commit it resolves to
+    pub fn resolve(&self) -> Result<(Repository, Oid), LabError> {
+        let repo = self.open_or_init()?;
+        let progress_bar = new_spinner_progress_bar();
+        progress_bar.set_message(format!("{CLONE} Fetching {}...", self.git_ref));
+        let resolved = self.resolve_in(&repo);
+        progress_bar.finish_and_clear();
+        let oid = resolved?;
+        info!("Resolved {} from {} to {oid}", self.git_ref, self.remote);
+        Ok((repo, oid))
+    }
+
+    fn resolve_in(&self, repo: &Repository) -> Result<Oid, LabError> {
+        let not_found = self.git_error("Failed to resolve");
+        let peel = |spec: &str| -> Result<Oid, LabError> {
+            Ok(repo
+                .revparse_single(spec)
+                .and_then(|object| object.peel_to_commit())
+                .map_err(&not_found)?
+                .id())
+        };
+
+        // a full hash is fetched directly, unless it is already here
+        if self.git_ref.len() == 40 && is_hex(&self.git_ref) {
+            let oid = Oid::from_str(&self.git_ref).map_err(&not_found)?;
+            if repo.find_commit(oid).is_err() {
+                self.fetch(repo, std::slice::from_ref(&self.git_ref))?;
+            }
+            return peel(&self.git_ref);
+        }
+
+        if let Some(remote_ref) = self.find_remote_ref(repo)? {
+            self.fetch(repo, &[format!("+{remote_ref}:{FETCHED_REF}")])?;
+            return peel(FETCHED_REF);
+        }
+
+        // a short hash can only be found among the commits of the remote's branches and tags
+        if is_hex(&self.git_ref) {
+            self.fetch(
+                repo,
+                &[
+                    "+refs/heads/*:refs/remotes/origin/*".to_string(),
+                    "+refs/tags/*:refs/tags/*".to_string(),
+                ],
+            )?;
+            return peel(&self.git_ref);
+        }
+
+        Err(LabError::build(format!(
+            "{} is not a branch, tag, ref, or commit of {}",
+            self.git_ref, self.remote
+        )))
+    }
+
+    /// Fetches `git_ref` and checks it out, returning the commit
+    pub fn checkout(&self) -> Result<Oid, LabError> {
+        let (repo, oid) = self.resolve()?;
+        let git_error = self.git_error("Failed to check out");
+        let commit = repo.find_commit(oid).map_err(&git_error)?;
+        repo.checkout_tree(commit.as_object(), None)
+            .map_err(&git_error)?;
+        repo.set_head_detached(oid).map_err(&git_error)?;
+        info!("Checked out commit: {oid}");
+        Ok(oid)
+    }
+
+    /// Contents of the first of `paths` that exists at the commit `git_ref` resolves to
+    pub fn read_first_file(&self, paths: &[&str]) -> Result<Option<String>, LabError> {
+        let (repo, oid) = self.resolve()?;
+        let git_error = self.git_error("Failed to read the tree of");
+        let tree = repo
+            .find_commit(oid)
+            .and_then(|commit| commit.tree())
+            .map_err(&git_error)?;
+        let Some(entry) = paths
+            .iter()
+            .find_map(|path| tree.get_path(Path::new(path)).ok())
+        else {
+            return Ok(None);
+        };
+        let blob = entry
+            .to_object(&repo)
+            .and_then(|object| object.peel_to_blob())
+            .map_err(&git_error)?;
+        Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+    }
 }
 
-#[derive(PartialEq, EnumString, IntoStaticStr, VariantNames, Clone)]
+#[derive(PartialEq, EnumString, IntoStaticStr, VariantNames, Clone, Debug, Deserialize)]
 #[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum BuildType {
     /// use Agave build from the previous run
     Skip,
@@ -34,12 +247,135 @@ pub enum BuildType {
     Release,
 }
 
+pub const DEFAULT_BUILD_CACHE_DIR: &str = "build-cache";
+
+/// How a validator is built from source, beyond the build type
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BuildOptions {
+    /// cargo features, passed to cargo-install-all.sh as `--features`
+    pub features: Vec<String>,
+    /// environment of the build, e.g. RUSTFLAGS
+    pub env: BTreeMap<String, String>,
+    /// install every binary instead of only those a validator needs
+    pub full_install: bool,
+    /// passed to cargo-install-all.sh as is
+    pub extra_args: Vec<String>,
+}
+
+impl BuildOptions {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Short hash of the options, so differently built images and cached builds never collide.
+    /// None for the default build, which keeps its plain tag
+    pub fn fingerprint(&self) -> Option<String> {
+        if self.is_default() {
+            return None;
+        }
+        let options = serde_json::to_string(self).expect("build options serialize to JSON");
+        let digest = format!("{:x}", Sha256::digest(options.as_bytes()));
+        Some(digest[..8].to_string())
+    }
+
+    fn install_script_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if !self.full_install {
+            args.push("--validator-only".to_string());
+        }
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+}
+
+impl std::fmt::Display for BuildOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Build Options\n\
+             \tFeatures: {:?}\n\
+             \tEnv: {:?}\n\
+             \tFull Install: {}\n\
+             \tExtra Args: {:?}",
+            self.features, self.env, self.full_install, self.extra_args,
+        )
+    }
+}
+
+/// The `version.yml` of a build
+#[derive(Debug, Deserialize, Serialize)]
+struct BuildVersion {
+    #[serde(default)]
+    channel: String,
+    commit: String,
+    #[serde(default)]
+    build_type: Option<String>,
+    #[serde(default, skip_serializing_if = "BuildOptions::is_default")]
+    build_options: BuildOptions,
+    /// built from a tree with uncommitted changes, so `commit` does not describe it
+    #[serde(default)]
+    dirty: bool,
+}
+
+impl BuildVersion {
+    /// None if `directory` holds no readable version.yml
+    fn read(directory: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(directory.join("version.yml")).ok()?;
+        serde_yaml::from_str(&contents).ok()
+    }
+}
+
+/// Whether the working tree has changes, including untracked files, that HEAD does not
+fn is_dirty(repo: &Repository) -> Result<bool, git2::Error> {
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .include_ignored(false)
+        .exclude_submodules(true);
+    Ok(!repo.statuses(Some(&mut options))?.is_empty())
+}
+
+/// First 8 hex chars of the SHA-256 of the tree's changes from HEAD, including untracked files.
+/// None if the tree is clean
+fn dirty_fingerprint(repo: &Repository) -> Result<Option<String>, git2::Error> {
+    if !is_dirty(repo)? {
+        return Ok(None);
+    }
+    let head = repo.head()?.peel_to_tree()?;
+    let mut options = DiffOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true)
+        .ignore_submodules(true);
+    let diff = repo.diff_tree_to_workdir_with_index(Some(&head), Some(&mut options))?;
+    let mut hasher = Sha256::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        hasher.update([line.origin() as u8]);
+        hasher.update(line.content());
+        true
+    })?;
+    let digest = format!("{:x}", hasher.finalize());
+    Ok(Some(digest[..8].to_string()))
+}
+
 pub struct BuildConfig {
     deploy_method: DeployMethod,
     build_type: BuildType,
     cluster_root_path: PathBuf,
     /// solana-release directory holding all solana/agave bins
     install_directory: PathBuf,
+    artifact_cache: ArtifactCache,
+    /// builds of clean commits, in `<build_cache>/<commit>-<debug|release>[-<options fingerprint>]`
+    build_cache: PathBuf,
+    build_options: BuildOptions,
+    /// expected SHA-256 of the release tarball
+    release_sha256: Option<String>,
 }
 
 impl BuildConfig {
@@ -47,25 +383,51 @@ impl BuildConfig {
         deploy_method: DeployMethod,
         build_type: BuildType,
         cluster_root_path: &Path,
-    ) -> Self {
+        artifact_cache: ArtifactCache,
+        build_cache: PathBuf,
+        build_options: BuildOptions,
+        release_sha256: Option<String>,
+    ) -> Result<Self, LabError> {
+        let is_release = matches!(deploy_method, DeployMethod::ReleaseChannel(_));
+        if !build_options.is_default() && is_release {
+            return Err(LabError::config(
+                "Build options only apply to builds from source, not to --release-channel",
+            ));
+        }
+        if release_sha256.is_some() && !is_release {
+            return Err(LabError::config(
+                "A release sha256 only applies to --release-channel",
+            ));
+        }
         // If the solana-release directory exists and we're not skipping the build, delete it and create a new one.
         let install_directory = cluster_root_path.join(SOLANA_RELEASE);
+        let io_error = |err: std::io::Error| {
+            LabError::build(format!(
+                "Failed to set up install directory {}",
+                install_directory.display()
+            ))
+            .with_source(err)
+        };
         if build_type != BuildType::Skip && install_directory.exists() {
-            std::fs::remove_dir_all(&install_directory).unwrap();
+            std::fs::remove_dir_all(&install_directory).map_err(io_error)?;
         }
-        std::fs::create_dir_all(&install_directory).unwrap();
-        BuildConfig {
+        std::fs::create_dir_all(&install_directory).map_err(io_error)?;
+        Ok(BuildConfig {
             deploy_method,
             build_type,
             cluster_root_path: cluster_root_path.to_path_buf(),
             install_directory,
-        }
+            artifact_cache,
+            build_cache,
+            build_options,
+            release_sha256,
+        })
     }
 
     /// Sets up build environment
     /// Builds deployment based on type
     /// returns image tag.
-    pub async fn prepare(&self) -> Result<String, Box<dyn Error>> {
+    pub async fn prepare(&self) -> Result<String, LabError> {
         match &self.deploy_method {
             DeployMethod::ReleaseChannel(channel) => {
                 if self.build_type == BuildType::Skip {
@@ -73,83 +435,63 @@ impl BuildConfig {
                 }
                 self.setup_tar_deploy(channel).await?;
                 info!("Successfully setup tar file");
-                cat_file(&self.install_directory.join("version.yml"))?;
+                let version_file = self.install_directory.join("version.yml");
+                cat_file(&version_file).map_err(|err| {
+                    LabError::download(format!("Failed to read {}", version_file.display()))
+                        .with_source(err)
+                })?;
                 Ok(channel.clone())
             }
             DeployMethod::Local(_) => Ok(self.build()?),
-            DeployMethod::Commit { commit, .. } => {
-                if self.build_type == BuildType::Skip {
-                    return Ok(commit[..8].to_string());
-                }
-                Ok(self.build()?)
-            }
+            DeployMethod::Commit { .. } | DeployMethod::Git { .. } => Ok(self.build()?),
         }
     }
 
-    async fn setup_tar_deploy(&self, release_channel: &String) -> Result<(), Box<dyn Error>> {
-        let tar_filename = format!("{SOLANA_RELEASE}.tar.bz2");
-        self.download_release_from_channel(&tar_filename, release_channel)
-            .await?;
+    async fn setup_tar_deploy(&self, release_channel: &str) -> Result<(), LabError> {
+        let tarball_filename = self.download_release_from_channel(release_channel).await?;
 
         // Extract it and load the release version metadata
-        let tarball_filename = self.cluster_root_path.join(&tar_filename);
-        extract_release_archive(&tarball_filename, &self.cluster_root_path).map_err(|err| {
-            format!(
-                "Unable to extract {tar_filename} into {}: {err}",
-                self.install_directory.display()
-            )
-        })?;
+        extract_release_archive(&tarball_filename, &self.cluster_root_path)?;
         Ok(())
     }
 
-    fn build(&self) -> Result<String, Box<dyn Error>> {
+    fn build(&self) -> Result<String, LabError> {
         let start_time = Instant::now();
 
         let build_path = match &self.deploy_method {
             DeployMethod::Local(path) => PathBuf::from(path),
-            DeployMethod::Commit { .. } => self.fetch_and_checkout()?,
-            _ => return Err("Unsupported deploy method".into()),
-        };
-
-        if self.build_type != BuildType::Skip {
-            let build_variant = if self.build_type == BuildType::Debug {
-                "--debug"
-            } else {
-                ""
-            };
-
-            let install_script = build_path.join("scripts/cargo-install-all.sh");
-            match std::process::Command::new(install_script)
-                .arg("--validator-only")
-                .arg(self.install_directory.clone())
-                .arg(build_variant)
-                .status()
-            {
-                Ok(result) => {
-                    if result.success() {
-                        info!("Successfully built validator")
-                    } else {
-                        return Err("Failed to build validator".into());
-                    }
-                }
-                Err(err) => return Err(Box::new(err)),
+            DeployMethod::Commit { .. } | DeployMethod::Git { .. } => {
+                let checkout = self.git_checkout()?;
+                checkout.checkout()?;
+                checkout.repo_path().to_path_buf()
             }
-        }
+            _ => return Err(LabError::build("Unsupported deploy method")),
+        };
 
-        let solana_repo = Repository::open(build_path.as_path())?;
-        let commit = solana_repo.revparse_single("HEAD")?.id();
-        let branch = solana_repo
-            .head()?
+        let git_error = |err: git2::Error| {
+            LabError::build(format!(
+                "Failed to read git metadata from {}",
+                build_path.display()
+            ))
+            .with_source(err)
+        };
+        let solana_repo = Repository::open(build_path.as_path()).map_err(git_error)?;
+        let commit = solana_repo.revparse_single("HEAD").map_err(git_error)?.id();
+        let head = solana_repo.head().map_err(git_error)?;
+        let branch = head
             .shorthand()
-            .expect("Failed to get shortened branch name")
+            .ok_or_else(|| LabError::build("Failed to get shortened branch name"))?
             .to_string();
 
         // Check if current commit is associated with a tag
         let mut note = branch;
         let mut commit_tag = None;
-        for tag in (&solana_repo.tag_names(None)?).into_iter().flatten() {
+        for tag in (&solana_repo.tag_names(None).map_err(git_error)?)
+            .into_iter()
+            .flatten()
+        {
             // Get the target object of the tag
-            let tag_object = solana_repo.revparse_single(tag)?.id();
+            let tag_object = solana_repo.revparse_single(tag).map_err(git_error)?.id();
             // Check if the commit associated with the tag is the same as the current commit
             if tag_object == commit {
                 info!("The current commit is associated with tag: {tag}");
@@ -158,131 +500,380 @@ impl BuildConfig {
                 break;
             }
         }
+        let mut label = commit_tag.unwrap_or_else(|| commit.to_string()[..8].to_string());
+        let fingerprint = self.build_options.fingerprint();
+        if let Some(fingerprint) = &fingerprint {
+            info!("{}", self.build_options);
+            label = format!("{label}-{fingerprint}");
+        }
 
-        // Write to branch/tag and commit to version.yml
-        let content = format!("channel: devbuild {note}\ncommit: {commit}");
-        std::fs::write(
-            self.cluster_root_path
-                .join(format!("{SOLANA_RELEASE}/version.yml")),
-            content,
-        )
-        .expect("Failed to write version.yml");
+        // a dirty tree is not the commit it is on, so its builds are never cached
+        // and its images are tagged by its changes
+        let dirty_fingerprint = dirty_fingerprint(&solana_repo).map_err(git_error)?;
+        let dirty = dirty_fingerprint.is_some();
+        if let Some(dirty_fingerprint) = &dirty_fingerprint {
+            info!("{} has uncommitted changes", build_path.display());
+            label = format!("{label}-dirty-{dirty_fingerprint}");
+        }
+
+        if self.build_type == BuildType::Skip {
+            self.check_installed_build(&commit.to_string(), dirty)?;
+            return Ok(label);
+        }
+
+        let build_type: &str = (&self.build_type).into();
+        let cache_entry = self.build_cache.join(match &fingerprint {
+            Some(fingerprint) => format!("{commit}-{build_type}-{fingerprint}"),
+            None => format!("{commit}-{build_type}"),
+        });
+        if !dirty && self.restore_cached_build(&cache_entry, &commit.to_string(), build_type)? {
+            info!("Reusing cached build {}", cache_entry.display());
+            return Ok(label);
+        }
 
-        let label = commit_tag.unwrap_or_else(|| commit.to_string()[..8].to_string());
+        self.run_install_script(&build_path)?;
+
+        // Write to branch/tag, commit, build type, and build options to version.yml
+        let version = BuildVersion {
+            channel: format!("devbuild {note}"),
+            commit: commit.to_string(),
+            build_type: Some(build_type.to_string()),
+            build_options: self.build_options.clone(),
+            dirty,
+        };
+        let version_file = self.install_directory.join("version.yml");
+        serde_yaml::to_string(&version)
+            .map_err(|err| LabError::build("Failed to serialize version.yml").with_source(err))
+            .and_then(|content| {
+                fs::write(&version_file, content).map_err(|err| {
+                    LabError::build(format!("Failed to write {}", version_file.display()))
+                        .with_source(err)
+                })
+            })?;
+
+        if !dirty {
+            self.cache_build(&cache_entry)?;
+        }
 
         info!("Build took {:.3?} seconds", start_time.elapsed());
         Ok(label)
     }
 
-    fn fetch_and_checkout(&self) -> Result<PathBuf, Box<dyn Error>> {
-        let repo_path = match &self.deploy_method {
-            DeployMethod::Commit {
-                commit,
-                username: user_name,
-                repo_name,
-            } => {
-                let repo_dir_name = format!("{repo_name}_{user_name}");
-                let git_repo = format!("https://github.com/{user_name}/{repo_name}.git");
-                let repo_path = self.cluster_root_path.join(repo_dir_name);
-
-                if !repo_path.exists() {
-                    self.initialize_repo(&repo_path, &git_repo, commit)?;
-                    info!(
-                        "Successfully initialized and fetched repo: {} into {}",
-                        git_repo,
-                        repo_path.display()
-                    );
+    fn run_install_script(&self, build_path: &Path) -> Result<(), LabError> {
+        let mut command =
+            std::process::Command::new(build_path.join("scripts/cargo-install-all.sh"));
+        command
+            .args(self.build_options.install_script_args())
+            .arg(self.install_directory.clone())
+            .envs(&self.build_options.env);
+        if self.build_type == BuildType::Debug {
+            command.arg("--debug");
+        }
+        match command.status() {
+            Ok(result) => {
+                if result.success() {
+                    info!("Successfully built validator");
+                    Ok(())
+                } else {
+                    Err(LabError::build(format!(
+                        "Failed to build validator. cargo-install-all.sh exited with {result}"
+                    )))
                 }
-
-                let repo = Repository::open(repo_path.clone())?;
-                self.checkout_commit(&repo, commit)?;
-                repo_path
             }
-            DeployMethod::Local(_) | DeployMethod::ReleaseChannel(_) => {
-                return Err(format!(
-                    "Cannot call clone_and_checkout for {:?}",
-                    self.deploy_method
-                )
-                .into())
+            Err(err) => Err(LabError::build("Failed to run cargo-install-all.sh").with_source(err)),
+        }
+    }
+
+    /// `--build-type skip` reuses the previous run's build only if it was built from `commit`.
+    /// Neither a build of a dirty tree nor a dirty source is described by its commit, so both are refused
+    fn check_installed_build(&self, commit: &str, dirty: bool) -> Result<(), LabError> {
+        if dirty {
+            return Err(LabError::build(
+                "Refusing to skip the build: the source has uncommitted changes",
+            ));
+        }
+        match BuildVersion::read(&self.install_directory) {
+            Some(version) if version.dirty => Err(LabError::build(format!(
+                "Refusing to skip the build: {} was built from uncommitted changes on {}",
+                self.install_directory.display(),
+                version.commit
+            ))),
+            Some(version)
+                if version.commit == commit && version.build_options == self.build_options =>
+            {
+                Ok(())
             }
-        };
-        Ok(repo_path)
+            Some(version) if version.commit != commit => Err(LabError::build(format!(
+                "Refusing to skip the build: {} was built from {} but the source is at {commit}",
+                self.install_directory.display(),
+                version.commit
+            ))),
+            Some(version) => Err(LabError::build(format!(
+                "Refusing to skip the build: {} was built with different options. {}",
+                self.install_directory.display(),
+                version.build_options
+            ))),
+            None => Err(LabError::build(format!(
+                "Refusing to skip the build: no previous build found in {}",
+                self.install_directory.display()
+            ))),
+        }
     }
 
-    fn initialize_repo(
+    /// Copies the cached build of `commit` into the install directory.
+    /// Returns false if there is none, or if the cached build does not match its key
+    fn restore_cached_build(
         &self,
-        repo_path: &Path,
-        git_repo: &str,
+        cache_entry: &Path,
         commit: &str,
-    ) -> Result<(), Box<dyn Error>> {
-        fs::create_dir_all(repo_path)?;
-        let progress_bar = new_spinner_progress_bar();
-        progress_bar.set_message(format!("{CLONE} Fetching Commit..."));
-
-        let repo = Repository::init(repo_path)?;
-        let mut remote = repo.remote("origin", git_repo)?;
-        self.fetch_commit(&mut remote, commit)?;
-
-        progress_bar.finish_and_clear();
-        Ok(())
+        build_type: &str,
+    ) -> Result<bool, LabError> {
+        let Some(version) = BuildVersion::read(cache_entry) else {
+            return Ok(false);
+        };
+        if version.dirty
+            || version.commit != commit
+            || version.build_type.as_deref() != Some(build_type)
+            || version.build_options != self.build_options
+        {
+            warn!(
+                "Ignoring cached build {}: it was built from {} ({:?})",
+                cache_entry.display(),
+                version.commit,
+                version.build_type
+            );
+            return Ok(false);
+        }
+        link_or_copy_dir(cache_entry, &self.install_directory).map_err(|err| {
+            LabError::build(format!(
+                "Failed to restore cached build {}",
+                cache_entry.display()
+            ))
+            .with_source(err)
+        })?;
+        Ok(true)
     }
 
-    fn checkout_commit(&self, repo: &Repository, commit: &str) -> Result<(), Box<dyn Error>> {
-        match repo.find_commit(Oid::from_str(commit)?) {
-            Ok(git_commit) => {
-                repo.checkout_tree(git_commit.as_object(), None)?;
-                repo.set_head_detached(git_commit.id())?;
-                info!("Checked out commit: {commit}");
-            }
-            Err(_) => {
-                let progress_bar = new_spinner_progress_bar();
-                progress_bar.set_message(format!("{CLONE} Fetching Commit..."));
-
-                // Commit not found locally, so we need to fetch it.
-                let mut remote = repo.find_remote("origin")?;
-                self.fetch_commit(&mut remote, commit)?;
-
-                // Find and checkout commit
-                let git_commit = repo.find_commit(Oid::from_str(commit)?)?;
-                repo.checkout_tree(git_commit.as_object(), None)?;
-                repo.set_head_detached(git_commit.id())?;
-                info!("Fetched and checked out commit: {commit}");
-                progress_bar.finish_and_clear();
-            }
+    fn cache_build(&self, cache_entry: &Path) -> Result<(), LabError> {
+        let io_error = |err: std::io::Error| {
+            LabError::build(format!(
+                "Failed to cache build in {}",
+                cache_entry.display()
+            ))
+            .with_source(err)
+        };
+        if cache_entry.exists() {
+            fs::remove_dir_all(cache_entry).map_err(io_error)?;
         }
+        // version.yml goes last, so an interrupted copy is never taken for a complete build
+        link_or_copy_dir(
+            &self.install_directory.join("bin"),
+            &cache_entry.join("bin"),
+        )
+        .map_err(io_error)?;
+        fs::copy(
+            self.install_directory.join("version.yml"),
+            cache_entry.join("version.yml"),
+        )
+        .map_err(io_error)?;
+        info!("Cached build in {}", cache_entry.display());
         Ok(())
     }
 
-    fn fetch_commit(&self, remote: &mut Remote, commit: &str) -> Result<(), Box<dyn Error>> {
-        let mut fetch_options = FetchOptions::new();
-        fetch_options.remote_callbacks(RemoteCallbacks::new());
-        remote.fetch(&[commit], Some(&mut fetch_options), None)?;
-        Ok(())
+    fn git_checkout(&self) -> Result<GitCheckout, LabError> {
+        GitCheckout::for_deploy_method(&self.deploy_method, &self.cluster_root_path).ok_or_else(
+            || {
+                LabError::build(format!(
+                    "{:?} is not built from a git remote",
+                    self.deploy_method
+                ))
+            },
+        )
     }
 
+    /// Returns the cached release tarball, downloading it on the first use of a version.
+    /// Moving channels like `stable` are downloaded on every use
     async fn download_release_from_channel(
         &self,
-        tar_filename: &str,
-        release_channel: &String,
-    ) -> Result<(), Box<dyn Error>> {
-        info!("Downloading release from channel: {release_channel}");
-        let file_path = self.cluster_root_path.join(tar_filename);
-        // Remove file
-        if let Err(err) = fs::remove_file(&file_path) {
-            if err.kind() != std::io::ErrorKind::NotFound {
-                return Err(format!("{err}: {:?}", "Error while removing file:").into());
-            }
-        }
-
+        release_channel: &str,
+    ) -> Result<PathBuf, LabError> {
         let download_url = format!(
             "https://release.anza.xyz/{release_channel}/solana-release-x86_64-unknown-linux-gnu.tar.bz2"
         );
-        info!("download_url: {download_url}");
+        info!("Release {release_channel} from {download_url}");
 
-        download_to_temp(download_url.as_str(), &file_path)
-            .await
-            .map_err(|err| format!("Unable to download {download_url}. Error: {err}"))?;
+        let key = release_key(release_channel);
+        let expected_sha256 = self.release_sha256.as_deref();
+        if is_moving_release(release_channel) {
+            self.artifact_cache
+                .fetch_moving(&key, &download_url, expected_sha256)
+                .await
+        } else {
+            self.artifact_cache
+                .fetch(&key, &download_url, expected_sha256)
+                .await
+        }
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use {super::*, git2::Signature, tempfile::TempDir};
+
+    #[test]
+    fn test_resolve_git_refs_from_bare_mirror() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let mirror_path = root.join("agave.git");
+        let mirror = Repository::init_bare(&mirror_path).unwrap();
+        let signature = Signature::now("lab", "lab@example.com").unwrap();
+        let blob = mirror.blob(b"declare_id!(\"x\")").unwrap();
+        let mut sdk = mirror.treebuilder(None).unwrap();
+        sdk.insert("feature_set.rs", blob, 0o100644).unwrap();
+        let sdk = sdk.write().unwrap();
+        let mut tree = mirror.treebuilder(None).unwrap();
+        tree.insert("sdk", sdk, 0o040000).unwrap();
+        let tree = mirror.find_tree(tree.write().unwrap()).unwrap();
+        let commit = mirror
+            .commit(
+                Some("refs/heads/feature"),
+                &signature,
+                &signature,
+                "feature",
+                &tree,
+                &[],
+            )
+            .unwrap();
+        mirror
+            .tag_lightweight("v1.0.0", &mirror.find_object(commit, None).unwrap(), false)
+            .unwrap();
+
+        let deploy_method = |git_ref: &str| DeployMethod::Git {
+            remote: mirror_path.to_str().unwrap().to_string(),
+            git_ref: git_ref.to_string(),
+        };
+        let short_hash = commit.to_string()[..8].to_string();
+        for git_ref in ["feature", "refs/heads/feature", "v1.0.0", &short_hash] {
+            let checkout = GitCheckout::for_deploy_method(&deploy_method(git_ref), root).unwrap();
+            assert_eq!(checkout.resolve().unwrap().1, commit, "{git_ref}");
+        }
+
+        let checkout = GitCheckout::for_deploy_method(&deploy_method("feature"), root).unwrap();
+        assert_eq!(
+            checkout
+                .read_first_file(&["feature-set/src/lib.rs", "sdk/feature_set.rs"])
+                .unwrap()
+                .as_deref(),
+            Some("declare_id!(\"x\")")
+        );
+        let missing = GitCheckout::for_deploy_method(&deploy_method("missing"), root).unwrap();
+        assert!(missing.resolve().is_err());
+    }
+
+    #[test]
+    fn test_build_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let artifact_cache = ArtifactCache::new(root.join("artifact-cache"), true).unwrap();
+        let build_config = |build_type, build_options| {
+            BuildConfig::new(
+                DeployMethod::Local(root.to_str().unwrap().to_string()),
+                build_type,
+                root,
+                artifact_cache.clone(),
+                root.join("build-cache"),
+                build_options,
+                None,
+            )
+            .unwrap()
+        };
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+        let entry = root.join(format!("build-cache/{commit}-release"));
+
+        let config = build_config(BuildType::Release, BuildOptions::default());
+        assert!(!config
+            .restore_cached_build(&entry, commit, "release")
+            .unwrap());
+        fs::create_dir_all(config.install_directory.join("bin")).unwrap();
+        fs::write(config.install_directory.join("bin/agave-validator"), b"elf").unwrap();
+        fs::write(
+            config.install_directory.join("version.yml"),
+            format!("channel: devbuild master\ncommit: {commit}\nbuild_type: release"),
+        )
+        .unwrap();
+        config.cache_build(&entry).unwrap();
+
+        // a new build starts from an empty install directory and restores the cached one
+        let config = build_config(BuildType::Release, BuildOptions::default());
+        assert!(!config.install_directory.join("bin").exists());
+        assert!(config
+            .restore_cached_build(&entry, commit, "release")
+            .unwrap());
+        assert_eq!(
+            fs::read(config.install_directory.join("bin/agave-validator")).unwrap(),
+            b"elf"
+        );
+        // mismatched builds are never reused
+        assert!(!config
+            .restore_cached_build(&entry, commit, "debug")
+            .unwrap());
+        assert!(!config
+            .restore_cached_build(&entry, &"f".repeat(40), "release")
+            .unwrap());
+        let config = build_config(BuildType::Skip, BuildOptions::default());
+        assert!(config.check_installed_build(commit, false).is_ok());
+        assert!(config
+            .check_installed_build(&"f".repeat(40), false)
+            .is_err());
+        // a dirty source is not its commit
+        assert!(config.check_installed_build(commit, true).is_err());
+
+        // a build of a dirty tree is never reused, even at the same commit once the tree is clean
+        fs::write(
+            config.install_directory.join("version.yml"),
+            format!("channel: devbuild master\ncommit: {commit}\nbuild_type: release\ndirty: true"),
+        )
+        .unwrap();
+        assert!(config.check_installed_build(commit, false).is_err());
+        assert!(config.check_installed_build(commit, true).is_err());
+        assert!(!config
+            .restore_cached_build(&config.install_directory, commit, "release")
+            .unwrap());
+
+        // nor are builds with other options
+        let options = BuildOptions {
+            env: BTreeMap::from([("RUSTFLAGS".to_string(), "-C target-cpu=native".to_string())]),
+            ..BuildOptions::default()
+        };
+        assert!(BuildOptions::default().fingerprint().is_none());
+        assert_eq!(options.fingerprint().unwrap().len(), 8);
+        let config = build_config(BuildType::Skip, options);
+        assert!(config.check_installed_build(commit, false).is_err());
+        assert!(!config
+            .restore_cached_build(&entry, commit, "release")
+            .unwrap());
+
+        // untracked files make a tree dirty, ignored ones do not
+        let repo_path = root.join("repo");
+        let repo = Repository::init(&repo_path).unwrap();
+        fs::write(repo_path.join(".gitignore"), "target\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(".gitignore")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("lab", "lab@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+        index.write().unwrap();
+        fs::create_dir_all(repo_path.join("target")).unwrap();
+        fs::write(repo_path.join("target/out"), b"").unwrap();
+        assert!(!is_dirty(&repo).unwrap());
+        assert!(dirty_fingerprint(&repo).unwrap().is_none());
+        fs::write(repo_path.join("new.rs"), b"").unwrap();
+        assert!(is_dirty(&repo).unwrap());
+        // each set of changes tags its own image
+        let fingerprint = dirty_fingerprint(&repo).unwrap().unwrap();
+        assert_eq!(fingerprint.len(), 8);
+        fs::write(repo_path.join("new.rs"), b"fn main() {}").unwrap();
+        assert_ne!(dirty_fingerprint(&repo).unwrap().unwrap(), fingerprint);
     }
 }
@@ -0,0 +1,607 @@
+use {
+    crate::{
+        error::LabError,
+        genesis::Genesis,
+        kubernetes::{
+            Kubernetes, CLIENT_ACCOUNTS_SECRET, CLIENT_SERVICE, NO_AIRDROP_FLAG,
+            RPC_NODE_ACCOUNT_SECRET, RPC_NODE_SERVICE, VALIDATOR_ACCOUNTS_SECRET,
+            VALIDATOR_SERVICE,
+        },
+        node::{
+            CLIENT_NAME_LABEL, RPC_NODE_IDENTITY_LABEL, RPC_NODE_NAME_LABEL,
+            VALIDATOR_IDENTITY_LABEL, VALIDATOR_NAME_LABEL,
+        },
+        teardown::{parse_indexed_name, REPLICA_SET_SUFFIX},
+        NodeType,
+    },
+    k8s_openapi::api::{apps::v1::ReplicaSet, core::v1::Secret},
+    kube::api::ObjectMeta,
+    log::*,
+    solana_sdk::{signature::read_keypair_file, signer::Signer},
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+        process::Command,
+    },
+};
+
+/// Names of the per-node objects of a node type that can be scaled
+struct ScalableNames {
+    secret_prefix: &'static str,
+    service_prefix: &'static str,
+    name_label: &'static str,
+    identity_label: Option<&'static str>,
+    volume_prefix: &'static str,
+}
+
+impl ScalableNames {
+    fn of(node_type: &NodeType) -> Result<Self, LabError> {
+        match node_type {
+            NodeType::Standard => Ok(Self {
+                secret_prefix: VALIDATOR_ACCOUNTS_SECRET,
+                service_prefix: VALIDATOR_SERVICE,
+                name_label: VALIDATOR_NAME_LABEL,
+                identity_label: Some(VALIDATOR_IDENTITY_LABEL),
+                volume_prefix: "validator-accounts-volume",
+            }),
+            NodeType::RPC => Ok(Self {
+                secret_prefix: RPC_NODE_ACCOUNT_SECRET,
+                service_prefix: RPC_NODE_SERVICE,
+                name_label: RPC_NODE_NAME_LABEL,
+                identity_label: Some(RPC_NODE_IDENTITY_LABEL),
+                volume_prefix: "rpc-node-accounts-volume",
+            }),
+            NodeType::Client(_, _) => Ok(Self {
+                secret_prefix: CLIENT_ACCOUNTS_SECRET,
+                service_prefix: CLIENT_SERVICE,
+                name_label: CLIENT_NAME_LABEL,
+                identity_label: None,
+                volume_prefix: "client-accounts-volume",
+            }),
+            NodeType::Bootstrap => {
+                Err(LabError::config("The bootstrap validator cannot be scaled"))
+            }
+        }
+    }
+}
+
+/// Deactivates a validator's stake with the solana CLI before the validator is removed,
+/// so the rest of the cluster does not keep waiting on its votes
+pub struct StakeDeactivator {
+    solana_path: PathBuf,
+    rpc_url: String,
+}
+
+impl StakeDeactivator {
+    pub fn new(solana_path: PathBuf, rpc_url: String) -> Self {
+        Self {
+            solana_path,
+            rpc_url,
+        }
+    }
+
+    /// The validator identity created and delegated the stake account, so it is the stake authority
+    fn deactivate(&self, stake_account: &Path, identity: &Path) -> Result<(), LabError> {
+        let output = Command::new(&self.solana_path)
+            .arg("--url")
+            .arg(&self.rpc_url)
+            .arg("--keypair")
+            .arg(identity)
+            .arg("deactivate-stake")
+            .arg(stake_account)
+            .arg("--stake-authority")
+            .arg(identity)
+            .output()
+            .map_err(|err| {
+                LabError::rpc(format!("Failed to execute {}", self.solana_path.display()))
+                    .with_source(err)
+            })?;
+        if !output.status.success() {
+            return Err(LabError::rpc(format!(
+                "Failed to deactivate stake account {}: {}",
+                stake_account.display(),
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Copies `template` for a node at a new index: same image, command, and scheduling,
+/// with the new labels and its own accounts secret
+fn reindex_replica_set(
+    template: &ReplicaSet,
+    name: String,
+    labels: &BTreeMap<String, String>,
+    template_volume: &str,
+    volume: &str,
+    secret_name: Option<String>,
+) -> ReplicaSet {
+    let mut replica_set = template.clone();
+    replica_set.metadata = ObjectMeta {
+        name: Some(name),
+        namespace: template.metadata.namespace.clone(),
+        ..Default::default()
+    };
+    replica_set.status = None;
+
+    let Some(spec) = replica_set.spec.as_mut() else {
+        return replica_set;
+    };
+    spec.selector.match_labels = Some(labels.clone());
+    let Some(pod_template) = spec.template.as_mut() else {
+        return replica_set;
+    };
+    pod_template
+        .metadata
+        .get_or_insert_with(Default::default)
+        .labels = Some(labels.clone());
+    if let Some(pod_spec) = pod_template.spec.as_mut() {
+        for pod_volume in pod_spec.volumes.iter_mut().flatten() {
+            if pod_volume.name == template_volume {
+                pod_volume.name = volume.to_string();
+                if let Some(secret) = pod_volume.secret.as_mut() {
+                    secret.secret_name = secret_name.clone();
+                }
+            }
+        }
+        for container in pod_spec.containers.iter_mut() {
+            // only the validators of the first deployment can be in genesis. new ones need an airdrop
+            if let Some(command) = container.command.as_mut() {
+                command.retain(|arg| arg != NO_AIRDROP_FLAG);
+            }
+            for mount in container.volume_mounts.iter_mut().flatten() {
+                if mount.name == template_volume {
+                    mount.name = volume.to_string();
+                }
+            }
+        }
+    }
+    replica_set
+}
+
+/// Adds nodes to or removes nodes from a running deployment.
+/// New nodes copy the highest-indexed node of their type, so they reuse its already pushed image.
+/// New clients also copy that client's Secret, so bench-tps clients share its funded accounts
+pub struct Scaler<'a, 'k> {
+    kubernetes: &'a Kubernetes<'k>,
+    config_dir: PathBuf,
+}
+
+impl<'a, 'k> Scaler<'a, 'k> {
+    pub fn new(kubernetes: &'a Kubernetes<'k>, config_dir: &Path) -> Self {
+        Self {
+            kubernetes,
+            config_dir: config_dir.to_path_buf(),
+        }
+    }
+
+    fn tag(&self) -> &str {
+        self.kubernetes.deployment_tag()
+    }
+
+    /// The deployment's ReplicaSets of `node_type`, by index
+    async fn deployed(
+        &self,
+        node_type: &NodeType,
+    ) -> Result<BTreeMap<usize, ReplicaSet>, LabError> {
+        let prefix = node_type.to_string();
+        Ok(self
+            .kubernetes
+            .list_replica_sets()
+            .await?
+            .into_iter()
+            .filter_map(|replica_set| {
+                let name = replica_set.metadata.name.as_deref()?;
+                match parse_indexed_name(name, &prefix, REPLICA_SET_SUFFIX) {
+                    Some((tag, index)) if tag == self.tag() => Some((index, replica_set)),
+                    _ => None,
+                }
+            })
+            .collect())
+    }
+
+    fn identity_path(&self, node_type: &NodeType, index: usize) -> PathBuf {
+        self.config_dir
+            .join(format!("{node_type}-identity-{}-{index}.json", self.tag()))
+    }
+
+    /// Clients are given a copy of the template client's Secret. `scale` does not know the
+    /// client config they were deployed with, and the copy keeps the bench-tps accounts
+    async fn copy_client_secret(
+        &self,
+        index: usize,
+        template_index: usize,
+    ) -> Result<Secret, LabError> {
+        let template_name = format!("{CLIENT_ACCOUNTS_SECRET}-{}-{template_index}", self.tag());
+        let template = self
+            .kubernetes
+            .list_secrets()
+            .await?
+            .into_iter()
+            .find(|secret| secret.metadata.name.as_deref() == Some(template_name.as_str()))
+            .ok_or_else(|| {
+                LabError::config(format!(
+                    "Secret {template_name} of the client to scale from does not exist"
+                ))
+            })?;
+        Ok(Secret {
+            metadata: ObjectMeta {
+                name: Some(format!("{CLIENT_ACCOUNTS_SECRET}-{}-{index}", self.tag())),
+                ..Default::default()
+            },
+            data: template.data,
+            string_data: template.string_data,
+            type_: template.type_,
+            ..Default::default()
+        })
+    }
+
+    async fn create_secret(
+        &self,
+        node_type: &NodeType,
+        index: usize,
+        template_index: usize,
+    ) -> Result<Secret, LabError> {
+        match node_type {
+            NodeType::Standard => self
+                .kubernetes
+                .create_validator_secret(index, &self.config_dir),
+            NodeType::RPC => self.kubernetes.create_rpc_secret(index, &self.config_dir),
+            NodeType::Client(_, _) => self.copy_client_secret(index, template_index).await,
+            NodeType::Bootstrap => {
+                Err(LabError::config("The bootstrap validator cannot be scaled"))
+            }
+        }
+    }
+
+    /// The selector of the template's Service, or just its name label if the Service is gone
+    async fn service_selector(
+        &self,
+        names: &ScalableNames,
+        template_index: usize,
+        template_labels: &BTreeMap<String, String>,
+    ) -> Result<BTreeMap<String, String>, LabError> {
+        let service_name = format!("{}-{}-{template_index}", names.service_prefix, self.tag());
+        let selector = self
+            .kubernetes
+            .list_services()
+            .await?
+            .into_iter()
+            .find(|service| service.metadata.name.as_deref() == Some(service_name.as_str()))
+            .and_then(|service| service.spec?.selector);
+        Ok(selector.unwrap_or_else(|| {
+            template_labels
+                .iter()
+                .filter(|(key, _)| key.as_str() == names.name_label)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        }))
+    }
+
+    /// Deploys `count` nodes of `node_type` after the highest deployed index.
+    /// Returns the names of the new ReplicaSets
+    pub async fn scale_up(
+        &self,
+        genesis: &mut Genesis,
+        node_type: NodeType,
+        count: usize,
+    ) -> Result<Vec<String>, LabError> {
+        let names = ScalableNames::of(&node_type)?;
+        let deployed = self.deployed(&node_type).await?;
+        let Some((&template_index, template)) = deployed.iter().next_back() else {
+            return Err(LabError::config(format!(
+                "Deployment {} has no {node_type} to scale from. Deploy at least one first",
+                self.tag()
+            )));
+        };
+        let template_labels = template
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.selector.match_labels.clone())
+            .unwrap_or_default();
+        let template_selector = self
+            .service_selector(&names, template_index, &template_labels)
+            .await?;
+
+        let first_index = template_index + 1;
+        if !matches!(node_type, NodeType::Client(_, _)) {
+            genesis.generate_accounts_from(node_type, first_index, count, Some(self.tag()))?;
+            info!("Generated {count} {node_type} account(s) from index {first_index}");
+        }
+
+        let mut replica_sets = vec![];
+        for index in first_index..first_index + count {
+            let secret = self
+                .create_secret(&node_type, index, template_index)
+                .await?;
+            self.kubernetes.deploy_secret(&secret).await?;
+
+            let node_name = format!("{node_type}-{index}");
+            let mut labels = template_labels.clone();
+            labels.insert(names.name_label.to_string(), node_name.clone());
+            if let Some(identity_label) = names.identity_label {
+                let identity_path = self.identity_path(&node_type, index);
+                let identity = read_keypair_file(&identity_path).map_err(|err| {
+                    LabError::config(format!("Failed to read {}: {err}", identity_path.display()))
+                })?;
+                labels.insert(identity_label.to_string(), identity.pubkey().to_string());
+            }
+
+            let replica_set = reindex_replica_set(
+                template,
+                format!("{node_type}-{}-{index}{REPLICA_SET_SUFFIX}", self.tag()),
+                &labels,
+                &format!("{}-{template_index}", names.volume_prefix),
+                &format!("{}-{index}", names.volume_prefix),
+                secret.metadata.name.clone(),
+            );
+            self.kubernetes.deploy_replicas_set(&replica_set).await?;
+
+            let mut selector = template_selector.clone();
+            selector.insert(names.name_label.to_string(), node_name);
+            let service = self
+                .kubernetes
+                .create_service(names.service_prefix, index, &selector);
+            self.kubernetes.deploy_service(&service).await?;
+            info!("Deployed {node_type} {index}");
+
+            replica_sets.extend(replica_set.metadata.name);
+        }
+        Ok(replica_sets)
+    }
+
+    /// Deletes the `count` highest-indexed nodes of `node_type`. Validators' stake is
+    /// deactivated first if `stake_deactivator` is set
+    pub async fn scale_down(
+        &self,
+        node_type: NodeType,
+        count: usize,
+        stake_deactivator: Option<&StakeDeactivator>,
+    ) -> Result<(), LabError> {
+        let names = ScalableNames::of(&node_type)?;
+        let deployed = self.deployed(&node_type).await?;
+        if count > deployed.len() {
+            return Err(LabError::config(format!(
+                "Cannot remove {count} {node_type}(s). Deployment {} has {}",
+                self.tag(),
+                deployed.len()
+            )));
+        }
+
+        for (&index, replica_set) in deployed.iter().rev().take(count) {
+            if let (NodeType::Standard, Some(stake_deactivator)) = (node_type, stake_deactivator) {
+                let stake_account = self.config_dir.join(format!(
+                    "validator-stake-account-{}-{index}.json",
+                    self.tag()
+                ));
+                stake_deactivator
+                    .deactivate(&stake_account, &self.identity_path(&node_type, index))?;
+                info!("Deactivated stake of {node_type} {index}");
+            }
+
+            if let Some(name) = &replica_set.metadata.name {
+                self.kubernetes.delete_replica_set(name).await?;
+            }
+            self.kubernetes
+                .delete_service(&format!("{}-{}-{index}", names.service_prefix, self.tag()))
+                .await?;
+            self.kubernetes
+                .delete_secret(&format!("{}-{}-{index}", names.secret_prefix, self.tag()))
+                .await?;
+            info!("Removed {node_type} {index}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            client_config::{BenchTpsConfig, ClientConfig},
+            docker::DockerImage,
+            genesis::GenesisFlags,
+            k8s_helpers,
+            kube_backend::InMemoryBackend,
+            kubernetes::PodRequests,
+            node_placement::NodePlacement,
+            validator_config::ValidatorConfig,
+            ClientType,
+        },
+        tempfile::TempDir,
+    };
+
+    const NAMESPACE: &str = "lab";
+    const TAG: &str = "v1-18-20";
+
+    #[tokio::test]
+    async fn test_scale_validators_up_and_down() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path();
+        let mut genesis = Genesis::new(
+            config_dir.to_path_buf(),
+            GenesisFlags::default(),
+            false,
+            None,
+        )
+        .unwrap();
+        genesis
+            .generate_accounts(NodeType::Standard, 2, Some(TAG))
+            .unwrap();
+
+        let mut validator_config = ValidatorConfig::default();
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        let mut kub_controller = Kubernetes::new(
+            &backend,
+            NAMESPACE,
+            &mut validator_config,
+            ClientConfig::None,
+            PodRequests::new("1".to_string(), "1Gi".to_string()),
+            NodePlacement::default(),
+            None,
+            TAG.to_string(),
+        );
+        let image = DockerImage::new(
+            "registry".to_string(),
+            NodeType::Standard,
+            "k8s-image".to_string(),
+            TAG.to_string(),
+        );
+        for index in 0..2 {
+            let secret = kub_controller
+                .create_validator_secret(index, config_dir)
+                .unwrap();
+            kub_controller.deploy_secret(&secret).await.unwrap();
+            let labels =
+                k8s_helpers::create_selector(VALIDATOR_NAME_LABEL, &format!("validator-{index}"));
+            let replica_set = kub_controller
+                .create_validator_replica_set(&image, secret.metadata.name.clone(), &labels, index)
+                .unwrap();
+            kub_controller
+                .deploy_replicas_set(&replica_set)
+                .await
+                .unwrap();
+            let service = kub_controller.create_service(VALIDATOR_SERVICE, index, &labels);
+            kub_controller.deploy_service(&service).await.unwrap();
+        }
+
+        let scaler = Scaler::new(&kub_controller, config_dir);
+        let added = scaler
+            .scale_up(&mut genesis, NodeType::Standard, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            added,
+            vec![
+                format!("validator-{TAG}-2-replicaset"),
+                format!("validator-{TAG}-3-replicaset"),
+            ]
+        );
+        {
+            let objects = backend.objects();
+            let replica_set = &objects.replica_sets[&(
+                NAMESPACE.to_string(),
+                format!("validator-{TAG}-3-replicaset"),
+            )];
+            let labels = replica_set
+                .spec
+                .as_ref()
+                .unwrap()
+                .selector
+                .match_labels
+                .as_ref()
+                .unwrap();
+            assert_eq!(labels[VALIDATOR_NAME_LABEL], "validator-3");
+            let identity =
+                read_keypair_file(config_dir.join(format!("validator-identity-{TAG}-3.json")))
+                    .unwrap();
+            assert_eq!(
+                labels[VALIDATOR_IDENTITY_LABEL],
+                identity.pubkey().to_string()
+            );
+            let pod_spec = replica_set
+                .spec
+                .as_ref()
+                .unwrap()
+                .template
+                .as_ref()
+                .unwrap()
+                .spec
+                .as_ref()
+                .unwrap();
+            let volume = &pod_spec.volumes.as_ref().unwrap()[0];
+            assert_eq!(volume.name, "validator-accounts-volume-3");
+            assert_eq!(
+                volume.secret.as_ref().unwrap().secret_name,
+                Some(format!("{VALIDATOR_ACCOUNTS_SECRET}-{TAG}-3"))
+            );
+            assert!(objects.services.contains_key(&(
+                NAMESPACE.to_string(),
+                format!("{VALIDATOR_SERVICE}-{TAG}-3")
+            )));
+        }
+
+        // the highest indices go first
+        scaler
+            .scale_down(NodeType::Standard, 3, None)
+            .await
+            .unwrap();
+        {
+            let objects = backend.objects();
+            assert_eq!(
+                objects
+                    .replica_sets
+                    .keys()
+                    .map(|(_, name)| name.as_str())
+                    .collect::<Vec<_>>(),
+                vec![format!("validator-{TAG}-0-replicaset")]
+            );
+            assert_eq!(objects.services.len(), 1);
+            assert_eq!(objects.secrets.len(), 1);
+        }
+
+        assert!(scaler
+            .scale_down(NodeType::Standard, 2, None)
+            .await
+            .is_err());
+
+        // added bench-tps clients get the accounts of the client they copy
+        genesis.generate_faucet().unwrap();
+        genesis
+            .generate_accounts(NodeType::Bootstrap, 1, None)
+            .unwrap();
+        std::fs::write(config_dir.join("bench-tps-0.yml"), b"accounts").unwrap();
+        let mut client_validator_config = ValidatorConfig::default();
+        let mut client_controller = Kubernetes::new(
+            &backend,
+            NAMESPACE,
+            &mut client_validator_config,
+            ClientConfig::BenchTps(BenchTpsConfig::default()),
+            PodRequests::new("1".to_string(), "1Gi".to_string()),
+            NodePlacement::default(),
+            None,
+            TAG.to_string(),
+        );
+        let secret = client_controller
+            .create_client_secret(0, config_dir)
+            .unwrap();
+        client_controller.deploy_secret(&secret).await.unwrap();
+        let client = NodeType::Client(ClientType::BenchTps, 0);
+        let client_image = DockerImage::new(
+            "registry".to_string(),
+            client,
+            "k8s-image".to_string(),
+            TAG.to_string(),
+        );
+        let labels = k8s_helpers::create_selector(CLIENT_NAME_LABEL, "client-0");
+        let replica_set = client_controller
+            .create_client_replica_set(&client_image, secret.metadata.name.clone(), &labels, 0)
+            .unwrap();
+        client_controller
+            .deploy_replicas_set(&replica_set)
+            .await
+            .unwrap();
+
+        let added = scaler.scale_up(&mut genesis, client, 1).await.unwrap();
+        assert_eq!(added, vec![format!("{client}-{TAG}-1-replicaset")]);
+        {
+            let objects = backend.objects();
+            let template = &objects.secrets[&(
+                NAMESPACE.to_string(),
+                format!("{CLIENT_ACCOUNTS_SECRET}-{TAG}-0"),
+            )];
+            let copy = &objects.secrets[&(
+                NAMESPACE.to_string(),
+                format!("{CLIENT_ACCOUNTS_SECRET}-{TAG}-1"),
+            )];
+            assert!(copy
+                .data
+                .as_ref()
+                .unwrap()
+                .contains_key("client-accounts.yml"));
+            assert_eq!(copy.data, template.data);
+        }
+    }
+}
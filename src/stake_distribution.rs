@@ -0,0 +1,261 @@
+use {
+    crate::error::LabError,
+    log::*,
+    reqwest::Client,
+    serde::Deserialize,
+    solana_sdk::native_token::{lamports_to_sol, sol_to_lamports},
+    std::{fs, path::PathBuf, str::FromStr},
+};
+
+pub const MAINNET_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+const DEFAULT_POWER_LAW_EXPONENT: f64 = 1.0;
+
+/// How stake is split across the validators of a deployment.
+/// Parsed from `--stake-distribution` or `stake-distribution` in --config:
+///   uniform                 -> every validator stakes `--internal-node-stake-sol`
+///   power-law[:<exponent>]  -> validator i gets a share proportional to 1 / (i + 1)^exponent
+///   mainnet                 -> shares follow the top-N mainnet-beta vote accounts by stake
+///   list:<sol>,<sol>,...    -> explicit stake per validator index
+///   file:<path>             -> explicit stake per validator index, one SOL amount per line
+/// Generated distributions keep the total stake at `num_validators * internal_node_stake_sol`
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum StakeDistribution {
+    #[default]
+    Uniform,
+    PowerLaw(/* exponent */ f64),
+    MainnetTopN,
+    List(Vec<f64>),
+    File(PathBuf),
+}
+
+fn parse_stakes<'a>(values: impl Iterator<Item = &'a str>) -> Result<Vec<f64>, String> {
+    values
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .parse::<f64>()
+                .map_err(|err| format!("Invalid stake amount '{value}': {err}"))
+        })
+        .collect()
+}
+
+impl FromStr for StakeDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+        match (kind, arg) {
+            ("uniform", None) => Ok(Self::Uniform),
+            ("power-law", None) => Ok(Self::PowerLaw(DEFAULT_POWER_LAW_EXPONENT)),
+            ("power-law", Some(exponent)) => exponent
+                .parse()
+                .map(Self::PowerLaw)
+                .map_err(|err| format!("Invalid power-law exponent '{exponent}': {err}")),
+            ("mainnet", None) => Ok(Self::MainnetTopN),
+            ("list", Some(stakes)) => parse_stakes(stakes.split(',')).map(Self::List),
+            ("file", Some(path)) => Ok(Self::File(PathBuf::from(path))),
+            _ => Err(format!(
+                "Invalid stake distribution '{s}'. Expected one of: uniform, power-law[:<exponent>], \
+                mainnet, list:<sol>,<sol>,..., file:<path>"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for StakeDistribution {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: T,
+}
+
+#[derive(Deserialize)]
+struct VoteAccounts {
+    current: Vec<VoteAccount>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VoteAccount {
+    activated_stake: u64,
+}
+
+/// Returns the activated stake of the `n` highest staked mainnet-beta vote accounts
+async fn fetch_mainnet_top_stakes(n: usize) -> Result<Vec<f64>, LabError> {
+    info!("Fetching vote accounts from {MAINNET_RPC_URL}");
+    let rpc_error = |err: reqwest::Error| {
+        LabError::rpc(format!(
+            "Failed to get vote accounts from {MAINNET_RPC_URL}"
+        ))
+        .with_source(err)
+    };
+    let response = Client::new()
+        .post(MAINNET_RPC_URL)
+        .header("Content-Type", "application/json")
+        .body(r#"{"jsonrpc":"2.0","id":1,"method":"getVoteAccounts"}"#)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(rpc_error)?
+        .json::<RpcResponse<VoteAccounts>>()
+        .await
+        .map_err(rpc_error)?;
+
+    let mut stakes: Vec<u64> = response
+        .result
+        .current
+        .into_iter()
+        .map(|account| account.activated_stake)
+        .collect();
+    if stakes.len() < n {
+        return Err(LabError::rpc(format!(
+            "Requested the top {n} mainnet stakes but only {} vote accounts are current",
+            stakes.len()
+        )));
+    }
+    stakes.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(stakes.into_iter().take(n).map(|s| s as f64).collect())
+}
+
+/// Scales `weights` so they sum to `total`
+fn scale_to_total(weights: &[f64], total: f64) -> Vec<f64> {
+    let sum: f64 = weights.iter().sum();
+    weights.iter().map(|weight| weight / sum * total).collect()
+}
+
+impl StakeDistribution {
+    /// Returns the stake, in SOL, of each of the `num_validators` validators, by validator index
+    pub async fn stakes(
+        &self,
+        num_validators: usize,
+        stake_sol: f64,
+    ) -> Result<Vec<f64>, LabError> {
+        let total = stake_sol * num_validators as f64;
+        let stakes = match self {
+            Self::Uniform => vec![stake_sol; num_validators],
+            Self::PowerLaw(exponent) => {
+                let weights: Vec<f64> = (0..num_validators)
+                    .map(|i| 1.0 / ((i + 1) as f64).powf(*exponent))
+                    .collect();
+                scale_to_total(&weights, total)
+            }
+            Self::MainnetTopN => {
+                let weights = fetch_mainnet_top_stakes(num_validators).await?;
+                scale_to_total(&weights, total)
+            }
+            Self::List(stakes) => stakes.clone(),
+            Self::File(path) => {
+                let content = fs::read_to_string(path).map_err(|err| {
+                    LabError::config(format!("Failed to read stake file {path:?}")).with_source(err)
+                })?;
+                parse_stakes(content.lines()).map_err(LabError::config)?
+            }
+        };
+
+        if stakes.len() != num_validators {
+            return Err(LabError::config(format!(
+                "Stake distribution has {} entries but {num_validators} validators are deployed",
+                stakes.len()
+            )));
+        }
+        if let Some(stake) = stakes
+            .iter()
+            .find(|stake| !stake.is_finite() || **stake <= 0.0)
+        {
+            return Err(LabError::config(format!(
+                "Validator stake must be greater than 0. Found: {stake}"
+            )));
+        }
+
+        // round to whole lamports so the startup scripts get an exact SOL amount
+        Ok(stakes
+            .into_iter()
+            .map(|stake| lamports_to_sol(sol_to_lamports(stake)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("uniform".parse(), Ok(StakeDistribution::Uniform));
+        assert_eq!(
+            "power-law".parse(),
+            Ok(StakeDistribution::PowerLaw(DEFAULT_POWER_LAW_EXPONENT))
+        );
+        assert_eq!(
+            "power-law:1.5".parse(),
+            Ok(StakeDistribution::PowerLaw(1.5))
+        );
+        assert_eq!("mainnet".parse(), Ok(StakeDistribution::MainnetTopN));
+        assert_eq!(
+            "list:10, 20.5,,30".parse(),
+            Ok(StakeDistribution::List(vec![10.0, 20.5, 30.0]))
+        );
+        assert_eq!(
+            "file:/tmp/stakes.txt".parse(),
+            Ok(StakeDistribution::File(PathBuf::from("/tmp/stakes.txt")))
+        );
+        for invalid in [
+            "",
+            "power-law:steep",
+            "list:10,x",
+            "list",
+            "uniform:1",
+            "mainnet:10",
+            "zipf",
+        ] {
+            assert!(invalid.parse::<StakeDistribution>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_scale_to_total() {
+        assert_eq!(scale_to_total(&[1.0, 1.0], 10.0), vec![5.0, 5.0]);
+        assert_eq!(scale_to_total(&[3.0, 1.0], 100.0), vec![75.0, 25.0]);
+        let scaled = scale_to_total(&[1.0, 0.5, 1.0 / 3.0], 11.0);
+        assert!((scaled.iter().sum::<f64>() - 11.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_stakes() {
+        let uniform = StakeDistribution::Uniform.stakes(3, 10.0).await.unwrap();
+        assert_eq!(uniform, vec![10.0; 3]);
+
+        // with an exponent of 1, validator i gets a share proportional to 1 / (i + 1)
+        let power_law = StakeDistribution::PowerLaw(1.0)
+            .stakes(3, 11.0)
+            .await
+            .unwrap();
+        assert_eq!(power_law, vec![18.0, 9.0, 6.0]);
+        let steep = StakeDistribution::PowerLaw(2.0)
+            .stakes(3, 10.0)
+            .await
+            .unwrap();
+        assert!(steep[0] / steep[1] > power_law[0] / power_law[1]);
+        assert!((steep.iter().sum::<f64>() - 30.0).abs() < 1e-6);
+
+        // explicit stakes must match the validator count and be positive
+        let list = StakeDistribution::List(vec![1.0, 2.0]);
+        assert_eq!(list.stakes(2, 10.0).await.unwrap(), vec![1.0, 2.0]);
+        assert!(list.stakes(3, 10.0).await.is_err());
+        assert!(StakeDistribution::List(vec![1.0, 0.0])
+            .stakes(2, 10.0)
+            .await
+            .is_err());
+    }
+}
@@ -1,21 +1,51 @@
-use std::{
-    fs::{self, File},
-    io::{Result, Write},
-    path::Path,
+use {
+    crate::{error::LabError, ClientType, NodeType},
+    std::{collections::BTreeMap, fs, path::PathBuf},
 };
 
+/// Where pods mount the startup scripts ConfigMap
+pub const STARTUP_SCRIPTS_DIR: &str = "/home/solana/k8s-cluster-scripts";
+pub const COMMON_SCRIPT: &str = "common.sh";
+
 pub struct StartupScripts;
 
 impl StartupScripts {
-    pub fn write_script_to_file(script: &str, script_path: &Path) -> Result<()> {
-        if script_path.exists() {
-            // If script already exists, delete it.
-            // prevents versioning issues.
-            fs::remove_file(script_path)?;
+    pub fn file_name(node_type: &NodeType) -> String {
+        format!("{node_type}-startup-script.sh")
+    }
+
+    /// Every script by file name. Each of `overrides`, by file name, is read from a local file
+    /// instead of using the built-in script
+    pub fn load(
+        overrides: &BTreeMap<String, PathBuf>,
+    ) -> Result<BTreeMap<String, String>, LabError> {
+        let mut scripts: BTreeMap<String, String> = [
+            NodeType::Bootstrap,
+            NodeType::Standard,
+            NodeType::RPC,
+            NodeType::Client(ClientType::BenchTps, 0),
+        ]
+        .iter()
+        .map(|node_type| (Self::file_name(node_type), node_type.script().to_string()))
+        .chain(std::iter::once((
+            COMMON_SCRIPT.to_string(),
+            Self::common().to_string(),
+        )))
+        .collect();
+
+        for (file_name, path) in overrides {
+            if !scripts.contains_key(file_name) {
+                return Err(LabError::config(format!(
+                    "Cannot override unknown startup script {file_name}. Expected one of: {}",
+                    scripts.keys().cloned().collect::<Vec<_>>().join(", ")
+                )));
+            }
+            let script = fs::read_to_string(path).map_err(|err| {
+                LabError::config(format!("Failed to read {}", path.display())).with_source(err)
+            })?;
+            scripts.insert(file_name.clone(), script);
         }
-        let mut file = File::create(script_path)?;
-        file.write_all(script.as_bytes())?;
-        Ok(())
+        Ok(scripts)
     }
 
     pub fn bootstrap() -> &'static str {
@@ -605,10 +635,14 @@ run_delegate_stake() {
   solana --keypair $IDENTITY_FILE stake-account validator-accounts/stake.json
 }
 
-echo "get airdrop and create vote account"
-setup_validator
-echo "create stake account and delegate stake"
-run_delegate_stake 
+if ((airdrops_enabled)); then
+  echo "get airdrop and create vote account"
+  setup_validator
+  echo "create stake account and delegate stake"
+  run_delegate_stake
+else
+  echo "identity, vote, and stake accounts are in genesis. skipping airdrop and delegation"
+fi
 
 echo running validator:
 
@@ -1065,7 +1099,7 @@ bench-tps)
   clientCommand="\
     solana-bench-tps \
       $benchTpsExtraArgs \
-      --read-client-keys ./client-accounts.yml \
+      --read-client-keys ./client-accounts/client-accounts.yml \
       --url "http://$LOAD_BALANCER_RPC_ADDRESS"
       ${args[*]} \
       ${runtime_args[*]} \
@@ -1215,3 +1249,32 @@ replace_arg() {
 "#
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tempfile::TempDir};
+
+    #[test]
+    fn test_load_overrides() {
+        let defaults = StartupScripts::load(&BTreeMap::new()).unwrap();
+        assert_eq!(defaults.len(), 5);
+        assert!(defaults.contains_key(COMMON_SCRIPT));
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("startup-script.sh");
+        fs::write(&path, "#!/usr/bin/env bash\necho custom\n").unwrap();
+        let file_name = StartupScripts::file_name(&NodeType::Standard);
+        let scripts =
+            StartupScripts::load(&BTreeMap::from([(file_name.clone(), path.clone())])).unwrap();
+        assert_eq!(scripts[&file_name], "#!/usr/bin/env bash\necho custom\n");
+        assert_eq!(
+            scripts[&StartupScripts::file_name(&NodeType::Bootstrap)],
+            defaults[&StartupScripts::file_name(&NodeType::Bootstrap)]
+        );
+
+        assert!(
+            StartupScripts::load(&BTreeMap::from([("unknown.sh".to_string(), path.clone())]))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,500 @@
+use {
+    crate::{
+        deploy::check_namespace,
+        error::LabError,
+        kube_backend::KubeBackend,
+        kubernetes::{
+            BOOTSTRAP_ACCOUNTS_SECRET, BOOTSTRAP_SERVICE, CLIENT_ACCOUNTS_SECRET, CLIENT_SERVICE,
+            LOAD_BALANCER_SERVICE, METRICS_SECRET, RPC_NODE_ACCOUNT_SECRET, RPC_NODE_SERVICE,
+            STARTUP_SCRIPTS_CONFIG_MAP, VALIDATOR_ACCOUNTS_SECRET, VALIDATOR_SERVICE,
+        },
+        ClientType, NodeType,
+    },
+    kube::Resource,
+    log::*,
+};
+
+pub(crate) const REPLICA_SET_SUFFIX: &str = "-replicaset";
+
+/// Splits `<prefix>-<tag>-<index><suffix>` into its tag and index
+pub(crate) fn parse_indexed_name<'a>(
+    name: &'a str,
+    prefix: &str,
+    suffix: &str,
+) -> Option<(&'a str, usize)> {
+    let rest = name
+        .strip_prefix(prefix)?
+        .strip_prefix('-')?
+        .strip_suffix(suffix)?;
+    let (name_tag, index) = rest.rsplit_once('-')?;
+    if name_tag.is_empty() || index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((name_tag, index.parse().ok()?))
+}
+
+/// Returns true if `name` is `<prefix>-<tag>-<index><suffix>`.
+/// If `tag` is None, any deployment tag matches.
+fn matches_indexed_name(name: &str, prefix: &str, tag: Option<&str>, suffix: &str) -> bool {
+    let Some((name_tag, _)) = parse_indexed_name(name, prefix, suffix) else {
+        return false;
+    };
+    match tag {
+        Some(tag) => name_tag == tag,
+        None => true,
+    }
+}
+
+/// Returns true if `name` is `<prefix>-<tag><suffix>`.
+/// If `tag` is None, any deployment tag matches.
+fn matches_tagged_name(name: &str, prefix: &str, tag: Option<&str>, suffix: &str) -> bool {
+    let Some(name_tag) = name
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|rest| rest.strip_suffix(suffix))
+    else {
+        return false;
+    };
+    match tag {
+        Some(tag) => name_tag == tag,
+        None => !name_tag.is_empty(),
+    }
+}
+
+/// Names of `items` for which `is_match` holds
+fn matching_names<K: Resource>(items: Vec<K>, is_match: impl Fn(&str) -> bool) -> Vec<String> {
+    items
+        .iter()
+        .filter_map(|item| item.meta().name.clone())
+        .filter(|name| is_match(name))
+        .collect()
+}
+
+/// Deletes the ReplicaSets, Services, Secrets, and ConfigMaps created by a deployment.
+/// With a deployment tag, only that tag's validators, rpc nodes, and clients are removed;
+/// the bootstrap validator, load balancer and metrics secret are left running.
+/// A tag's startup scripts are kept while its bootstrap validator still runs.
+pub struct Teardown<'a> {
+    backend: &'a dyn KubeBackend,
+    namespace: String,
+    deployment_tag: Option<String>,
+}
+
+impl<'a> Teardown<'a> {
+    pub async fn new(
+        backend: &'a dyn KubeBackend,
+        namespace: &str,
+        deployment_tag: Option<String>,
+    ) -> Result<Teardown<'a>, LabError> {
+        check_namespace(backend, namespace).await?;
+        Ok(Self {
+            backend,
+            namespace: namespace.to_owned(),
+            deployment_tag,
+        })
+    }
+
+    fn tag(&self) -> Option<&str> {
+        self.deployment_tag.as_deref()
+    }
+
+    fn is_full_teardown(&self) -> bool {
+        self.deployment_tag.is_none()
+    }
+
+    fn is_lab_replica_set(&self, name: &str) -> bool {
+        let indexed = [
+            NodeType::Standard,
+            NodeType::RPC,
+            NodeType::Client(ClientType::BenchTps, 0),
+        ]
+        .iter()
+        .any(|node_type| {
+            matches_indexed_name(name, &node_type.to_string(), self.tag(), REPLICA_SET_SUFFIX)
+        });
+
+        indexed
+            || (self.is_full_teardown()
+                && matches_tagged_name(
+                    name,
+                    &NodeType::Bootstrap.to_string(),
+                    None,
+                    REPLICA_SET_SUFFIX,
+                ))
+    }
+
+    fn is_lab_service(&self, name: &str) -> bool {
+        let indexed = [VALIDATOR_SERVICE, RPC_NODE_SERVICE, CLIENT_SERVICE]
+            .iter()
+            .any(|prefix| matches_indexed_name(name, prefix, self.tag(), ""));
+
+        indexed
+            || (self.is_full_teardown()
+                && (name == BOOTSTRAP_SERVICE || name == LOAD_BALANCER_SERVICE))
+    }
+
+    fn is_lab_secret(&self, name: &str) -> bool {
+        let indexed = [
+            VALIDATOR_ACCOUNTS_SECRET,
+            RPC_NODE_ACCOUNT_SECRET,
+            CLIENT_ACCOUNTS_SECRET,
+        ]
+        .iter()
+        .any(|prefix| matches_indexed_name(name, prefix, self.tag(), ""));
+
+        indexed
+            || (self.is_full_teardown()
+                && (name == METRICS_SECRET
+                    || matches_tagged_name(name, BOOTSTRAP_ACCOUNTS_SECRET, None, "")))
+    }
+
+    fn is_lab_config_map(&self, name: &str, bootstrap_replica_sets: &[String]) -> bool {
+        if !matches_tagged_name(name, STARTUP_SCRIPTS_CONFIG_MAP, self.tag(), "") {
+            return false;
+        }
+        // the bootstrap validator still mounts its tag's scripts
+        let bootstrap = NodeType::Bootstrap.to_string();
+        !bootstrap_replica_sets.iter().any(|replica_set| {
+            matches_tagged_name(replica_set, &bootstrap, self.tag(), REPLICA_SET_SUFFIX)
+        })
+    }
+
+    pub async fn run(&self) -> Result<(), LabError> {
+        match self.tag() {
+            Some(tag) => info!("Tearing down deployment {tag} in {}", self.namespace),
+            None => info!("Tearing down all deployments in {}", self.namespace),
+        }
+
+        let namespace = self.namespace.as_str();
+        let replica_sets =
+            matching_names(self.backend.list_replica_sets(namespace).await?, |name| {
+                self.is_lab_replica_set(name)
+            });
+        for name in &replica_sets {
+            self.backend.delete_replica_set(namespace, name).await?;
+            info!("Deleted ReplicaSet: {name}");
+        }
+
+        let services = matching_names(self.backend.list_services(namespace).await?, |name| {
+            self.is_lab_service(name)
+        });
+        for name in &services {
+            self.backend.delete_service(namespace, name).await?;
+            info!("Deleted Service: {name}");
+        }
+
+        let secrets = matching_names(self.backend.list_secrets(namespace).await?, |name| {
+            self.is_lab_secret(name)
+        });
+        for name in &secrets {
+            self.backend.delete_secret(namespace, name).await?;
+            info!("Deleted Secret: {name}");
+        }
+
+        let remaining_replica_sets =
+            matching_names(self.backend.list_replica_sets(namespace).await?, |_| true);
+        let config_maps = matching_names(self.backend.list_config_maps(namespace).await?, |name| {
+            self.is_lab_config_map(name, &remaining_replica_sets)
+        });
+        for name in &config_maps {
+            self.backend.delete_config_map(namespace, name).await?;
+            info!("Deleted ConfigMap: {name}");
+        }
+
+        if replica_sets.is_empty()
+            && services.is_empty()
+            && secrets.is_empty()
+            && config_maps.is_empty()
+        {
+            warn!("Nothing to tear down in namespace: {}", self.namespace);
+        } else {
+            info!(
+                "Teardown complete. Deleted {} replica set(s), {} service(s), {} secret(s), \
+                 {} config map(s)",
+                replica_sets.len(),
+                services.len(),
+                secrets.len(),
+                config_maps.len()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::kube_backend::InMemoryBackend,
+        k8s_openapi::api::{
+            apps::v1::ReplicaSet,
+            core::v1::{ConfigMap, Secret, Service},
+        },
+        kube::api::ObjectMeta,
+    };
+
+    const NAMESPACE: &str = "lab";
+
+    fn metadata(name: &str) -> ObjectMeta {
+        ObjectMeta {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_indexed_name() {
+        assert_eq!(
+            parse_indexed_name(
+                "validator-v1-18-15-3-replicaset",
+                "validator",
+                "-replicaset"
+            ),
+            Some(("v1-18-15", 3))
+        );
+        assert_eq!(
+            parse_indexed_name("validator-service-abcd1234-12", VALIDATOR_SERVICE, ""),
+            Some(("abcd1234", 12))
+        );
+        // the bootstrap validator is not a validator
+        assert_eq!(
+            parse_indexed_name(
+                "bootstrap-validator-v1-18-15-replicaset",
+                "validator",
+                "-replicaset"
+            ),
+            None
+        );
+        for name in [
+            "validator-replicaset",
+            "validator-3-replicaset",
+            "validator-abcd1234-x-replicaset",
+            "validator-abcd1234-3",
+            "my-validator-abcd1234-3-replicaset",
+        ] {
+            assert_eq!(
+                parse_indexed_name(name, "validator", "-replicaset"),
+                None,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches_indexed_name() {
+        let name = "rpc-node-v1-18-15-0-replicaset";
+        assert!(matches_indexed_name(name, "rpc-node", None, "-replicaset"));
+        assert!(matches_indexed_name(
+            name,
+            "rpc-node",
+            Some("v1-18-15"),
+            "-replicaset"
+        ));
+        // a tag that is a prefix of another tag, or ends in its index, does not match
+        assert!(!matches_indexed_name(
+            name,
+            "rpc-node",
+            Some("v1-18"),
+            "-replicaset"
+        ));
+        assert!(!matches_indexed_name(
+            "rpc-node-v1-18-15-0-replicaset",
+            "rpc-node",
+            Some("v1-18-15-0"),
+            "-replicaset"
+        ));
+        assert!(!matches_indexed_name(
+            "rpc-node-service-v1-18-15-0",
+            "rpc-node",
+            None,
+            "-replicaset"
+        ));
+    }
+
+    #[test]
+    fn test_matches_tagged_name() {
+        let bootstrap = NodeType::Bootstrap.to_string();
+        let name = "bootstrap-validator-v1-18-15-replicaset";
+        assert!(matches_tagged_name(name, &bootstrap, None, "-replicaset"));
+        assert!(matches_tagged_name(
+            name,
+            &bootstrap,
+            Some("v1-18-15"),
+            "-replicaset"
+        ));
+        assert!(!matches_tagged_name(
+            name,
+            &bootstrap,
+            Some("v1-18"),
+            "-replicaset"
+        ));
+        assert!(matches_tagged_name(
+            "startup-scripts-abcd1234",
+            STARTUP_SCRIPTS_CONFIG_MAP,
+            Some("abcd1234"),
+            ""
+        ));
+        // foreign resources
+        assert!(!matches_tagged_name(
+            "bootstrap-validator-replicaset",
+            &bootstrap,
+            None,
+            "-replicaset"
+        ));
+        assert!(!matches_tagged_name(
+            "startup-scripts",
+            STARTUP_SCRIPTS_CONFIG_MAP,
+            None,
+            ""
+        ));
+        assert!(!matches_tagged_name(
+            "other-bootstrap-validator-v1-replicaset",
+            &bootstrap,
+            None,
+            "-replicaset"
+        ));
+    }
+
+    async fn deploy_two_tags(backend: &InMemoryBackend) {
+        for tag in ["v1-18-15", "abcd1234"] {
+            for name in [
+                format!("bootstrap-validator-{tag}-replicaset"),
+                format!("validator-{tag}-0-replicaset"),
+                format!("rpc-node-{tag}-0-replicaset"),
+                format!("client-{tag}-0-replicaset"),
+            ] {
+                backend
+                    .create_replica_set(
+                        NAMESPACE,
+                        &ReplicaSet {
+                            metadata: metadata(&name),
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+            for name in [
+                format!("{VALIDATOR_SERVICE}-{tag}-0"),
+                format!("{RPC_NODE_SERVICE}-{tag}-0"),
+            ] {
+                backend
+                    .create_service(
+                        NAMESPACE,
+                        &Service {
+                            metadata: metadata(&name),
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+            for name in [
+                format!("{BOOTSTRAP_ACCOUNTS_SECRET}-{tag}"),
+                format!("{VALIDATOR_ACCOUNTS_SECRET}-{tag}-0"),
+            ] {
+                backend
+                    .create_secret(
+                        NAMESPACE,
+                        &Secret {
+                            metadata: metadata(&name),
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                    .unwrap();
+            }
+            backend
+                .apply_config_map(
+                    NAMESPACE,
+                    &ConfigMap {
+                        metadata: metadata(&format!("{STARTUP_SCRIPTS_CONFIG_MAP}-{tag}")),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        for name in [BOOTSTRAP_SERVICE, LOAD_BALANCER_SERVICE, "dashboard"] {
+            backend
+                .create_service(
+                    NAMESPACE,
+                    &Service {
+                        metadata: metadata(name),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        backend
+            .create_secret(
+                NAMESPACE,
+                &Secret {
+                    metadata: metadata(METRICS_SECRET),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    fn names<K>(objects: &std::collections::BTreeMap<(String, String), K>) -> Vec<&str> {
+        objects.keys().map(|(_, name)| name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_teardown() {
+        let backend = InMemoryBackend::new(&[NAMESPACE]);
+        assert!(Teardown::new(&backend, "missing", None).await.is_err());
+        deploy_two_tags(&backend).await;
+
+        // a tag leaves its bootstrap validator, and the startup scripts it mounts, running
+        Teardown::new(&backend, NAMESPACE, Some("abcd1234".to_string()))
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+        {
+            let objects = backend.objects();
+            assert_eq!(
+                names(&objects.replica_sets),
+                vec![
+                    "bootstrap-validator-abcd1234-replicaset",
+                    "bootstrap-validator-v1-18-15-replicaset",
+                    "client-v1-18-15-0-replicaset",
+                    "rpc-node-v1-18-15-0-replicaset",
+                    "validator-v1-18-15-0-replicaset",
+                ]
+            );
+            assert_eq!(
+                names(&objects.secrets),
+                vec![
+                    "bootstrap-accounts-secret-abcd1234",
+                    "bootstrap-accounts-secret-v1-18-15",
+                    METRICS_SECRET,
+                    "validator-accounts-secret-v1-18-15-0",
+                ]
+            );
+            assert_eq!(
+                names(&objects.config_maps),
+                vec!["startup-scripts-abcd1234", "startup-scripts-v1-18-15"]
+            );
+        }
+
+        // everything the lab created, but not foreign resources
+        Teardown::new(&backend, NAMESPACE, None)
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+        let objects = backend.objects();
+        assert!(objects.replica_sets.is_empty());
+        assert!(objects.secrets.is_empty());
+        assert!(objects.config_maps.is_empty());
+        assert_eq!(names(&objects.services), vec!["dashboard"]);
+    }
+}
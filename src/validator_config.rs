@@ -1,16 +1,60 @@
-use solana_sdk::pubkey::Pubkey;
+use {
+    crate::genesis::{DEFAULT_INTERNAL_NODE_SOL, DEFAULT_INTERNAL_NODE_STAKE_SOL},
+    serde::Deserialize,
+    solana_ledger::blockstore_cleanup_service::DEFAULT_MAX_LEDGER_SHREDS,
+    solana_sdk::pubkey::Pubkey,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
 pub struct ValidatorConfig {
     pub internal_node_sol: f64,
     pub internal_node_stake_sol: f64,
     pub commission: u8,
+    #[serde(skip)]
     pub shred_version: Option<u16>,
     pub max_ledger_size: Option<u64>,
     pub skip_poh_verify: bool,
     pub no_snapshot_fetch: bool,
     pub require_tower: bool,
     pub enable_full_rpc: bool,
+    #[serde(skip)]
     pub known_validators: Vec<Pubkey>,
     pub restart: bool,
+    /// stake in SOL by validator index for the deployment being deployed
+    #[serde(skip)]
+    pub validator_stakes: Vec<f64>,
+    /// the deployment's validators were funded and staked in genesis, so they skip the airdrop
+    #[serde(skip)]
+    pub accounts_in_genesis: bool,
+}
+
+impl Default for ValidatorConfig {
+    fn default() -> Self {
+        Self {
+            internal_node_sol: DEFAULT_INTERNAL_NODE_SOL,
+            internal_node_stake_sol: DEFAULT_INTERNAL_NODE_STAKE_SOL,
+            commission: 100,
+            shred_version: None,
+            max_ledger_size: Some(DEFAULT_MAX_LEDGER_SHREDS),
+            skip_poh_verify: false,
+            no_snapshot_fetch: false,
+            require_tower: false,
+            enable_full_rpc: false,
+            known_validators: vec![],
+            restart: true,
+            validator_stakes: vec![],
+            accounts_in_genesis: false,
+        }
+    }
+}
+
+impl ValidatorConfig {
+    /// Stake for `validator_index`. Falls back to `internal_node_stake_sol`
+    pub fn stake_sol(&self, validator_index: usize) -> f64 {
+        self.validator_stakes
+            .get(validator_index)
+            .copied()
+            .unwrap_or(self.internal_node_stake_sol)
+    }
 }